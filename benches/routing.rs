@@ -0,0 +1,33 @@
+// Measures how long handle_action takes to route an action as the number of tables grows.
+// Routing goes through the player to table index, so the time per action should stay flat.
+use lib::auth::Session;
+use lib::gamecoordinator::GameCoordinator;
+use lib::gamestate::GameAction;
+use lib::tableconfig::TableConfig;
+use std::time::Instant;
+
+const ACTIONS: usize = 100_000;
+
+fn main() {
+    println!("{:>8} {:>14}", "tables", "ns per action");
+    for tables in &[10, 100, 1_000, 10_000] {
+        let mut coordinator = GameCoordinator::with_config(TableConfig {
+            min_seats: 1,
+            max_seats: 1,
+            ..TableConfig::default()
+        });
+        let players: Vec<Session> = (0..*tables).map(|_| coordinator.on_new_user()).collect();
+        assert_eq!(*tables, coordinator.get_current_games().len());
+
+        let start = Instant::now();
+        for i in 0..ACTIONS {
+            let player = &players[(i * 7919) % players.len()];
+            coordinator
+                .handle_action(player, GameAction::CashOut)
+                .unwrap();
+            coordinator.get_other_events();
+        }
+        let elapsed = start.elapsed();
+        println!("{:>8} {:>14}", tables, elapsed.as_nanos() / ACTIONS as u128);
+    }
+}
@@ -0,0 +1,469 @@
+use crate::basicstrategy::Play;
+use crate::card::{Card, CardSuit, Deck, Value};
+use crate::dealer::{DealerPolicy, HandValue};
+use crate::tableconfig::TableConfig;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+// What is left to draw, by card value from 2 to 11 for the ace
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Shoe {
+    counts: [u16; 10],
+}
+
+impl Shoe {
+    pub fn new(decks: usize) -> Self {
+        Self::of(Deck::new(decks).get_cards())
+    }
+
+    pub fn of<'a>(cards: impl IntoIterator<Item = &'a Card>) -> Self {
+        let mut counts = [0; 10];
+        for card in cards {
+            counts[card.get_value() as usize - 2] += 1;
+        }
+        Self { counts }
+    }
+
+    pub fn get_count(&self, value: i8) -> u16 {
+        self.counts[value as usize - 2]
+    }
+
+    pub fn total(&self) -> u16 {
+        self.counts.iter().sum()
+    }
+
+    // The shoe after a card of the value is drawn, None when there is none left
+    pub fn without(&self, value: i8) -> Option<Self> {
+        let mut shoe = *self;
+        let count = &mut shoe.counts[value as usize - 2];
+        *count = count.checked_sub(1)?;
+        Some(shoe)
+    }
+
+    // Every value that can come next with its chance
+    fn draws(&self) -> impl Iterator<Item = (i8, f64, Shoe)> + '_ {
+        let total = self.total() as f64;
+        (2..=11).filter_map(move |value| {
+            let shoe = self.without(value)?;
+            Some((value, self.get_count(value) as f64 / total, shoe))
+        })
+    }
+}
+
+// A card standing for its value, the suit and face never change a total
+fn card(value: i8) -> Card {
+    let value = match value {
+        11 => Value::Ace,
+        10 => Value::Ten,
+        _ => Value::ALL[value as usize - 2],
+    };
+    Card::new(CardSuit::Clubs, value)
+}
+
+// Where the dealer's hand ends up. totals is indexed by the final total, a blackjack is only
+// counted as blackjack and not as a 21.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DealerOutcomes {
+    pub totals: [f64; 22],
+    pub bust: f64,
+    pub blackjack: f64,
+}
+
+impl DealerOutcomes {
+    pub fn get_total(&self, total: i8) -> f64 {
+        self.totals.get(total as usize).copied().unwrap_or(0.0)
+    }
+}
+
+impl Default for DealerOutcomes {
+    fn default() -> Self {
+        Self {
+            totals: [0.0; 22],
+            bust: 0.0,
+            blackjack: 0.0,
+        }
+    }
+}
+
+// The expected value of every play for a hand per unit bet, None for a play the hand cannot
+// make. dealer is where the dealer ends up against it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HandAnalysis {
+    pub dealer: DealerOutcomes,
+    pub stand: f64,
+    pub hit: f64,
+    pub double: Option<f64>,
+    pub split: Option<f64>,
+    pub surrender: Option<f64>,
+}
+
+impl HandAnalysis {
+    // The expected value of a play, None when the hand does not allow it
+    pub fn get(&self, play: Play) -> Option<f64> {
+        match play {
+            Play::Stand => Some(self.stand),
+            Play::Hit => Some(self.hit),
+            Play::Double => self.double,
+            Play::Split => self.split,
+            Play::Surrender => self.surrender,
+        }
+    }
+
+    // The analysis with only the plays offered allows, hitting and standing are always offered
+    pub fn offered(mut self, offered: impl Fn(Play) -> bool) -> Self {
+        self.double = self.double.filter(|_| offered(Play::Double));
+        self.split = self.split.filter(|_| offered(Play::Split));
+        self.surrender = self.surrender.filter(|_| offered(Play::Surrender));
+        self
+    }
+
+    // The play with the highest expected value, the first of them on a tie
+    pub fn best(&self) -> Play {
+        let plays = [
+            Play::Stand,
+            Play::Hit,
+            Play::Double,
+            Play::Split,
+            Play::Surrender,
+        ];
+        plays
+            .iter()
+            .filter_map(|play| self.get(*play).map(|ev| (*play, ev)))
+            .fold((Play::Stand, f64::MIN), |best, next| {
+                if next.1 > best.1 {
+                    next
+                } else {
+                    best
+                }
+            })
+            .0
+    }
+}
+
+// How many results an analyzer keeps by default. A hint takes a few hundred and every card dealt
+// changes the shoe they are keyed by, so old results are rarely asked for again.
+pub const DEFAULT_CACHE_LIMIT: usize = 10_000;
+
+#[derive(Debug)]
+struct Cache {
+    limit: usize,
+    dealer: HashMap<(i8, Shoe), DealerOutcomes>,
+    // The best of standing and hitting on a hand, by its sorted values
+    play_on: HashMap<(Vec<i8>, i8, Shoe), f64>,
+    hands: HashMap<(Vec<i8>, i8, Shoe), HandAnalysis>,
+}
+
+impl Cache {
+    fn new(limit: usize) -> Self {
+        Self {
+            limit,
+            dealer: HashMap::new(),
+            play_on: HashMap::new(),
+            hands: HashMap::new(),
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.dealer.len() + self.play_on.len() + self.hands.len()
+    }
+
+    // Starts over once the limit is reached, so the cache never outgrows it
+    fn make_room(&mut self) {
+        if self.len() >= self.limit {
+            *self = Self::new(self.limit);
+        }
+    }
+}
+
+// Works out exact dealer outcomes and hand values for a shoe under a table's rules and dealer
+// policy. Results are cached up to a limit, and clones share the cache so a table can hand one to
+// every hint panel that asks.
+//
+// Bets are settled as at the table: the dealer does not check for blackjack, a natural pays 3:2
+// and a dealer blackjack beats any other 21. A split hand is played as if the other were not
+// there, by hitting and standing only, split aces get one card each and there is no resplitting.
+// A shoe that runs out is followed by a full one, as a table reshuffles.
+#[derive(Clone)]
+pub struct Analyzer {
+    rules: TableConfig,
+    policy: Arc<dyn DealerPolicy>,
+    cache: Arc<Mutex<Cache>>,
+}
+
+impl fmt::Debug for Analyzer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Analyzer")
+            .field("rules", &self.rules)
+            .field("policy", &self.policy)
+            .finish()
+    }
+}
+
+impl Analyzer {
+    // Plays the dealer by the rules' dealer_rule
+    pub fn new(rules: TableConfig) -> Self {
+        let policy = Arc::new(rules.dealer_rule);
+        Self::with_policy(rules, policy)
+    }
+
+    pub fn with_policy(rules: TableConfig, policy: Arc<dyn DealerPolicy>) -> Self {
+        Self {
+            rules,
+            policy,
+            cache: Arc::new(Mutex::new(Cache::new(DEFAULT_CACHE_LIMIT))),
+        }
+    }
+
+    // How many results are cached
+    pub fn get_cached(&self) -> usize {
+        self.cache.lock().unwrap().len()
+    }
+
+    // Applies to every clone, the cache starts over if it already holds more
+    pub fn set_cache_limit(&mut self, limit: usize) {
+        let mut cache = self.cache.lock().unwrap();
+        cache.limit = limit;
+        cache.make_room();
+    }
+
+    fn cache(&self) -> MutexGuard<'_, Cache> {
+        let mut cache = self.cache.lock().unwrap();
+        cache.make_room();
+        cache
+    }
+
+    // Where the dealer ends up from the up card when the hole card and every card after it come
+    // from shoe
+    pub fn dealer_outcomes(&self, up_card: Card, shoe: &Shoe) -> DealerOutcomes {
+        let key = (up_card.get_value(), *shoe);
+        if let Some(outcomes) = self.cache.lock().unwrap().dealer.get(&key) {
+            return *outcomes;
+        }
+        let mut outcomes = DealerOutcomes::default();
+        self.dealer_draw(&mut vec![up_card], *shoe, 1.0, &mut outcomes);
+        self.cache().dealer.insert(key, outcomes);
+        outcomes
+    }
+
+    fn dealer_draw(
+        &self,
+        hand: &mut Vec<Card>,
+        shoe: Shoe,
+        chance: f64,
+        into: &mut DealerOutcomes,
+    ) {
+        let value = HandValue::of(hand);
+        if hand.len() == 2 && value.total == 21 {
+            into.blackjack += chance;
+        } else if value.total > 21 {
+            into.bust += chance;
+        } else if hand.len() >= 2 && (value.total == 21 || !self.policy.should_hit(hand)) {
+            into.totals[value.total as usize] += chance;
+        } else {
+            for (value, draw, shoe) in self.refill(shoe).draws() {
+                hand.push(card(value));
+                self.dealer_draw(hand, shoe, chance * draw, into);
+                hand.pop();
+            }
+        }
+    }
+
+    fn refill(&self, shoe: Shoe) -> Shoe {
+        if shoe.total() == 0 {
+            Shoe::new(self.rules.decks)
+        } else {
+            shoe
+        }
+    }
+
+    // Every play for hand against the up card, with shoe holding every card not seen, the
+    // dealer's hole card among them
+    pub fn analyze(&self, hand: &[Card], up_card: Card, shoe: &Shoe) -> HandAnalysis {
+        let key = (sorted_values(hand), up_card.get_value(), *shoe);
+        if let Some(analysis) = self.cache.lock().unwrap().hands.get(&key) {
+            return *analysis;
+        }
+        let first_two = hand.len() == 2;
+        let natural = first_two && HandValue::of(hand).total == 21;
+        let stand = self.stand(hand, natural, up_card, shoe);
+        let analysis = HandAnalysis {
+            dealer: self.dealer_outcomes(up_card, shoe),
+            stand,
+            hit: self.hit(&mut hand.to_vec(), up_card, shoe),
+            double: Some(first_two)
+                .filter(|first_two| *first_two)
+                .map(|_| self.double(hand, up_card, shoe)),
+            split: Some(hand)
+                .filter(|hand| first_two && hand[0].get_value() == hand[1].get_value())
+                .map(|hand| self.split(hand[0], up_card, shoe)),
+            surrender: Some(-0.5).filter(|_| first_two),
+        };
+        self.cache().hands.insert(key, analysis);
+        analysis
+    }
+
+    fn stand(&self, hand: &[Card], natural: bool, up_card: Card, shoe: &Shoe) -> f64 {
+        let total = HandValue::of(hand).total;
+        if total > 21 {
+            return -1.0;
+        }
+        let dealer = self.dealer_outcomes(up_card, shoe);
+        if natural {
+            return 1.5 * (1.0 - dealer.blackjack);
+        }
+        let won: f64 = (0..total).map(|lower| dealer.get_total(lower)).sum();
+        let lost: f64 = (total + 1..=21)
+            .map(|higher| dealer.get_total(higher))
+            .sum();
+        dealer.bust + won - lost - dealer.blackjack
+    }
+
+    fn hit(&self, hand: &mut Vec<Card>, up_card: Card, shoe: &Shoe) -> f64 {
+        self.refill(*shoe)
+            .draws()
+            .map(|(value, chance, shoe)| {
+                hand.push(card(value));
+                let ev = self.play_on(hand, up_card, &shoe);
+                hand.pop();
+                chance * ev
+            })
+            .sum()
+    }
+
+    // The best of standing and hitting again, the only plays left after a card is taken
+    fn play_on(&self, hand: &mut Vec<Card>, up_card: Card, shoe: &Shoe) -> f64 {
+        let total = HandValue::of(hand).total;
+        if total > 21 {
+            return -1.0;
+        }
+        let stand = self.stand(hand, false, up_card, shoe);
+        if total == 21 {
+            return stand;
+        }
+        let key = (sorted_values(hand), up_card.get_value(), *shoe);
+        if let Some(ev) = self.cache.lock().unwrap().play_on.get(&key) {
+            return *ev;
+        }
+        let ev = stand.max(self.hit(hand, up_card, shoe));
+        self.cache().play_on.insert(key, ev);
+        ev
+    }
+
+    fn double(&self, hand: &[Card], up_card: Card, shoe: &Shoe) -> f64 {
+        let mut hand = hand.to_vec();
+        2.0 * self
+            .refill(*shoe)
+            .draws()
+            .map(|(value, chance, shoe)| {
+                hand.push(card(value));
+                let ev = self.stand(&hand, false, up_card, &shoe);
+                hand.pop();
+                chance * ev
+            })
+            .sum::<f64>()
+    }
+
+    fn split(&self, pair_card: Card, up_card: Card, shoe: &Shoe) -> f64 {
+        let one_hand: f64 = self
+            .refill(*shoe)
+            .draws()
+            .map(|(value, chance, shoe)| {
+                let mut hand = vec![pair_card, card(value)];
+                let ev = if pair_card.is_ace() {
+                    self.stand(&hand, false, up_card, &shoe)
+                } else {
+                    self.play_on(&mut hand, up_card, &shoe)
+                };
+                chance * ev
+            })
+            .sum();
+        2.0 * one_hand
+    }
+}
+
+fn sorted_values(hand: &[Card]) -> Vec<i8> {
+    let mut values: Vec<i8> = hand.iter().map(|card| card.get_value()).collect();
+    values.sort_unstable();
+    values
+}
+
+#[test]
+fn exact_dealer_outcomes_and_hand_values() {
+    use crate::tableconfig::DealerRule;
+
+    let cards = |values: &[i8]| -> Vec<Card> { values.iter().map(|value| card(*value)).collect() };
+    let shoe_without = |decks, seen: &[i8]| {
+        seen.iter().fold(Shoe::new(decks), |shoe, value| {
+            shoe.without(*value).unwrap()
+        })
+    };
+    let analyzer = Analyzer::new(TableConfig::default());
+
+    // Two tens and a seven behind a 10, worked out by hand
+    let tiny = Shoe::of(&cards(&[10, 10, 7]));
+    let outcomes = analyzer.dealer_outcomes(card(10), &tiny);
+    assert_eq!(2.0 / 3.0, outcomes.get_total(20));
+    assert_eq!(1.0 / 3.0, outcomes.get_total(17));
+    assert_eq!(0.0, outcomes.bust + outcomes.blackjack);
+
+    // The chances add up, a 6 busts 42% of the time and an ace turns into blackjack with the
+    // share of tens left in the shoe
+    let shoe = shoe_without(6, &[6]);
+    let six = analyzer.dealer_outcomes(card(6), &shoe);
+    let sum: f64 = six.totals.iter().sum::<f64>() + six.bust + six.blackjack;
+    assert!((sum - 1.0).abs() < 1e-9);
+    assert!((six.bust - 0.42).abs() < 0.005);
+    let shoe = shoe_without(6, &[11]);
+    let ace = analyzer.dealer_outcomes(card(11), &shoe);
+    assert_eq!(96.0 / 311.0, ace.blackjack);
+    let hits_soft_17 = Analyzer::new(TableConfig {
+        dealer_rule: DealerRule::HitSoft17,
+        ..TableConfig::default()
+    });
+    assert!(
+        hits_soft_17
+            .dealer_outcomes(card(6), &shoe_without(6, &[6]))
+            .bust
+            > six.bust
+    );
+
+    // 16 against a 10 is about as bad standing as hitting, 11 against a 6 is doubled
+    let shoe = shoe_without(6, &[10, 6, 10]);
+    let sixteen = analyzer.analyze(&cards(&[10, 6]), card(10), &shoe);
+    assert!((sixteen.stand + 0.575).abs() < 0.01 && (sixteen.hit + 0.57).abs() < 0.01);
+    assert_eq!(Some(-0.5), sixteen.surrender);
+    assert_eq!(None, sixteen.split);
+    assert_eq!(Play::Surrender, sixteen.best());
+    let offered = sixteen.offered(|play| play != Play::Surrender);
+    assert_eq!(None, offered.surrender);
+    assert_eq!(Play::Hit, offered.best());
+    let shoe = shoe_without(6, &[5, 6, 6]);
+    let eleven = analyzer.analyze(&cards(&[5, 6]), card(6), &shoe);
+    assert!((eleven.double.unwrap() - 0.67).abs() < 0.02);
+    assert_eq!(Play::Double, eleven.best());
+
+    // Aces are split, and a natural is only pushed by a dealer blackjack
+    let shoe = shoe_without(6, &[11, 11, 7]);
+    let aces = analyzer.analyze(&cards(&[11, 11]), card(7), &shoe);
+    assert_eq!(Play::Split, aces.best());
+    assert_ne!(Play::Split, aces.offered(|play| play != Play::Split).best());
+    let shoe = shoe_without(6, &[11, 10, 11]);
+    let natural = analyzer.analyze(&cards(&[11, 10]), card(11), &shoe);
+    assert_eq!(1.5 * (1.0 - natural.dealer.blackjack), natural.stand);
+
+    // Asking again is answered from the cache
+    let cached = analyzer.get_cached();
+    assert_eq!(
+        sixteen,
+        analyzer.analyze(&cards(&[6, 10]), card(10), &shoe_without(6, &[10, 6, 10]))
+    );
+    assert_eq!(cached, analyzer.clone().get_cached());
+
+    // A full cache starts over rather than growing
+    let mut limited = analyzer.clone();
+    limited.set_cache_limit(100);
+    assert_eq!(0, analyzer.get_cached());
+    analyzer.analyze(&cards(&[8, 8]), card(6), &shoe_without(6, &[8, 8, 6]));
+    assert!(analyzer.get_cached() <= 100);
+}
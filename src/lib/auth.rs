@@ -0,0 +1,321 @@
+use crate::gamestate::PlayerID;
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::collections::HashMap;
+use std::fmt;
+use std::time::Duration;
+use time::OffsetDateTime;
+
+// PBKDF2-HMAC-SHA256 rounds for new passwords, each credential keeps the rounds it was hashed with
+pub const DEFAULT_HASH_ROUNDS: u32 = 100_000;
+pub const DEFAULT_TOKEN_LIFETIME: Duration = Duration::from_secs(24 * 60 * 60);
+pub const MIN_PASSWORD_LENGTH: usize = 8;
+
+// A password is never stored, only a salted hash of it
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct Credential {
+    pub player: PlayerID,
+    salt: String,
+    hash: String,
+    rounds: u32,
+}
+
+// "<player>.<expires at>.<signature>", signed with the authenticator's secret so it can be
+// checked without keeping any state per token
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq, Hash)]
+pub struct SessionToken(String);
+
+// A verified player. Only the authenticator hands these out, so holding one proves the password
+// or token behind it checked out.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Session {
+    player: PlayerID,
+    expires_at: OffsetDateTime,
+    token: SessionToken,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum AuthError {
+    InvalidUsername,
+    UsernameTaken,
+    WeakPassword,
+    InvalidCredentials,
+    InvalidToken,
+    TokenExpired,
+}
+
+impl SessionToken {
+    pub fn new(token: impl Into<String>) -> Self {
+        Self(token.into())
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for SessionToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl Session {
+    // For identities that were verified before they were written down, such as journal entries.
+    // They carry no token and never expire.
+    pub(crate) fn trusted(player: PlayerID) -> Self {
+        Self {
+            player,
+            expires_at: OffsetDateTime::unix_epoch(),
+            token: SessionToken::new(""),
+        }
+    }
+
+    pub fn get_player(&self) -> PlayerID {
+        self.player
+    }
+
+    pub fn get_expires_at(&self) -> OffsetDateTime {
+        self.expires_at
+    }
+
+    pub fn get_token(&self) -> &SessionToken {
+        &self.token
+    }
+
+    pub fn is_expired(&self, now: OffsetDateTime) -> bool {
+        !self.token.as_str().is_empty() && now >= self.expires_at
+    }
+}
+
+// Keeps the credentials of registered players and signs the tokens every session is resumed with
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct Authenticator {
+    // Keyed by the lowercased username
+    credentials: HashMap<String, Credential>,
+    secret: String,
+    token_lifetime: Duration,
+    hash_rounds: u32,
+}
+
+impl Default for Authenticator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Authenticator {
+    pub fn new() -> Self {
+        Self {
+            credentials: HashMap::new(),
+            secret: Self::random_hex(),
+            token_lifetime: DEFAULT_TOKEN_LIFETIME,
+            hash_rounds: DEFAULT_HASH_ROUNDS,
+        }
+    }
+
+    // 244 random bits from two v4 uuids
+    fn random_hex() -> String {
+        let first = PlayerID::new_v4();
+        let second = PlayerID::new_v4();
+        hex::encode([first.as_bytes().as_ref(), second.as_bytes().as_ref()].concat())
+    }
+
+    fn hash(password: &str, salt: &str, rounds: u32) -> String {
+        let mut hash = [0u8; 32];
+        pbkdf2::pbkdf2_hmac::<Sha256>(password.as_bytes(), salt.as_bytes(), rounds, &mut hash);
+        hex::encode(hash)
+    }
+
+    // Compares every byte so the time taken does not give away how much of a hash matched
+    fn constant_time_eq(a: &str, b: &str) -> bool {
+        a.len() == b.len()
+            && a.bytes()
+                .zip(b.bytes())
+                .fold(0, |diff, (a, b)| diff | (a ^ b))
+                == 0
+    }
+
+    fn normalise_username(username: &str) -> Result<String, AuthError> {
+        let valid = (3..=32).contains(&username.len())
+            && username
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-' || c == '.');
+        if !valid {
+            return Err(AuthError::InvalidUsername);
+        }
+        Ok(username.to_ascii_lowercase())
+    }
+
+    // Salts and hashes a new password for the player, nothing is stored until add_credential
+    pub fn hash_password(&self, player: PlayerID, password: &str) -> Result<Credential, AuthError> {
+        if password.chars().count() < MIN_PASSWORD_LENGTH {
+            return Err(AuthError::WeakPassword);
+        }
+        let salt = Self::random_hex();
+        Ok(Credential {
+            player,
+            hash: Self::hash(password, &salt, self.hash_rounds),
+            salt,
+            rounds: self.hash_rounds,
+        })
+    }
+
+    // Usernames are unique ignoring case
+    pub fn add_credential(
+        &mut self,
+        username: &str,
+        credential: Credential,
+    ) -> Result<(), AuthError> {
+        let username = Self::normalise_username(username)?;
+        if self.credentials.contains_key(&username) {
+            return Err(AuthError::UsernameTaken);
+        }
+        self.credentials.insert(username, credential);
+        Ok(())
+    }
+
+    pub fn is_username_taken(&self, username: &str) -> bool {
+        Self::normalise_username(username)
+            .map(|username| self.credentials.contains_key(&username))
+            .unwrap_or(false)
+    }
+
+    pub fn login(
+        &self,
+        username: &str,
+        password: &str,
+        now: OffsetDateTime,
+    ) -> Result<Session, AuthError> {
+        let credential = Self::normalise_username(username)
+            .ok()
+            .and_then(|username| self.credentials.get(&username));
+        match credential {
+            Some(credential)
+                if Self::constant_time_eq(
+                    &Self::hash(password, &credential.salt, credential.rounds),
+                    &credential.hash,
+                ) =>
+            {
+                Ok(self.issue(credential.player, now))
+            }
+            Some(_) => Err(AuthError::InvalidCredentials),
+            None => {
+                // Unknown usernames take as long as wrong passwords
+                Self::hash(password, &self.secret, self.hash_rounds);
+                Err(AuthError::InvalidCredentials)
+            }
+        }
+    }
+
+    fn sign(&self, payload: &str) -> Hmac<Sha256> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(self.secret.as_bytes())
+            .expect("hmac takes keys of any length");
+        mac.update(payload.as_bytes());
+        mac
+    }
+
+    // Starts a session lasting token_lifetime, for guests as well as registered players. The
+    // caller vouches for the player, nothing is checked, so only the coordinators may call it.
+    pub(crate) fn issue(&self, player: PlayerID, now: OffsetDateTime) -> Session {
+        // Tokens only carry whole seconds
+        let expires_at =
+            OffsetDateTime::from_unix_timestamp((now + self.token_lifetime).unix_timestamp());
+        let payload = format!("{}.{}", player.to_simple(), expires_at.unix_timestamp());
+        let signature = hex::encode(self.sign(&payload).finalize().into_bytes());
+        Session {
+            player,
+            expires_at,
+            token: SessionToken(format!("{}.{}", payload, signature)),
+        }
+    }
+
+    pub fn verify(&self, token: &SessionToken, now: OffsetDateTime) -> Result<Session, AuthError> {
+        let parts: Vec<&str> = token.as_str().split('.').collect();
+        let (player, expires_at, signature) = match parts.as_slice() {
+            [player, expires_at, signature] => (player, expires_at, signature),
+            _ => return Err(AuthError::InvalidToken),
+        };
+        let signature = hex::decode(signature).map_err(|_| AuthError::InvalidToken)?;
+        self.sign(&format!("{}.{}", player, expires_at))
+            .verify_slice(&signature)
+            .map_err(|_| AuthError::InvalidToken)?;
+
+        let player = PlayerID::parse_str(player).map_err(|_| AuthError::InvalidToken)?;
+        let expires_at = expires_at
+            .parse()
+            .map(OffsetDateTime::from_unix_timestamp)
+            .map_err(|_| AuthError::InvalidToken)?;
+        if now >= expires_at {
+            return Err(AuthError::TokenExpired);
+        }
+        Ok(Session {
+            player,
+            expires_at,
+            token: token.clone(),
+        })
+    }
+
+    pub fn get_token_lifetime(&self) -> Duration {
+        self.token_lifetime
+    }
+
+    pub fn set_token_lifetime(&mut self, lifetime: Duration) {
+        self.token_lifetime = lifetime;
+    }
+
+    // Only affects passwords hashed from now on
+    pub fn set_hash_rounds(&mut self, rounds: u32) {
+        self.hash_rounds = rounds;
+    }
+}
+
+#[test]
+fn passwords_and_tokens() {
+    let now = OffsetDateTime::now_utc();
+    let mut auth = Authenticator::new();
+    auth.set_hash_rounds(1_000);
+    let player = PlayerID::new_v4();
+    assert_eq!(
+        Err(AuthError::WeakPassword),
+        auth.hash_password(player, "short")
+    );
+    let credential = auth.hash_password(player, "correct horse").unwrap();
+    assert!(!credential.hash.contains("correct horse"));
+    auth.add_credential("Dealer_Dan", credential.clone())
+        .unwrap();
+    assert_eq!(
+        Err(AuthError::UsernameTaken),
+        auth.add_credential("dealer_dan", credential)
+    );
+
+    assert_eq!(
+        Err(AuthError::InvalidCredentials),
+        auth.login("dealer_dan", "wrong horse", now)
+    );
+    assert_eq!(
+        Err(AuthError::InvalidCredentials),
+        auth.login("nobody", "correct horse", now)
+    );
+    let session = auth.login("DEALER_DAN", "correct horse", now).unwrap();
+    assert_eq!(player, session.get_player());
+
+    // Tokens check out until they expire, and only with the secret that signed them
+    let token = session.get_token().clone();
+    assert_eq!(Ok(session.clone()), auth.verify(&token, now));
+    assert_eq!(
+        Err(AuthError::TokenExpired),
+        auth.verify(&token, now + DEFAULT_TOKEN_LIFETIME)
+    );
+    assert_eq!(
+        Err(AuthError::InvalidToken),
+        Authenticator::new().verify(&token, now)
+    );
+    let forged = SessionToken::new(token.as_str().replacen(
+        &player.to_simple().to_string(),
+        &PlayerID::new_v4().to_simple().to_string(),
+        1,
+    ));
+    assert_eq!(Err(AuthError::InvalidToken), auth.verify(&forged, now));
+}
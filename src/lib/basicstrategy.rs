@@ -0,0 +1,348 @@
+use crate::card::Card;
+use crate::dealer::HandValue;
+use crate::gamestate::{ChipPile, GameAction};
+use crate::strategy::{offered_bet, PlayerStrategy, PlayerView};
+use crate::tableconfig::{DealerRule, TableConfig};
+use serde::{Deserialize, Serialize};
+
+// Every move basic strategy can recommend, whether or not a table offers it
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Play {
+    Hit,
+    Stand,
+    Double,
+    Split,
+    Surrender,
+}
+
+impl Play {
+    // The action that makes the play at a table, which has no surrender
+    pub fn to_action(self) -> Option<GameAction> {
+        match self {
+            Play::Hit => Some(GameAction::Hit),
+            Play::Stand => Some(GameAction::Stand),
+            Play::Double => Some(GameAction::Double),
+            Play::Split => Some(GameAction::Split),
+            Play::Surrender => None,
+        }
+    }
+}
+
+// Which first two card totals may be doubled
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq)]
+pub enum DoubleRule {
+    AnyTwo,
+    NineToEleven,
+    Never,
+}
+
+// The rules that change the recommended play
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq)]
+pub struct StrategyRules {
+    pub hit_soft_17: bool,
+    pub double: DoubleRule,
+    pub split: bool,
+    pub double_after_split: bool,
+    // Late surrender, only once the dealer has checked for blackjack
+    pub surrender: bool,
+}
+
+impl StrategyRules {
+    // Tables double on 9-11, split pairs without doubling after and have no surrender, a dealer
+    // standing on a lower total is played like one standing on soft 17
+    pub fn for_table(config: &TableConfig) -> Self {
+        Self {
+            hit_soft_17: config.dealer_rule == DealerRule::HitSoft17,
+            double: DoubleRule::NineToEleven,
+            split: true,
+            double_after_split: false,
+            surrender: false,
+        }
+    }
+}
+
+// The chart's entries, the second play is what to do when the first is not allowed
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Entry {
+    Play(Play),
+    Or(Play, Play),
+}
+
+// The total-dependent basic strategy for four to eight decks, the published charts for every
+// combination of H17/S17, double after split and late surrender come out of it. up_card is
+// the dealer's face up card.
+pub fn recommend(hand: &[Card], up_card: Card, rules: &StrategyRules) -> Play {
+    let value = HandValue::of(hand);
+    let up = up_card.get_value();
+    let first_two = hand.len() == 2;
+    let can_double = first_two
+        && match rules.double {
+            DoubleRule::AnyTwo => true,
+            DoubleRule::NineToEleven => !value.soft && (9..=11).contains(&value.total),
+            DoubleRule::Never => false,
+        };
+    let can_split = first_two && rules.split && hand[0].get_value() == hand[1].get_value();
+    let can_surrender = first_two && rules.surrender;
+
+    let entry = Some(hand)
+        .filter(|_| can_split)
+        .and_then(|hand| split_entry(hand, up, rules))
+        .or_else(|| surrender_entry(value, up, rules).filter(|_| can_surrender))
+        .unwrap_or_else(|| total_entry(value, up, rules));
+    let allowed = |play| match play {
+        Play::Double => can_double,
+        Play::Split => can_split,
+        Play::Surrender => can_surrender,
+        Play::Hit | Play::Stand => true,
+    };
+    match entry {
+        Entry::Play(play) => play,
+        Entry::Or(play, _) if allowed(play) => play,
+        Entry::Or(_, otherwise) => otherwise,
+    }
+}
+
+// Pairs worth splitting, anything else is played by its total
+fn split_entry(hand: &[Card], up: i8, rules: &StrategyRules) -> Option<Entry> {
+    let das = rules.double_after_split;
+    let split = match hand[0].get_value() {
+        11 => true,
+        9 => (2..=9).contains(&up) && up != 7,
+        8 if rules.hit_soft_17 && up == 11 => {
+            return Some(Entry::Or(Play::Surrender, Play::Split));
+        }
+        8 => true,
+        7 => up <= 7,
+        6 => (3..=6).contains(&up) || das && up == 2,
+        4 => das && (5..=6).contains(&up),
+        2 | 3 => (4..=7).contains(&up) || das && up <= 3,
+        _ => false,
+    };
+    if split {
+        Some(Entry::Play(Play::Split))
+    } else {
+        None
+    }
+}
+
+fn surrender_entry(value: HandValue, up: i8, rules: &StrategyRules) -> Option<Entry> {
+    if value.soft {
+        return None;
+    }
+    match value.total {
+        16 if up >= 9 => Some(Entry::Or(Play::Surrender, Play::Hit)),
+        15 if up == 10 || rules.hit_soft_17 && up == 11 => {
+            Some(Entry::Or(Play::Surrender, Play::Hit))
+        }
+        17 if rules.hit_soft_17 && up == 11 => Some(Entry::Or(Play::Surrender, Play::Stand)),
+        _ => None,
+    }
+}
+
+fn total_entry(value: HandValue, up: i8, rules: &StrategyRules) -> Entry {
+    use Play::{Double, Hit, Stand};
+    let play = |play| Entry::Play(play);
+    let total = value.total;
+    if value.soft {
+        return match total {
+            19 if rules.hit_soft_17 && up == 6 => Entry::Or(Double, Stand),
+            19..=21 => play(Stand),
+            18 if up == 2 && !rules.hit_soft_17 => play(Stand),
+            18 if up <= 6 => Entry::Or(Double, Stand),
+            18 if up <= 8 => play(Stand),
+            17 if (3..=6).contains(&up) => Entry::Or(Double, Hit),
+            15 | 16 if (4..=6).contains(&up) => Entry::Or(Double, Hit),
+            13 | 14 if (5..=6).contains(&up) => Entry::Or(Double, Hit),
+            _ => play(Hit),
+        };
+    }
+    match total {
+        17..=i8::MAX => play(Stand),
+        13..=16 if up <= 6 => play(Stand),
+        12 if (4..=6).contains(&up) => play(Stand),
+        11 if up == 11 && !rules.hit_soft_17 => play(Hit),
+        11 => Entry::Or(Double, Hit),
+        10 if up <= 9 => Entry::Or(Double, Hit),
+        9 if (3..=6).contains(&up) => Entry::Or(Double, Hit),
+        _ => play(Hit),
+    }
+}
+
+// Bets the minimum and plays basic strategy for the table's rules
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BasicStrategy;
+
+impl PlayerStrategy for BasicStrategy {
+    fn decide(&self, view: &PlayerView, _bankroll: ChipPile, legal: &[GameAction]) -> GameAction {
+        if let Some(bet) = offered_bet(legal) {
+            return bet;
+        }
+        let up_card = match view.dealer_up_card {
+            Some(up_card) => up_card,
+            None => return GameAction::Stand,
+        };
+        let mut rules = StrategyRules::for_table(&view.rules);
+        if !legal.contains(&GameAction::Double) {
+            rules.double = DoubleRule::Never;
+        }
+        rules.split = legal.contains(&GameAction::Split);
+        recommend(&view.hand, up_card, &rules)
+            .to_action()
+            .unwrap_or(GameAction::Stand)
+    }
+}
+
+// The published four to eight deck charts, one column per dealer up card from 2 to ace.
+// D doubles or else hits, Ds doubles or else stands, R surrenders or else hits, Rs surrenders
+// or else stands, Rp surrenders or else splits.
+#[cfg(test)]
+const HARD_S17: [(i8, &str); 10] = [
+    (17, "S  S  S  S  S  S  S  S  S  S"),
+    (16, "S  S  S  S  S  H  H  R  R  R"),
+    (15, "S  S  S  S  S  H  H  H  R  H"),
+    (14, "S  S  S  S  S  H  H  H  H  H"),
+    (13, "S  S  S  S  S  H  H  H  H  H"),
+    (12, "H  H  S  S  S  H  H  H  H  H"),
+    (11, "D  D  D  D  D  D  D  D  D  H"),
+    (10, "D  D  D  D  D  D  D  D  H  H"),
+    (9, "H  D  D  D  D  H  H  H  H  H"),
+    (8, "H  H  H  H  H  H  H  H  H  H"),
+];
+
+#[cfg(test)]
+const SOFT_S17: [(i8, &str); 8] = [
+    (9, "S  S  S  S  S  S  S  S  S  S"),
+    (8, "S  S  S  S  S  S  S  S  S  S"),
+    (7, "S  Ds Ds Ds Ds S  S  H  H  H"),
+    (6, "H  D  D  D  D  H  H  H  H  H"),
+    (5, "H  H  D  D  D  H  H  H  H  H"),
+    (4, "H  H  D  D  D  H  H  H  H  H"),
+    (3, "H  H  H  D  D  H  H  H  H  H"),
+    (2, "H  H  H  D  D  H  H  H  H  H"),
+];
+
+#[cfg(test)]
+const PAIRS_DAS: [(i8, &str); 10] = [
+    (11, "P  P  P  P  P  P  P  P  P  P"),
+    (10, "S  S  S  S  S  S  S  S  S  S"),
+    (9, "P  P  P  P  P  S  P  P  S  S"),
+    (8, "P  P  P  P  P  P  P  P  P  P"),
+    (7, "P  P  P  P  P  P  H  H  H  H"),
+    (6, "P  P  P  P  P  H  H  H  H  H"),
+    (5, "D  D  D  D  D  D  D  D  H  H"),
+    (4, "H  H  H  P  P  H  H  H  H  H"),
+    (3, "P  P  P  P  P  P  H  H  H  H"),
+    (2, "P  P  P  P  P  P  H  H  H  H"),
+];
+
+#[cfg(test)]
+fn card(value: i8) -> Card {
+    use crate::card::{CardSuit, Value};
+    let value = match value {
+        11 | 1 => Value::Ace,
+        10 => Value::King,
+        _ => Value::ALL[value as usize - 2],
+    };
+    Card::new(CardSuit::Clubs, value)
+}
+
+// Checks every cell of a chart, once with everything on offer and once with nothing, where
+// each entry falls back to its second play. A split has no fallback of its own, the hand is
+// then played by its total.
+#[cfg(test)]
+fn check_chart(rules: StrategyRules, chart: &[(i8, &str)], hand: impl Fn(i8) -> Vec<Card>) {
+    let bare = StrategyRules {
+        double: DoubleRule::Never,
+        split: false,
+        surrender: false,
+        ..rules
+    };
+    for (row, line) in chart {
+        for (entry, up) in line.split_whitespace().zip(2..) {
+            let (full, fallback) = match entry {
+                "H" => (Play::Hit, Play::Hit),
+                "S" => (Play::Stand, Play::Stand),
+                "D" => (Play::Double, Play::Hit),
+                "Ds" => (Play::Double, Play::Stand),
+                "P" => (Play::Split, Play::Split),
+                "R" => (Play::Surrender, Play::Hit),
+                "Rs" => (Play::Surrender, Play::Stand),
+                "Rp" => (Play::Surrender, Play::Split),
+                _ => panic!("unknown chart entry {}", entry),
+            };
+            let cards = hand(*row);
+            assert_eq!(
+                full,
+                recommend(&cards, card(up), &rules),
+                "{:?} against {}",
+                cards,
+                up
+            );
+            let without = StrategyRules {
+                surrender: false,
+                ..rules
+            };
+            if entry == "Rp" {
+                assert_eq!(fallback, recommend(&cards, card(up), &without));
+            } else if entry != "P" {
+                assert_eq!(
+                    fallback,
+                    recommend(&cards, card(up), &bare),
+                    "{:?} against {} with nothing on offer",
+                    cards,
+                    up
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn matches_published_charts() {
+    let s17 = StrategyRules {
+        hit_soft_17: false,
+        double: DoubleRule::AnyTwo,
+        split: true,
+        double_after_split: true,
+        surrender: true,
+    };
+    let hard = |total: i8| match total {
+        12..=17 => vec![card(10), card(total - 10)],
+        _ => vec![card(total - 3), card(3)],
+    };
+    let soft = |other: i8| vec![card(11), card(other)];
+    let pair = |value: i8| vec![card(value), card(value)];
+    check_chart(s17, &HARD_S17, hard);
+    check_chart(s17, &SOFT_S17, soft);
+    check_chart(s17, &PAIRS_DAS, pair);
+
+    // Hitting soft 17 changes a handful of cells
+    let h17 = StrategyRules {
+        hit_soft_17: true,
+        ..s17
+    };
+    let mut hard_h17 = HARD_S17;
+    hard_h17[0].1 = "S  S  S  S  S  S  S  S  S  Rs";
+    hard_h17[2].1 = "S  S  S  S  S  H  H  H  R  R";
+    hard_h17[6].1 = "D  D  D  D  D  D  D  D  D  D";
+    let mut soft_h17 = SOFT_S17;
+    soft_h17[1].1 = "S  S  S  S  Ds S  S  S  S  S";
+    soft_h17[2].1 = "Ds Ds Ds Ds Ds S  S  H  H  H";
+    let mut pairs_h17 = PAIRS_DAS;
+    pairs_h17[3].1 = "P  P  P  P  P  P  P  P  P  Rp";
+    check_chart(h17, &hard_h17, hard);
+    check_chart(h17, &soft_h17, soft);
+    check_chart(h17, &pairs_h17, pair);
+
+    // Without double after split the small pairs split less often
+    let no_das = StrategyRules {
+        double_after_split: false,
+        ..s17
+    };
+    let mut pairs_no_das = PAIRS_DAS;
+    pairs_no_das[5].1 = "H  P  P  P  P  H  H  H  H  H";
+    pairs_no_das[7].1 = "H  H  H  H  H  H  H  H  H  H";
+    pairs_no_das[8].1 = "H  H  P  P  P  P  H  H  H  H";
+    pairs_no_das[9].1 = "H  H  P  P  P  P  H  H  H  H";
+    check_chart(no_das, &pairs_no_das, pair);
+}
@@ -7,9 +7,20 @@ pub struct Card {
     value: Value,
 }
 
+impl fmt::Display for Card {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}{}",
+            self.card_suit.to_symbol(),
+            self.value.to_symbol()
+        )
+    }
+}
+
 impl fmt::Debug for Card {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        f.write_str(&*format!("{}{:?}", self.card_suit.to_symbol(), self.value))
+        f.write_str(&format!("{}{:?}", self.card_suit.to_symbol(), self.value))
     }
 }
 
@@ -19,6 +30,12 @@ fn debug_output_for_card() {
     assert_eq!(format!("{:?}", card), "♠Two".to_string());
 }
 
+#[test]
+fn display_output_for_card() {
+    let card = Card::new(CardSuit::Clubs, Value::King);
+    assert_eq!(format!("{}", card), "♣K".to_string());
+}
+
 #[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
 pub enum CardSuit {
     Spades,
@@ -49,7 +66,59 @@ pub struct Deck {
     cards: Vec<Card>,
 }
 
+impl Deck {
+    // Stacks the given number of ordered 52 card decks on top of each other
+    pub fn new(decks: usize) -> Self {
+        let mut cards = Vec::with_capacity(decks * 52);
+        for _ in 0..decks {
+            for suit in CardSuit::ALL.iter() {
+                for value in Value::ALL.iter() {
+                    cards.push(Card::new(*suit, *value));
+                }
+            }
+        }
+        Self { cards }
+    }
+
+    // Fisher-Yates driven by splitmix64, so the same seed always gives the same order
+    pub fn shuffle(&mut self, seed: u64) {
+        let mut state = seed;
+        for i in (1..self.cards.len()).rev() {
+            state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+            let mut z = state;
+            z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+            z ^= z >> 31;
+            self.cards.swap(i, (z % (i as u64 + 1)) as usize);
+        }
+    }
+
+    pub fn get_cards(&self) -> &Vec<Card> {
+        &self.cards
+    }
+
+    pub fn into_cards(self) -> Vec<Card> {
+        self.cards
+    }
+}
+
 impl Value {
+    pub const ALL: [Value; 13] = [
+        Value::Two,
+        Value::Three,
+        Value::Four,
+        Value::Five,
+        Value::Six,
+        Value::Seven,
+        Value::Eight,
+        Value::Nine,
+        Value::Ten,
+        Value::Jack,
+        Value::Queen,
+        Value::King,
+        Value::Ace,
+    ];
+
     pub fn value(&self) -> i8 {
         match &self {
             Value::Two => 2,
@@ -67,9 +136,34 @@ impl Value {
             Value::Ace => 11,
         }
     }
+
+    pub fn to_symbol(&self) -> &'static str {
+        match self {
+            Value::Two => "2",
+            Value::Three => "3",
+            Value::Four => "4",
+            Value::Five => "5",
+            Value::Six => "6",
+            Value::Seven => "7",
+            Value::Eight => "8",
+            Value::Nine => "9",
+            Value::Ten => "10",
+            Value::Jack => "J",
+            Value::Queen => "Q",
+            Value::King => "K",
+            Value::Ace => "A",
+        }
+    }
 }
 
 impl CardSuit {
+    pub const ALL: [CardSuit; 4] = [
+        CardSuit::Spades,
+        CardSuit::Hearts,
+        CardSuit::Diamonds,
+        CardSuit::Clubs,
+    ];
+
     pub fn to_symbol(&self) -> String {
         match self {
             CardSuit::Spades => "♠".to_string(),
@@ -82,17 +176,14 @@ impl CardSuit {
 
 impl Card {
     pub fn is_face(&self) -> bool {
-        match self.value {
-            Value::Jack | Value::Queen | Value::King | Value::Ace => true,
-            _ => false,
-        }
+        matches!(
+            self.value,
+            Value::Jack | Value::Queen | Value::King | Value::Ace
+        )
     }
 
     pub fn is_ace(&self) -> bool {
-        match self.value {
-            Value::Ace => true,
-            _ => false,
-        }
+        matches!(self.value, Value::Ace)
     }
 
     pub fn new(suit: CardSuit, value: Value) -> Self {
@@ -106,3 +197,17 @@ impl Card {
         self.value.value()
     }
 }
+
+#[test]
+fn shuffled_shoe_is_reproducible() {
+    let mut shoe = Deck::new(6);
+    shoe.shuffle(42);
+    let mut same = Deck::new(6);
+    same.shuffle(42);
+    let mut other = Deck::new(6);
+    other.shuffle(43);
+    assert_eq!(312, shoe.get_cards().len());
+    assert_eq!(shoe.get_cards(), same.get_cards());
+    assert_ne!(shoe.get_cards(), other.get_cards());
+    assert_ne!(shoe.get_cards(), Deck::new(6).get_cards());
+}
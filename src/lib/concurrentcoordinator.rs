@@ -0,0 +1,392 @@
+use crate::auth::{Authenticator, Session, SessionToken};
+use crate::clock::{Clock, SystemClock};
+use crate::eventqueue::{EventQueue, Replay, SequencedEvent, DEFAULT_QUEUE_LIMIT};
+use crate::gamecoordinator::{CoordinatorError, GameCoordinator, TableListing};
+use crate::gamestate::{ChipPile, GameAction, GameID, GameState, PlayerID, TableEvent};
+use crate::ledger::{Account, EntryKind, Ledger};
+use crate::tableconfig::TableConfig;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex, RwLock};
+use std::thread::{self, JoinHandle};
+
+type Reply<T> = Sender<Result<T, CoordinatorError>>;
+
+// Everything a table actor can be asked to do, answered on the enclosed channel
+enum TableCommand {
+    Action(PlayerID, GameAction, Reply<Vec<TableEvent>>),
+    Join(PlayerID, Reply<()>),
+    Leave(PlayerID, Reply<()>),
+    // Leaves only between rounds, for a player moving to another table
+    MoveAway(PlayerID, Reply<()>),
+    Listing(Sender<TableListing>),
+    Inspect(Box<dyn FnOnce(&mut GameState) + Send>),
+}
+
+struct TableHandle {
+    commands: Sender<TableCommand>,
+    thread: JoinHandle<GameState>,
+}
+
+// Each table runs on its own thread and owns its GameState, so players at different tables
+// never wait on each other. Only the ledger is shared, and it is locked just long enough to
+// post the entries for a single action.
+#[derive(Clone)]
+pub struct ConcurrentCoordinator {
+    ledger: Arc<Mutex<Ledger>>,
+    tables: Arc<RwLock<HashMap<GameID, TableHandle>>>,
+    player_tables: Arc<RwLock<HashMap<PlayerID, GameID>>>,
+    // Held for the whole of a join or leave, so one player's moves never interleave
+    player_moves: Arc<Mutex<HashMap<PlayerID, Arc<Mutex<()>>>>>,
+    events_to_send: Arc<Mutex<HashMap<PlayerID, EventQueue>>>,
+    next_game_id: Arc<AtomicU64>,
+    auth: Arc<Authenticator>,
+    clock: Arc<dyn Clock>,
+}
+
+impl Default for ConcurrentCoordinator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Everything a table's thread shares with the coordinator
+struct TableShared {
+    ledger: Arc<Mutex<Ledger>>,
+    events_to_send: Arc<Mutex<HashMap<PlayerID, EventQueue>>>,
+    clock: Arc<dyn Clock>,
+}
+
+fn run_table(
+    mut game: GameState,
+    shared: TableShared,
+    commands: Receiver<TableCommand>,
+) -> GameState {
+    let TableShared {
+        ledger,
+        events_to_send,
+        clock,
+    } = shared;
+    for command in commands {
+        match command {
+            TableCommand::Action(player_id, action, reply) => {
+                let result = GameCoordinator::apply_action(
+                    || ledger.lock().unwrap(),
+                    &mut game,
+                    player_id,
+                    action,
+                )
+                .map(|client_event| {
+                    let table_event = game.stamp(client_event, clock.now());
+                    // Queued before the next command is taken, so every queue holds the
+                    // table's events in the table's order
+                    let mut events_to_send = events_to_send.lock().unwrap();
+                    for id in game.get_player_list().iter().filter(|id| **id != player_id) {
+                        let queue = events_to_send.entry(*id).or_default();
+                        for event in &table_event {
+                            queue.push(event.clone(), DEFAULT_QUEUE_LIMIT);
+                        }
+                    }
+                    table_event
+                });
+                reply.send(result).ok();
+            }
+            TableCommand::Join(player_id, reply) => {
+                reply
+                    .send(game.add_user(player_id).map_err(CoordinatorError::from))
+                    .ok();
+            }
+            TableCommand::Leave(player_id, reply) => {
+                let result =
+                    GameCoordinator::leave_table(|| ledger.lock().unwrap(), &mut game, player_id)
+                        .map(|_| ());
+                reply.send(result).ok();
+            }
+            TableCommand::MoveAway(player_id, reply) => {
+                let result = if game.is_between_rounds() {
+                    GameCoordinator::leave_table(|| ledger.lock().unwrap(), &mut game, player_id)
+                        .map(|_| ())
+                } else {
+                    Err(CoordinatorError::RoundInProgress)
+                };
+                reply.send(result).ok();
+            }
+            TableCommand::Listing(reply) => {
+                reply.send(TableListing::from_game(&game)).ok();
+            }
+            TableCommand::Inspect(inspect) => inspect(&mut game),
+        }
+    }
+    game
+}
+
+impl ConcurrentCoordinator {
+    pub fn new() -> Self {
+        Self::with_clock(Arc::new(SystemClock))
+    }
+
+    pub fn with_clock(clock: Arc<dyn Clock>) -> Self {
+        Self {
+            ledger: Arc::new(Mutex::new(Ledger::new())),
+            tables: Arc::new(RwLock::new(HashMap::new())),
+            player_tables: Arc::new(RwLock::new(HashMap::new())),
+            player_moves: Arc::new(Mutex::new(HashMap::new())),
+            events_to_send: Arc::new(Mutex::new(HashMap::new())),
+            next_game_id: Arc::new(AtomicU64::new(0)),
+            auth: Arc::new(Authenticator::new()),
+            clock,
+        }
+    }
+
+    // Players here are guests, every session is signed so it can be resumed with verify
+    pub fn on_new_user(&self) -> Session {
+        self.auth.issue(PlayerID::new_v4(), self.clock.now())
+    }
+
+    pub fn verify(&self, token: &SessionToken) -> Result<Session, CoordinatorError> {
+        Ok(self.auth.verify(token, self.clock.now())?)
+    }
+
+    pub fn open_table(&self, config: TableConfig) -> Result<GameID, CoordinatorError> {
+        if !config.is_valid() {
+            return Err(CoordinatorError::InvalidTableConfig);
+        }
+        let id = self.next_game_id.fetch_add(1, Ordering::SeqCst);
+        let mut game = GameState::with_config(id, Vec::new(), config);
+        game.set_shoe_seed(PlayerID::new_v4().as_u128() as u64);
+        let shared = TableShared {
+            ledger: self.ledger.clone(),
+            events_to_send: self.events_to_send.clone(),
+            clock: self.clock.clone(),
+        };
+        let (commands, receiver) = channel();
+        let thread = thread::spawn(move || run_table(game, shared, receiver));
+        self.tables
+            .write()
+            .unwrap()
+            .insert(id, TableHandle { commands, thread });
+        Ok(id)
+    }
+
+    // Sends a command to a table and waits for its answer without holding any coordinator lock
+    fn request<T>(
+        &self,
+        id: GameID,
+        command: impl FnOnce(Reply<T>) -> TableCommand,
+    ) -> Result<T, CoordinatorError> {
+        let (reply, answer) = channel();
+        self.sender(id)?
+            .send(command(reply))
+            .map_err(|_| CoordinatorError::TableNotFound)?;
+        answer.recv().map_err(|_| CoordinatorError::TableNotFound)?
+    }
+
+    fn sender(&self, id: GameID) -> Result<Sender<TableCommand>, CoordinatorError> {
+        self.tables
+            .read()
+            .unwrap()
+            .get(&id)
+            .map(|table| table.commands.clone())
+            .ok_or(CoordinatorError::TableNotFound)
+    }
+
+    fn player_move(&self, player_id: PlayerID) -> Arc<Mutex<()>> {
+        self.player_moves
+            .lock()
+            .unwrap()
+            .entry(player_id)
+            .or_default()
+            .clone()
+    }
+
+    // Seats the player, a seated player moves tables between rounds. The new seat is taken
+    // before the old one is given up, so a full table leaves the player where they were.
+    pub fn join_table(&self, session: &Session, id: GameID) -> Result<(), CoordinatorError> {
+        GameCoordinator::check_session(session, self.clock.now())?;
+        let player_id = session.get_player();
+        let player_move = self.player_move(player_id);
+        let _moving = player_move.lock().unwrap();
+        let current = self.get_player_table(player_id);
+        if current == Some(id) {
+            return Ok(());
+        }
+        self.request(id, |reply| TableCommand::Join(player_id, reply))?;
+        if let Some(current) = current {
+            if let Err(error) =
+                self.request(current, |reply| TableCommand::MoveAway(player_id, reply))
+            {
+                self.request(id, |reply| TableCommand::Leave(player_id, reply))?;
+                return Err(error);
+            }
+        }
+        // A table closed meanwhile has already returned the player's stack and let them go
+        let tables = self.tables.read().unwrap();
+        let mut player_tables = self.player_tables.write().unwrap();
+        if !tables.contains_key(&id) {
+            player_tables.remove(&player_id);
+            return Err(CoordinatorError::TableNotFound);
+        }
+        player_tables.insert(player_id, id);
+        Ok(())
+    }
+
+    // Removes the player from their table and returns their stack to the wallet
+    pub fn leave_table(&self, session: &Session) -> Result<(), CoordinatorError> {
+        GameCoordinator::check_session(session, self.clock.now())?;
+        let player_id = session.get_player();
+        let player_move = self.player_move(player_id);
+        let _moving = player_move.lock().unwrap();
+        let id = self
+            .get_player_table(player_id)
+            .ok_or(CoordinatorError::PlayerNotFound)?;
+        self.request(id, |reply| TableCommand::Leave(player_id, reply))?;
+        self.player_tables.write().unwrap().remove(&player_id);
+        Ok(())
+    }
+
+    pub fn deposit(&self, player_id: PlayerID, amount: ChipPile) -> Result<(), CoordinatorError> {
+        self.ledger.lock().unwrap().transfer(
+            EntryKind::Deposit,
+            Account::Cashier,
+            Account::Wallet(player_id),
+            amount,
+        )?;
+        Ok(())
+    }
+
+    pub fn handle_action(
+        &self,
+        session: &Session,
+        action: GameAction,
+    ) -> Result<Vec<TableEvent>, CoordinatorError> {
+        GameCoordinator::check_session(session, self.clock.now())?;
+        let player_id = session.get_player();
+        let id = self
+            .get_player_table(player_id)
+            .ok_or(CoordinatorError::PlayerNotFound)?;
+        self.request(id, |reply| TableCommand::Action(player_id, action, reply))
+    }
+
+    pub fn get_other_events(&self) -> HashMap<PlayerID, Vec<SequencedEvent>> {
+        self.events_to_send
+            .lock()
+            .unwrap()
+            .iter_mut()
+            .map(|(player_id, queue)| (*player_id, queue.take_undelivered()))
+            .filter(|(_, events)| !events.is_empty())
+            .collect()
+    }
+
+    pub fn ack_events(&self, session: &Session, seq: u64) -> Result<(), CoordinatorError> {
+        GameCoordinator::check_session(session, self.clock.now())?;
+        if let Some(queue) = self
+            .events_to_send
+            .lock()
+            .unwrap()
+            .get_mut(&session.get_player())
+        {
+            queue.ack(seq);
+        }
+        Ok(())
+    }
+
+    pub fn replay_events(
+        &self,
+        session: &Session,
+        last_seen: u64,
+    ) -> Result<Replay, CoordinatorError> {
+        GameCoordinator::check_session(session, self.clock.now())?;
+        Ok(self
+            .events_to_send
+            .lock()
+            .unwrap()
+            .get(&session.get_player())
+            .map(|queue| queue.replay(last_seen))
+            .unwrap_or_else(|| Replay::Events(Vec::new())))
+    }
+
+    pub fn lobby(&self) -> Vec<TableListing> {
+        let ids: Vec<GameID> = self.tables.read().unwrap().keys().copied().collect();
+        let mut listings: Vec<TableListing> = ids
+            .into_iter()
+            .filter_map(|id| {
+                let (reply, answer) = channel();
+                self.sender(id)
+                    .ok()?
+                    .send(TableCommand::Listing(reply))
+                    .ok()?;
+                answer.recv().ok()
+            })
+            .collect();
+        listings.sort_by_key(|listing| listing.id);
+        listings
+    }
+
+    // Runs a closure on the table's own thread, waiting for it to finish
+    pub fn with_table<R: Send + 'static>(
+        &self,
+        id: GameID,
+        inspect: impl FnOnce(&mut GameState) -> R + Send + 'static,
+    ) -> Result<R, CoordinatorError> {
+        let (reply, answer) = channel();
+        self.sender(id)?
+            .send(TableCommand::Inspect(Box::new(move |game| {
+                reply.send(inspect(game)).ok();
+            })))
+            .map_err(|_| CoordinatorError::TableNotFound)?;
+        answer.recv().map_err(|_| CoordinatorError::TableNotFound)
+    }
+
+    // Stops the table's thread and hands back its final state
+    pub fn close_table(&self, id: GameID) -> Result<GameState, CoordinatorError> {
+        let table = self
+            .tables
+            .write()
+            .unwrap()
+            .remove(&id)
+            .ok_or(CoordinatorError::TableNotFound)?;
+        drop(table.commands);
+        let mut game = table
+            .thread
+            .join()
+            .map_err(|_| CoordinatorError::TableNotFound)?;
+        // Only once the table has stopped, so nobody it seated last is left pointing at it
+        self.player_tables
+            .write()
+            .unwrap()
+            .retain(|_, table_id| *table_id != id);
+
+        // Anyone still seated gets their bet refunded and their stack returned
+        game.refund_bets()?;
+        let mut ledger = self.ledger.lock().unwrap();
+        for settlement in game.get_last_settlement() {
+            ledger.transfer(
+                EntryKind::Refund,
+                Account::House,
+                Account::Table(settlement.player),
+                settlement.payout,
+            )?;
+        }
+        for player_id in game.get_player_list() {
+            let money = game.get_player_money(*player_id)?;
+            GameCoordinator::return_to_wallet(&mut ledger, *player_id, money)?;
+        }
+        Ok(game)
+    }
+
+    pub fn get_player_table(&self, player_id: PlayerID) -> Option<GameID> {
+        self.player_tables.read().unwrap().get(&player_id).copied()
+    }
+
+    pub fn get_wallet_balance(&self, player_id: PlayerID) -> ChipPile {
+        self.ledger
+            .lock()
+            .unwrap()
+            .balance(Account::Wallet(player_id))
+    }
+
+    pub fn get_ledger(&self) -> Ledger {
+        self.ledger.lock().unwrap().clone()
+    }
+}
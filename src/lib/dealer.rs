@@ -0,0 +1,96 @@
+use crate::card::Card;
+use crate::tableconfig::DealerRule;
+use std::fmt;
+
+// The best total of a hand, every ace counts as 1 and one of them as 11 when that does not go
+// over 21. The hand is soft while an ace is counted as 11.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HandValue {
+    pub total: i8,
+    pub soft: bool,
+}
+
+impl HandValue {
+    pub fn of(hand: &[Card]) -> Self {
+        let hard: i8 = hand
+            .iter()
+            .map(|card| if card.is_ace() { 1 } else { card.get_value() })
+            .sum();
+        if hand.iter().any(Card::is_ace) && hard + 10 <= 21 {
+            Self {
+                total: hard + 10,
+                soft: true,
+            }
+        } else {
+            Self {
+                total: hard,
+                soft: false,
+            }
+        }
+    }
+}
+
+// Decides whether the dealer draws another card once every player is done. A table plays by its
+// config's DealerRule unless GameState::set_dealer_policy gives it one of its own.
+pub trait DealerPolicy: fmt::Debug + Send + Sync {
+    fn should_hit(&self, hand: &[Card]) -> bool;
+}
+
+impl DealerPolicy for DealerRule {
+    fn should_hit(&self, hand: &[Card]) -> bool {
+        let value = HandValue::of(hand);
+        match self {
+            DealerRule::StandSoft17 => value.total < 17,
+            DealerRule::HitSoft17 => value.total < 17 || value.total == 17 && value.soft,
+            DealerRule::StandOn(total) => value.total < *total,
+        }
+    }
+}
+
+#[test]
+fn dealer_rules() {
+    use crate::card::{CardSuit, Value};
+
+    let hand = |values: &[Value]| -> Vec<Card> {
+        values
+            .iter()
+            .map(|value| Card::new(CardSuit::Spades, *value))
+            .collect()
+    };
+    let soft_17 = hand(&[Value::Ace, Value::Six]);
+    let hard_17 = hand(&[Value::Ten, Value::Seven]);
+    let two_aces = hand(&[Value::Ace, Value::Ace, Value::Five]);
+    let sixteen = hand(&[Value::Ace, Value::Five, Value::Ten]);
+    assert_eq!(
+        HandValue {
+            total: 17,
+            soft: true
+        },
+        HandValue::of(&soft_17)
+    );
+    assert_eq!(
+        HandValue {
+            total: 17,
+            soft: true
+        },
+        HandValue::of(&two_aces)
+    );
+    assert_eq!(
+        HandValue {
+            total: 16,
+            soft: false
+        },
+        HandValue::of(&sixteen)
+    );
+    assert_eq!(
+        22,
+        HandValue::of(&hand(&[Value::Ace, Value::Ace, Value::King, Value::Queen])).total
+    );
+
+    assert!(!DealerRule::StandSoft17.should_hit(&soft_17));
+    assert!(DealerRule::HitSoft17.should_hit(&soft_17));
+    assert!(DealerRule::HitSoft17.should_hit(&two_aces));
+    assert!(!DealerRule::HitSoft17.should_hit(&hard_17));
+    assert!(DealerRule::StandSoft17.should_hit(&sixteen));
+    assert!(!DealerRule::StandOn(16).should_hit(&sixteen));
+}
@@ -0,0 +1,128 @@
+use crate::gamestate::TableEvent;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+
+// How many unacknowledged events a player's queue holds before the oldest are dropped
+pub const DEFAULT_QUEUE_LIMIT: usize = 256;
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct SequencedEvent {
+    pub seq: u64,
+    pub event: TableEvent,
+}
+
+// What a reconnecting client needs to catch up from the last event it saw
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub enum Replay {
+    Events(Vec<SequencedEvent>),
+    // Events it missed were already dropped, a full state snapshot brings it up to this seq
+    Snapshot(u64),
+}
+
+// The outbound events of a single player. Every event gets the next seq and stays queued until
+// the client acknowledges it, so anything lost in transit can be replayed.
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct EventQueue {
+    last_seq: u64,
+    delivered: u64,
+    dropped_through: u64,
+    pending: VecDeque<SequencedEvent>,
+}
+
+impl EventQueue {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, event: TableEvent, limit: usize) -> u64 {
+        self.last_seq += 1;
+        self.pending.push_back(SequencedEvent {
+            seq: self.last_seq,
+            event,
+        });
+        while self.pending.len() > limit {
+            if let Some(dropped) = self.pending.pop_front() {
+                self.dropped_through = dropped.seq;
+            }
+        }
+        self.last_seq
+    }
+
+    // Hands out the events that have not been handed out yet, they stay queued until acknowledged
+    pub fn take_undelivered(&mut self) -> Vec<SequencedEvent> {
+        let events = self.get_undelivered();
+        self.delivered = self.last_seq;
+        events
+    }
+
+    pub fn get_undelivered(&self) -> Vec<SequencedEvent> {
+        self.pending
+            .iter()
+            .filter(|event| event.seq > self.delivered)
+            .cloned()
+            .collect()
+    }
+
+    // The client has processed everything up to and including seq
+    pub fn ack(&mut self, seq: u64) {
+        let seq = seq.min(self.last_seq);
+        while self.pending.front().is_some_and(|event| event.seq <= seq) {
+            self.pending.pop_front();
+        }
+        self.delivered = self.delivered.max(seq);
+    }
+
+    pub fn replay(&self, last_seen: u64) -> Replay {
+        if last_seen < self.dropped_through {
+            return Replay::Snapshot(self.last_seq);
+        }
+        Replay::Events(
+            self.pending
+                .iter()
+                .filter(|event| event.seq > last_seen)
+                .cloned()
+                .collect(),
+        )
+    }
+
+    pub fn get_last_seq(&self) -> u64 {
+        self.last_seq
+    }
+
+    pub fn get_pending(&self) -> &VecDeque<SequencedEvent> {
+        &self.pending
+    }
+}
+
+#[test]
+fn queue_replays_unacknowledged_events() {
+    let round_over = TableEvent {
+        seq: 1,
+        at: time::OffsetDateTime::unix_epoch(),
+        event: crate::gamestate::ClientEvent::RoundOver,
+    };
+    let mut queue = EventQueue::new();
+    for _ in 0..3 {
+        queue.push(round_over.clone(), 4);
+    }
+    assert_eq!(3, queue.take_undelivered().len());
+    assert!(queue.take_undelivered().is_empty());
+
+    // Delivered but unacknowledged events are still replayed
+    queue.ack(1);
+    assert_eq!(
+        vec![2, 3],
+        match queue.replay(1) {
+            Replay::Events(events) => events.iter().map(|event| event.seq).collect(),
+            Replay::Snapshot(_) => Vec::new(),
+        }
+    );
+
+    // Once the limit pushes out events the client never saw it needs a snapshot
+    for _ in 0..3 {
+        queue.push(round_over.clone(), 4);
+    }
+    assert_eq!(4, queue.get_pending().len());
+    assert_eq!(Replay::Snapshot(6), queue.replay(1));
+    assert!(matches!(queue.replay(2), Replay::Events(events) if events.len() == 4));
+}
@@ -1,26 +1,142 @@
 #![allow(unused_imports)]
 #![allow(dead_code)]
+use crate::auth::{AuthError, Authenticator, Credential, Session, SessionToken};
+use crate::clock::{Clock, SystemClock};
+use crate::eventqueue::{EventQueue, Replay, SequencedEvent, DEFAULT_QUEUE_LIMIT};
 use crate::gamestate::{
-    ChipPile, ClientEvent, FromPlayer, GameAction, GameError, GameState, PlayerID,
+    ChipPile, ClientEvent, FromPlayer, GameAction, GameError, GameID, GameState, PlayerID,
+    Settlement, TableEvent,
 };
+use crate::ledger::{Account, EntryKind, Ledger, LedgerEntry, LedgerError};
+use crate::observer::{GameObserver, Observers};
+use crate::profile::{PlayerProfile, ProfileError, ProfileRegistry, ProfileUpdate, PublicProfile};
+use crate::storage::StorageError;
+use crate::strategy::{BotStrategy, PlayerStrategy};
+use crate::tableconfig::{BuyInLimits, TableConfig};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::ops::DerefMut;
 use std::option::Option;
-use time::Time;
+use std::sync::Arc;
+use time::OffsetDateTime;
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct GameCoordinator {
     available_players: Vec<PlayerID>,
-    current_games: Vec<GameState>,
-    last_player_input: HashMap<PlayerID, Time>,
-    player_money: HashMap<PlayerID, ChipPile>,
-    events_to_send: HashMap<PlayerID, Vec<ClientEvent>>,
+    current_games: HashMap<GameID, GameState>,
+    player_tables: HashMap<PlayerID, GameID>,
+    next_game_id: GameID,
+    last_player_input: HashMap<PlayerID, OffsetDateTime>,
+    ledger: Ledger,
+    events_to_send: HashMap<PlayerID, EventQueue>,
+    event_queue_limit: usize,
+    table_config: TableConfig,
+    countdown_started: Option<OffsetDateTime>,
+    table_activity: HashMap<GameID, OffsetDateTime>,
+    spectators: HashMap<GameID, Vec<PlayerID>>,
+    archived_games: Vec<ArchivedTable>,
+    #[serde(default = "default_archive_limit")]
+    archive_limit: usize,
+    shoe_seed: u64,
+    disconnected: HashMap<PlayerID, OffsetDateTime>,
+    auth: Authenticator,
+    profiles: ProfileRegistry,
+    // Every timestamp the coordinator takes comes from here, a restored coordinator starts on
+    // the system clock
+    #[serde(skip, default = "system_clock")]
+    clock: Arc<dyn Clock>,
+    // Added to every table, a restored coordinator has none until they are added again
+    #[serde(skip)]
+    observers: Observers,
+    // Seated players the coordinator plays for, by the strategy each one plays
+    #[serde(default)]
+    bots: HashMap<PlayerID, BotStrategy>,
+    // While set the coordinator leaves its bots' moves to its owner and only notes the tables
+    // where they may have one, see defer_bot_turns
+    #[serde(skip)]
+    bot_turns: Option<Vec<GameID>>,
+}
+
+// A move one of the coordinator's bots wants to make, fallback is made instead when the table
+// refuses it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BotMove {
+    pub bot: PlayerID,
+    pub action: GameAction,
+    pub fallback: Option<GameAction>,
+}
+
+fn system_clock() -> Arc<dyn Clock> {
+    Arc::new(SystemClock)
+}
+
+// How many closed tables are kept by default, the oldest are forgotten first
+pub const DEFAULT_ARCHIVE_LIMIT: usize = 100;
+
+fn default_archive_limit() -> usize {
+    DEFAULT_ARCHIVE_LIMIT
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq)]
+pub enum CloseReason {
+    Empty,
+    Abandoned,
+}
+
+// What is kept of a closed table for history after it leaves current_games. last_settlement is
+// the table's last round, or the bets refunded when it closed mid-round.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct ArchivedTable {
+    pub id: GameID,
+    pub reason: CloseReason,
+    pub closed_at: OffsetDateTime,
+    #[serde(default)]
+    pub last_settlement: Vec<Settlement>,
 }
 
 #[derive(Debug)]
 pub enum CoordinatorError {
     GameError(GameError),
     PlayerNotFound,
+    StorageError(StorageError),
+    LedgerError(LedgerError),
+    RoundInProgress,
+    OutsideBuyInLimits(BuyInLimits),
+    TableNotFound,
+    TableFull,
+    TableNameTaken,
+    InvalidTableConfig,
+    AuthError(AuthError),
+    ProfileError(ProfileError),
+}
+
+// What the lobby shows about a table before a player sits down
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct TableListing {
+    pub id: GameID,
+    pub name: String,
+    pub occupancy: usize,
+    pub max_seats: usize,
+    pub min_bet: ChipPile,
+    pub max_bet: ChipPile,
+    pub buy_in_limits: BuyInLimits,
+    pub rules: String,
+}
+
+impl TableListing {
+    pub fn from_game(game: &GameState) -> Self {
+        let config = game.get_config();
+        Self {
+            id: game.get_id(),
+            name: config.name.clone(),
+            occupancy: game.get_player_list().len(),
+            max_seats: config.max_seats,
+            min_bet: config.min_bet,
+            max_bet: config.max_bet,
+            buy_in_limits: config.buy_in_limits,
+            rules: config.rules_summary(),
+        }
+    }
 }
 
 impl From<GameError> for CoordinatorError {
@@ -29,92 +145,1177 @@ impl From<GameError> for CoordinatorError {
     }
 }
 
+impl From<StorageError> for CoordinatorError {
+    fn from(error: StorageError) -> Self {
+        CoordinatorError::StorageError(error)
+    }
+}
+
+impl From<LedgerError> for CoordinatorError {
+    fn from(error: LedgerError) -> Self {
+        CoordinatorError::LedgerError(error)
+    }
+}
+
+impl From<AuthError> for CoordinatorError {
+    fn from(error: AuthError) -> Self {
+        CoordinatorError::AuthError(error)
+    }
+}
+
+impl From<ProfileError> for CoordinatorError {
+    fn from(error: ProfileError) -> Self {
+        CoordinatorError::ProfileError(error)
+    }
+}
+
+impl Default for GameCoordinator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl GameCoordinator {
     pub fn new() -> Self {
+        Self::with_config(TableConfig::default())
+    }
+
+    pub fn with_config(table_config: TableConfig) -> Self {
         Self {
             available_players: Vec::new(),
-            current_games: Vec::new(),
+            current_games: HashMap::new(),
+            player_tables: HashMap::new(),
+            next_game_id: 0,
             last_player_input: HashMap::new(),
-            player_money: HashMap::new(),
+            ledger: Ledger::new(),
             events_to_send: HashMap::new(),
+            event_queue_limit: DEFAULT_QUEUE_LIMIT,
+            table_config,
+            countdown_started: None,
+            table_activity: HashMap::new(),
+            spectators: HashMap::new(),
+            archived_games: Vec::new(),
+            archive_limit: DEFAULT_ARCHIVE_LIMIT,
+            shoe_seed: PlayerID::new_v4().as_u128() as u64,
+            disconnected: HashMap::new(),
+            auth: Authenticator::new(),
+            profiles: ProfileRegistry::new(),
+            clock: system_clock(),
+            observers: Observers::default(),
+            bots: HashMap::new(),
+            bot_turns: None,
         }
     }
 
-    pub fn on_new_user(&mut self) -> PlayerID {
-        let id = PlayerID::new_v4();
-        self.available_players.push(id);
-        if self.available_players.len() == 4 {
-            self.current_games
-                .push(GameState::new(self.available_players.clone()));
-            self.available_players.clear();
+    pub fn now(&self) -> OffsetDateTime {
+        self.clock.now()
+    }
+
+    pub fn get_clock(&self) -> Arc<dyn Clock> {
+        self.clock.clone()
+    }
+
+    pub fn set_clock(&mut self, clock: Arc<dyn Clock>) {
+        self.clock = clock;
+    }
+
+    // Watches every open table and every table opened from now on
+    pub fn add_observer(&mut self, observer: Arc<dyn GameObserver>) {
+        for game in self.current_games.values_mut() {
+            game.add_observer(observer.clone());
         }
+        self.observers.push(observer);
+    }
+
+    // Every connection starts out as a guest with a session of its own, see register and login
+    pub fn on_new_user(&mut self) -> Session {
+        let player_id = self.add_user(PlayerID::new_v4());
+        self.auth.issue(player_id, self.now())
+    }
+
+    // Adds a user with a known id, used when replaying a journal
+    pub fn add_user(&mut self, id: PlayerID) -> PlayerID {
+        self.available_players.push(id);
+        self.matchmake(self.now());
 
         id
     }
 
     pub fn on_dropped_user(&mut self, player_id: PlayerID) {
+        self.on_dropped_user_at(player_id, self.now());
+    }
+
+    // Seated players are only marked disconnected and keep their seat, hand and bet for the
+    // table's reconnect_grace. Waiting players have nothing at stake and leave straight away.
+    pub fn on_dropped_user_at(&mut self, player_id: PlayerID, now: OffsetDateTime) {
+        match self.player_tables.get(&player_id).copied() {
+            Some(id) => {
+                self.disconnected.insert(player_id, now);
+                self.table_activity.insert(id, now);
+                self.auto_stand(id);
+                self.play_bots(id);
+            }
+            None => self.remove_player(player_id, now),
+        }
+    }
+
+    // Takes a player off the coordinator for good, the stack left at the table goes back to
+    // their wallet, even mid-round
+    fn remove_player(&mut self, player_id: PlayerID, now: OffsetDateTime) {
+        self.disconnected.remove(&player_id);
+        self.events_to_send.remove(&player_id);
         if self.available_players.contains(&player_id) {
             self.available_players.retain(|&x| x != player_id);
         } else {
-            for game in self.get_mut_current_games() {
-                game.remove_user(player_id);
+            let current_games = &mut self.current_games;
+            let table_activity = &mut self.table_activity;
+            if let Some(game) = self
+                .player_tables
+                .remove(&player_id)
+                .and_then(|id| current_games.get_mut(&id))
+            {
+                let id = game.get_id();
+                table_activity.insert(id, now);
+                let ledger = &mut self.ledger;
+                if let Ok(client_event) = Self::leave_table(|| ledger, game, player_id) {
+                    self.publish(id, None, client_event);
+                }
+                self.auto_stand(id);
+                self.play_bots(id);
             }
         }
+        self.matchmake(now);
     }
 
+    // A disconnected player whose turn comes up stands, so the rest of the table is not held up
+    fn auto_stand(&mut self, id: GameID) {
+        loop {
+            let game = match self.current_games.get_mut(&id) {
+                Some(game) => game,
+                None => return,
+            };
+            let player_id = match game.get_current_player() {
+                Some(player_id)
+                    if !game.is_between_rounds() && self.disconnected.contains_key(&player_id) =>
+                {
+                    player_id
+                }
+                _ => return,
+            };
+            let ledger = &mut self.ledger;
+            match Self::apply_action(|| ledger, game, player_id, GameAction::Stand) {
+                // Nobody asked for these, so everyone gets them queued, the player who stood included
+                Ok(client_event) => {
+                    self.publish(id, None, client_event);
+                }
+                Err(_) => return,
+            }
+        }
+    }
+
+    // Players who did not come back within their table's reconnect_grace are removed
+    fn expire_disconnected(&mut self, now: OffsetDateTime) {
+        let mut expired: Vec<PlayerID> = self
+            .disconnected
+            .iter()
+            .filter(|(player_id, since)| {
+                let grace = self
+                    .get_player_game(**player_id)
+                    .map(|game| game.get_config().reconnect_grace)
+                    .unwrap_or_default();
+                now >= **since + grace
+            })
+            .map(|(player_id, _)| *player_id)
+            .collect();
+        expired.sort();
+        for player_id in expired {
+            self.remove_player(player_id, now);
+        }
+    }
+
+    pub fn set_profile(
+        &mut self,
+        session: &Session,
+        update: ProfileUpdate,
+    ) -> Result<PlayerProfile, CoordinatorError> {
+        self.set_profile_at(session, update, self.now())
+    }
+
+    // Creates or changes a known player's profile, now only counts for a new profile
+    pub fn set_profile_at(
+        &mut self,
+        session: &Session,
+        update: ProfileUpdate,
+        now: OffsetDateTime,
+    ) -> Result<PlayerProfile, CoordinatorError> {
+        Self::check_session(session, now)?;
+        let player_id = session.get_player();
+        if !self.is_known_player(player_id) {
+            return Err(CoordinatorError::PlayerNotFound);
+        }
+        Ok(self.profiles.update(player_id, update, now)?.clone())
+    }
+
+    pub fn get_profile(&self, player_id: PlayerID) -> Option<&PlayerProfile> {
+        self.profiles.get(player_id)
+    }
+
+    pub fn get_public_profile(&self, player_id: PlayerID) -> PublicProfile {
+        self.profiles.get_public(player_id)
+    }
+
+    pub fn get_profiles(&self) -> &ProfileRegistry {
+        &self.profiles
+    }
+
+    // Lets the name checks be swapped out
+    pub fn get_mut_profiles(&mut self) -> &mut ProfileRegistry {
+        &mut self.profiles
+    }
+
+    // Gives the session's player a username and password to log in with from now on, a guest
+    // keeps their id, wallet and seat
+    pub fn register(
+        &mut self,
+        session: &Session,
+        username: &str,
+        password: &str,
+    ) -> Result<(), CoordinatorError> {
+        Self::check_session(session, self.now())?;
+        if self.auth.is_username_taken(username) {
+            return Err(AuthError::UsernameTaken.into());
+        }
+        let credential = self.auth.hash_password(session.get_player(), password)?;
+        self.add_credential(username, credential)
+    }
+
+    // Adds an already hashed credential, used when replaying a journal
+    pub fn add_credential(
+        &mut self,
+        username: &str,
+        credential: Credential,
+    ) -> Result<(), CoordinatorError> {
+        if !self.is_known_player(credential.player) {
+            return Err(CoordinatorError::PlayerNotFound);
+        }
+        self.auth.add_credential(username, credential)?;
+        Ok(())
+    }
+
+    pub fn login(&mut self, username: &str, password: &str) -> Result<Session, CoordinatorError> {
+        let session = self.auth.login(username, password, self.now())?;
+        self.reconnect(&session)?;
+        Ok(session)
+    }
+
+    // Checks a token without touching the player, see resume for reconnecting
+    pub fn verify(&self, token: &SessionToken) -> Result<Session, CoordinatorError> {
+        Ok(self.auth.verify(token, self.now())?)
+    }
+
+    // Hands a reconnecting client back its session, as long as the token has not expired
+    pub fn resume(&mut self, token: &SessionToken) -> Result<Session, CoordinatorError> {
+        let session = self.verify(token)?;
+        self.reconnect(&session)?;
+        Ok(session)
+    }
+
+    // A player still within their reconnect grace takes their seat back, a player who was
+    // already removed waits for a table again
+    pub fn reconnect(&mut self, session: &Session) -> Result<(), CoordinatorError> {
+        Self::check_session(session, self.now())?;
+        let player_id = session.get_player();
+        self.disconnected.remove(&player_id);
+        if !self.available_players.contains(&player_id)
+            && !self.player_tables.contains_key(&player_id)
+        {
+            self.add_user(player_id);
+        }
+        Ok(())
+    }
+
+    pub fn get_auth(&self) -> &Authenticator {
+        &self.auth
+    }
+
+    // Lets the token lifetime and hash rounds be changed
+    pub fn get_mut_auth(&mut self) -> &mut Authenticator {
+        &mut self.auth
+    }
+
+    pub fn is_disconnected(&self, player_id: PlayerID) -> bool {
+        self.disconnected.contains_key(&player_id)
+    }
+
+    // Starts any table whose countdown has expired and removes players whose reconnect grace
+    // has run out, should be called periodically by the server
+    pub fn tick(&mut self) {
+        self.tick_at(self.now());
+    }
+
+    pub fn tick_at(&mut self, now: OffsetDateTime) {
+        self.expire_disconnected(now);
+        self.matchmake(now);
+        self.collect_tables(now);
+    }
+
+    // Closes tables that have been empty or idle for longer than their grace period
+    fn collect_tables(&mut self, now: OffsetDateTime) {
+        let mut expired: Vec<(GameID, CloseReason)> = self
+            .current_games
+            .values()
+            .filter(|game| !game.get_config().persistent)
+            .filter_map(|game| {
+                let config = game.get_config();
+                let last_activity = *self.table_activity.get(&game.get_id())?;
+                if game.get_player_list().is_empty() && now >= last_activity + config.empty_grace {
+                    Some((game.get_id(), CloseReason::Empty))
+                } else if now >= last_activity + config.abandoned_after {
+                    Some((game.get_id(), CloseReason::Abandoned))
+                } else {
+                    None
+                }
+            })
+            .collect();
+        expired.sort_by_key(|(id, _)| *id);
+        for (id, reason) in expired {
+            self.close_table(id, reason, now).ok();
+        }
+    }
+
+    // Refunds any bets still in play, returns every stack to its wallet and archives the table
+    fn close_table(
+        &mut self,
+        id: GameID,
+        reason: CloseReason,
+        now: OffsetDateTime,
+    ) -> Result<(), CoordinatorError> {
+        let mut game = self
+            .current_games
+            .remove(&id)
+            .ok_or(CoordinatorError::TableNotFound)?;
+        self.table_activity.remove(&id);
+
+        game.refund_bets()?;
+        for settlement in game.get_last_settlement() {
+            self.ledger.transfer(
+                EntryKind::Refund,
+                Account::House,
+                Account::Table(settlement.player),
+                settlement.payout,
+            )?;
+        }
+        let mut watchers = self.spectators.remove(&id).unwrap_or_default();
+        for player_id in game.get_player_list().clone() {
+            let money = game.get_player_money(player_id)?;
+            Self::return_to_wallet(&mut self.ledger, player_id, money)?;
+            self.player_tables.remove(&player_id);
+            watchers.push(player_id);
+        }
+        game.notify(&[ClientEvent::TableClosed(id)]);
+        let closed = game.stamp(vec![ClientEvent::TableClosed(id)], now);
+        for player_id in watchers {
+            self.events_to_send
+                .entry(player_id)
+                .or_default()
+                .push(closed[0].clone(), self.event_queue_limit);
+        }
+
+        self.archived_games.push(ArchivedTable {
+            id,
+            reason,
+            closed_at: now,
+            last_settlement: game.get_last_settlement().to_vec(),
+        });
+        self.trim_archive();
+        Ok(())
+    }
+
+    // Spectators receive a table's events without taking a seat
+    pub fn spectate(&mut self, session: &Session, id: GameID) -> Result<(), CoordinatorError> {
+        Self::check_session(session, self.now())?;
+        let player_id = session.get_player();
+        self.get_game(id)?;
+        let watchers = self.spectators.entry(id).or_default();
+        if !watchers.contains(&player_id) {
+            watchers.push(player_id);
+        }
+        Ok(())
+    }
+
+    pub fn stop_spectating(
+        &mut self,
+        session: &Session,
+        id: GameID,
+    ) -> Result<(), CoordinatorError> {
+        Self::check_session(session, self.now())?;
+        if let Some(watchers) = self.spectators.get_mut(&id) {
+            watchers.retain(|&x| x != session.get_player());
+        }
+        Ok(())
+    }
+
+    fn matchmake(&mut self, now: OffsetDateTime) {
+        // Waiting players first fill open seats at tables that are between rounds
+        while !self.available_players.is_empty() {
+            let open_table = self
+                .current_games
+                .values()
+                .filter(|game| game.has_open_seat())
+                .map(|game| game.get_id())
+                .min();
+            match open_table {
+                Some(id) => {
+                    let player_id = self.available_players.remove(0);
+                    self.seat_player(player_id, id).ok();
+                }
+                None => break,
+            }
+        }
+
+        let config = self.table_config.clone();
+        while self.available_players.len() >= config.max_seats {
+            let players: Vec<PlayerID> = self.available_players.drain(..config.max_seats).collect();
+            self.start_table(players);
+        }
+
+        if self.available_players.len() < config.min_seats {
+            self.countdown_started = None;
+            return;
+        }
+        let started = *self.countdown_started.get_or_insert(now);
+        if now >= started + config.start_countdown {
+            let players: Vec<PlayerID> = self.available_players.drain(..).collect();
+            self.start_table(players);
+            self.countdown_started = None;
+        }
+    }
+
+    fn start_table(&mut self, players: Vec<PlayerID>) -> GameID {
+        let id = self.next_game_id;
+        self.next_game_id += 1;
+        let mut config = self.table_config.clone();
+        config.name = format!("{} {}", config.name, id + 1);
+        for player_id in &players {
+            self.player_tables.insert(*player_id, id);
+        }
+        self.table_activity.insert(id, self.now());
+        let joined: Vec<ClientEvent> = players
+            .iter()
+            .map(|player_id| ClientEvent::PlayerJoined(self.profiles.get_public(*player_id)))
+            .collect();
+        self.current_games
+            .insert(id, self.new_table(id, players, config));
+        self.announce(id, joined);
+        id
+    }
+
+    // Tables shuffle from the coordinator's seed so replaying the journal deals the same cards
+    fn new_table(&self, id: GameID, players: Vec<PlayerID>, config: TableConfig) -> GameState {
+        let mut game = GameState::with_config(id, players, config);
+        game.set_shoe_seed(self.shoe_seed ^ id);
+        for observer in self.observers.iter() {
+            game.add_observer(observer.clone());
+        }
+        game
+    }
+
+    fn seat_player(&mut self, player_id: PlayerID, id: GameID) -> Result<(), CoordinatorError> {
+        self.get_mut_game(id)?.add_user(player_id)?;
+        self.player_tables.insert(player_id, id);
+        self.table_activity.insert(id, self.now());
+        let joined = ClientEvent::PlayerJoined(self.profiles.get_public(player_id));
+        self.announce(id, vec![joined]);
+        Ok(())
+    }
+
+    // Opens an empty named table that players can pick from the lobby
+    pub fn open_table(&mut self, config: TableConfig) -> Result<GameID, CoordinatorError> {
+        if !config.is_valid() {
+            return Err(CoordinatorError::InvalidTableConfig);
+        }
+        if self.find_table(&config.name).is_some() {
+            return Err(CoordinatorError::TableNameTaken);
+        }
+        let id = self.next_game_id;
+        self.next_game_id += 1;
+        self.current_games
+            .insert(id, self.new_table(id, Vec::new(), config));
+        self.table_activity.insert(id, self.now());
+        Ok(id)
+    }
+
+    pub fn lobby(&self) -> Vec<TableListing> {
+        let mut listings: Vec<TableListing> = self
+            .current_games
+            .values()
+            .map(TableListing::from_game)
+            .collect();
+        listings.sort_by_key(|listing| listing.id);
+        listings
+    }
+
+    pub fn find_table(&self, name: &str) -> Option<GameID> {
+        self.current_games
+            .values()
+            .find(|game| game.get_name() == name)
+            .map(|game| game.get_id())
+    }
+
+    // Seats a waiting player at the table, a seated player moves tables between rounds
+    pub fn join_table(&mut self, session: &Session, id: GameID) -> Result<(), CoordinatorError> {
+        Self::check_session(session, self.now())?;
+        let player_id = session.get_player();
+        let target = self
+            .current_games
+            .get(&id)
+            .ok_or(CoordinatorError::TableNotFound)?;
+        if self.player_tables.get(&player_id) == Some(&id) {
+            return Ok(());
+        }
+        if !target.has_open_seat() {
+            return Err(CoordinatorError::TableFull);
+        }
+
+        if self.available_players.contains(&player_id) {
+            self.available_players.retain(|&x| x != player_id);
+        } else {
+            let current =
+                Self::lookup_player_game(&self.player_tables, &mut self.current_games, player_id)?;
+            if !current.is_between_rounds() {
+                return Err(CoordinatorError::RoundInProgress);
+            }
+            let current_id = current.get_id();
+            let ledger = &mut self.ledger;
+            let client_event = Self::leave_table(|| ledger, current, player_id)?;
+            self.player_tables.remove(&player_id);
+            self.table_activity.insert(current_id, self.now());
+            self.publish(current_id, None, client_event);
+        }
+        self.seat_player(player_id, id)
+    }
+
+    // Picks the open table whose minimum bet is closest to the player's preference
+    pub fn join_by_stakes(
+        &mut self,
+        session: &Session,
+        preferred_bet: ChipPile,
+    ) -> Result<GameID, CoordinatorError> {
+        Self::check_session(session, self.now())?;
+        let distance = |game: &GameState| (game.get_config().min_bet - preferred_bet).abs();
+        let id = self
+            .current_games
+            .values()
+            .filter(|game| game.has_open_seat())
+            .min_by(|a, b| {
+                distance(a)
+                    .partial_cmp(&distance(b))
+                    .unwrap_or(std::cmp::Ordering::Equal)
+                    .then(a.get_id().cmp(&b.get_id()))
+            })
+            .map(|game| game.get_id())
+            .ok_or(CoordinatorError::TableFull)?;
+        self.join_table(session, id)?;
+        Ok(id)
+    }
+
+    // Applies a player's action, then lets the bots at their table take any turns that follow
     pub fn handle_action(
         &mut self,
-        player_id: PlayerID,
+        session: &Session,
         action: GameAction,
-    ) -> Result<Vec<ClientEvent>, CoordinatorError> {
-        let player_game = self
-            .get_mut_current_games()
-            .iter_mut()
-            .filter(|game| game.get_player_list().contains(&&player_id))
-            .next()
-            .ok_or(CoordinatorError::PlayerNotFound)?;
-        let client_event = player_game.action(action, player_id)?;
-        let players = player_game.get_player_list().clone();
-        drop(player_game);
+    ) -> Result<Vec<TableEvent>, CoordinatorError> {
+        let table_event = self.act(session, action)?;
+        if let Some(id) = self.player_tables.get(&session.get_player()).copied() {
+            self.play_bots(id);
+        }
+        Ok(table_event)
+    }
+
+    // Seats a computer player that bets and plays through the same validation and events as
+    // everyone else. Its buy-in is deposited by the cashier like any other player's. It first
+    // bets once someone acts at the table or play_bots is called, so the seats can be filled
+    // before betting closes them.
+    pub fn seat_bot(
+        &mut self,
+        id: GameID,
+        strategy: BotStrategy,
+        buy_in: ChipPile,
+    ) -> Result<PlayerID, CoordinatorError> {
+        let bot = PlayerID::new_v4();
+        self.seat_bot_as(bot, id, strategy, buy_in)?;
+        Ok(bot)
+    }
+
+    // Seats a bot with a known id, used when replaying a journal
+    pub fn seat_bot_as(
+        &mut self,
+        bot: PlayerID,
+        id: GameID,
+        strategy: BotStrategy,
+        buy_in: ChipPile,
+    ) -> Result<(), CoordinatorError> {
+        let game = self.get_game(id)?;
+        if !game.has_open_seat() {
+            return Err(CoordinatorError::TableFull);
+        }
+        let limits = &game.get_config().buy_in_limits;
+        if !game.is_between_rounds() || buy_in < limits.min || buy_in > limits.max {
+            return Err(GameError::InvaildAction.into());
+        }
+        self.ledger.transfer(
+            EntryKind::Deposit,
+            Account::Cashier,
+            Account::Wallet(bot),
+            buy_in,
+        )?;
+        self.seat_player(bot, id)?;
+        self.bots.insert(bot, strategy);
+        if let Err(error) = self.act(&Session::trusted(bot), GameAction::BuyIn(buy_in)) {
+            self.remove_bot(bot)?;
+            return Err(error);
+        }
+        Ok(())
+    }
+
+    // Frees the bot's seat, its stack goes back to its wallet
+    pub fn remove_bot(&mut self, bot: PlayerID) -> Result<(), CoordinatorError> {
+        if self.bots.remove(&bot).is_none() {
+            return Err(CoordinatorError::PlayerNotFound);
+        }
+        self.remove_player(bot, self.now());
+        Ok(())
+    }
+
+    pub fn is_bot(&self, player_id: PlayerID) -> bool {
+        self.bots.contains_key(&player_id)
+    }
+
+    // Stops the coordinator moving its bots itself. Each table where a bot may have a move is
+    // noted instead, for the owner to make the moves with get_bot_move and handle_action, as
+    // PersistentCoordinator does to journal them.
+    pub fn defer_bot_turns(&mut self) {
+        self.bot_turns.get_or_insert_with(Vec::new);
+    }
+
+    // The tables noted since the last call, in the order they were first noted
+    pub fn take_bot_turns(&mut self) -> Vec<GameID> {
+        self.bot_turns
+            .as_mut()
+            .map(std::mem::take)
+            .unwrap_or_default()
+    }
+
+    // The move of the first bot at the table that has one, as its strategy decides it
+    pub fn get_bot_move(&self, id: GameID) -> Option<BotMove> {
+        let game = self.current_games.get(&id)?;
+        game.get_player_list().iter().find_map(|bot| {
+            let strategy = self.bots.get(bot)?;
+            let legal = game.legal_actions(*bot);
+            let view = game.get_player_view(*bot).ok()?;
+            if legal.is_empty() {
+                return None;
+            }
+            let bankroll = game.get_player_money(*bot).unwrap_or(0.0);
+            Some(BotMove {
+                bot: *bot,
+                action: strategy.decide(&view, bankroll, &legal),
+                fallback: Some(GameAction::Stand).filter(|stand| legal.contains(stand)),
+            })
+        })
+    }
+
+    // Lets the bots at a table act until nobody but a human can move or a round is over, so a
+    // table of only bots plays one round per call. Bots bet as soon as betting is open and play
+    // their hands as their turns come up.
+    pub fn play_bots(&mut self, id: GameID) {
+        if let Some(turns) = &mut self.bot_turns {
+            if !turns.contains(&id) {
+                turns.push(id);
+            }
+            return;
+        }
+        while let Some(next) = self.get_bot_move(id) {
+            let session = Session::trusted(next.bot);
+            let result = match (self.act(&session, next.action), next.fallback) {
+                (Err(_), Some(fallback)) => self.act(&session, fallback),
+                (result, _) => result,
+            };
+            match result {
+                Ok(table_event) if !Self::is_round_over(&table_event) => {}
+                _ => return,
+            }
+        }
+    }
+
+    // Whether the events of an action include the end of the round
+    pub fn is_round_over(table_event: &[TableEvent]) -> bool {
+        table_event
+            .iter()
+            .any(|event| event.event == ClientEvent::RoundOver)
+    }
+
+    fn act(
+        &mut self,
+        session: &Session,
+        action: GameAction,
+    ) -> Result<Vec<TableEvent>, CoordinatorError> {
+        let player_id = session.get_player();
+        let now = self.now();
+        Self::check_session(session, now)?;
+        let player_game =
+            Self::lookup_player_game(&self.player_tables, &mut self.current_games, player_id)?;
+        let game_id = player_game.get_id();
+
+        let ledger = &mut self.ledger;
+        let client_event = Self::apply_action(|| ledger, player_game, player_id, action)?;
+
+        self.table_activity.insert(game_id, now);
+        self.last_player_input.insert(player_id, now);
+        let table_event = self.publish(game_id, Some(player_id), client_event);
+        self.auto_stand(game_id);
+        Ok(table_event)
+    }
+
+    // Every coordinator checks a session here before doing anything for it, so a token that ran
+    // out mid-connection stops playing
+    pub fn check_session(session: &Session, now: OffsetDateTime) -> Result<(), CoordinatorError> {
+        if session.is_expired(now) {
+            return Err(AuthError::TokenExpired.into());
+        }
+        Ok(())
+    }
+
+    // Stamps what happened at a table with the table's next seqs and the time, then queues it
+    fn publish(
+        &mut self,
+        id: GameID,
+        except: Option<PlayerID>,
+        client_event: Vec<ClientEvent>,
+    ) -> Vec<TableEvent> {
+        let now = self.now();
+        let table_event = match self.current_games.get_mut(&id) {
+            Some(game) => game.stamp(client_event, now),
+            None => return Vec::new(),
+        };
+        self.queue_events(id, except, &table_event);
+        table_event
+    }
+
+    // Publishes events the coordinator made rather than the table, telling its observers first
+    fn announce(&mut self, id: GameID, client_event: Vec<ClientEvent>) -> Vec<TableEvent> {
+        if let Some(game) = self.current_games.get(&id) {
+            game.notify(&client_event);
+        }
+        self.publish(id, None, client_event)
+    }
+
+    // Queues events for everyone at the table and watching it, apart from the player who
+    // already has them as the answer to their action
+    fn queue_events(&mut self, id: GameID, except: Option<PlayerID>, client_event: &[TableEvent]) {
+        let players = match self.current_games.get(&id) {
+            Some(game) => game.get_player_list(),
+            None => return,
+        };
+        let events_to_send = &mut self.events_to_send;
+        let limit = self.event_queue_limit;
+        let bots = &self.bots;
         players
             .iter()
-            .filter(|id| id != &&player_id)
+            .chain(self.spectators.get(&id).into_iter().flatten())
+            .filter(|id| Some(**id) != except && !bots.contains_key(id))
             .for_each(|id| {
-                self.events_to_send
-                    .entry(*id)
-                    .and_modify(|events| events.extend(client_event.clone()))
-                    .or_insert(client_event.clone());
+                let queue = events_to_send.entry(*id).or_default();
+                for event in client_event {
+                    queue.push(event.clone(), limit);
+                }
             });
+    }
+
+    // Runs an action against a single table, keeping the ledger in step with the table's money.
+    // The ledger is only borrowed once the table has done its part, so a shared ledger stays
+    // locked for as short a time as possible.
+    pub(crate) fn apply_action<L, F>(
+        lock_ledger: F,
+        game: &mut GameState,
+        player_id: PlayerID,
+        action: GameAction,
+    ) -> Result<Vec<ClientEvent>, CoordinatorError>
+    where
+        L: DerefMut<Target = Ledger>,
+        F: FnOnce() -> L,
+    {
+        match action {
+            // Chips can only reach the table through the wallet
+            GameAction::AddMoney(_) => Err(GameError::InvaildAction.into()),
+            GameAction::BuyIn(value) => Self::buy_in_at(&mut lock_ledger(), game, player_id, value),
+            GameAction::CashOut => {
+                if !game.is_between_rounds() {
+                    return Err(CoordinatorError::RoundInProgress);
+                }
+                let client_event = game.action(action, player_id)?;
+                let money = client_event
+                    .iter()
+                    .map(|event| match event {
+                        ClientEvent::CashOut(_, money) => *money,
+                        _ => 0.0,
+                    })
+                    .sum();
+                Self::return_to_wallet(&mut lock_ledger(), player_id, money)?;
+                Ok(client_event)
+            }
+            _ => {
+                let bets_before: HashMap<PlayerID, ChipPile> = game
+                    .get_player_list()
+                    .iter()
+                    .map(|id| (*id, game.get_player_stake(*id).unwrap_or(0.0)))
+                    .collect();
+                let client_event = game.action(action, player_id)?;
+                Self::record_bets(&mut lock_ledger(), game, &bets_before, &client_event)?;
+                Ok(client_event)
+            }
+        }
+    }
+
+    // Takes a player off a single table and sends their stack back to their wallet. A refunded
+    // bet comes back from the house first, and a round their leaving settled is recorded like
+    // any other.
+    pub(crate) fn leave_table<L, F>(
+        lock_ledger: F,
+        game: &mut GameState,
+        player_id: PlayerID,
+    ) -> Result<Vec<ClientEvent>, CoordinatorError>
+    where
+        L: DerefMut<Target = Ledger>,
+        F: FnOnce() -> L,
+    {
+        let bets_before: HashMap<PlayerID, ChipPile> = game
+            .get_player_list()
+            .iter()
+            .filter(|id| **id != player_id)
+            .map(|id| (*id, game.get_player_stake(*id).unwrap_or(0.0)))
+            .collect();
+        let departure = game.remove_user(player_id)?;
+        let mut ledger = lock_ledger();
+        if let Some(settlement) = departure.settlement {
+            if settlement.payout > 0.0 {
+                ledger.transfer(
+                    EntryKind::Refund,
+                    Account::House,
+                    Account::Table(player_id),
+                    settlement.payout,
+                )?;
+            }
+        }
+        Self::return_to_wallet(&mut ledger, player_id, departure.money)?;
+        Self::record_bets(&mut ledger, game, &bets_before, &departure.events)?;
+        Ok(departure.events)
+    }
+
+    pub fn buy_in(
+        &mut self,
+        session: &Session,
+        amount: ChipPile,
+    ) -> Result<Vec<TableEvent>, CoordinatorError> {
+        self.handle_action(session, GameAction::BuyIn(amount))
+    }
+
+    pub fn cash_out(&mut self, session: &Session) -> Result<Vec<TableEvent>, CoordinatorError> {
+        self.handle_action(session, GameAction::CashOut)
+    }
+
+    pub(crate) fn buy_in_at(
+        ledger: &mut Ledger,
+        game: &mut GameState,
+        player_id: PlayerID,
+        amount: ChipPile,
+    ) -> Result<Vec<ClientEvent>, CoordinatorError> {
+        if !game.is_between_rounds() {
+            return Err(CoordinatorError::RoundInProgress);
+        }
+        if !game.can_buy_in(player_id, amount) {
+            return Err(CoordinatorError::OutsideBuyInLimits(
+                game.get_buy_in_limits(),
+            ));
+        }
+        if ledger.balance(Account::Wallet(player_id)) < amount {
+            return Err(LedgerError::InsufficientFunds.into());
+        }
+        let client_event = game.action(GameAction::BuyIn(amount), player_id)?;
+        ledger.transfer(
+            EntryKind::BuyIn,
+            Account::Wallet(player_id),
+            Account::Table(player_id),
+            amount,
+        )?;
         Ok(client_event)
     }
 
-    pub fn get_other_events(&mut self) -> HashMap<PlayerID, Vec<ClientEvent>> {
-        let client_event = self.events_to_send.clone();
-        self.events_to_send.clear();
-        client_event
+    pub(crate) fn return_to_wallet(
+        ledger: &mut Ledger,
+        player_id: PlayerID,
+        money: ChipPile,
+    ) -> Result<(), CoordinatorError> {
+        if money > 0.0 {
+            ledger.transfer(
+                EntryKind::CashOut,
+                Account::Table(player_id),
+                Account::Wallet(player_id),
+                money,
+            )?;
+        }
+        Ok(())
+    }
+
+    // Stakes move from the table to the house when placed and winnings come back when the round
+    // settles, a hand at a time for a player who split
+    pub(crate) fn record_bets(
+        ledger: &mut Ledger,
+        game: &GameState,
+        bets_before: &HashMap<PlayerID, ChipPile>,
+        client_event: &[ClientEvent],
+    ) -> Result<(), CoordinatorError> {
+        let settled = client_event
+            .iter()
+            .any(|event| matches!(event, ClientEvent::RoundOver));
+        // Seat order keeps the ledger the same when a journal is replayed
+        let seat = |id: &PlayerID| {
+            let index = game
+                .get_player_list()
+                .iter()
+                .position(|player| player == id);
+            (index.unwrap_or(usize::MAX), *id)
+        };
+        let mut bets_before: Vec<(&PlayerID, &ChipPile)> = bets_before.iter().collect();
+        bets_before.sort_by_key(|(id, _)| seat(id));
+        for (id, bet_before) in bets_before {
+            let settlements: Vec<&Settlement> = game
+                .get_last_settlement()
+                .iter()
+                .filter(|settlement| settled && settlement.player == *id)
+                .collect();
+            let staked = if settlements.is_empty() {
+                game.get_player_stake(*id).unwrap_or(0.0)
+            } else {
+                settlements.iter().map(|settlement| settlement.stake).sum()
+            } - bet_before;
+            if staked > 0.0 {
+                ledger.transfer(EntryKind::Bet, Account::Table(*id), Account::House, staked)?;
+            }
+            for settlement in settlements {
+                if settlement.payout > 0.0 {
+                    ledger.transfer(
+                        EntryKind::Payout,
+                        Account::House,
+                        Account::Table(*id),
+                        settlement.payout,
+                    )?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn deposit(
+        &mut self,
+        player_id: PlayerID,
+        amount: ChipPile,
+    ) -> Result<(), CoordinatorError> {
+        if !self.is_known_player(player_id) {
+            return Err(CoordinatorError::PlayerNotFound);
+        }
+        self.ledger.transfer(
+            EntryKind::Deposit,
+            Account::Cashier,
+            Account::Wallet(player_id),
+            amount,
+        )?;
+        Ok(())
+    }
+
+    // Positive amounts credit the wallet from the house, negative amounts return money to the house
+    pub fn adjust(
+        &mut self,
+        player_id: PlayerID,
+        amount: ChipPile,
+    ) -> Result<(), CoordinatorError> {
+        if !self.is_known_player(player_id) {
+            return Err(CoordinatorError::PlayerNotFound);
+        }
+        if amount < 0.0 {
+            self.ledger.transfer(
+                EntryKind::Adjustment,
+                Account::Wallet(player_id),
+                Account::House,
+                -amount,
+            )?;
+        } else {
+            self.ledger.transfer(
+                EntryKind::Adjustment,
+                Account::House,
+                Account::Wallet(player_id),
+                amount,
+            )?;
+        }
+        Ok(())
+    }
+
+    fn is_known_player(&self, player_id: PlayerID) -> bool {
+        self.available_players.contains(&player_id)
+            || self.player_tables.contains_key(&player_id)
+            || self.ledger.get_wallets().contains_key(&player_id)
+    }
+
+    pub fn get_wallet_balance(&self, player_id: PlayerID) -> ChipPile {
+        self.ledger.balance(Account::Wallet(player_id))
+    }
+
+    pub fn get_ledger(&self) -> &Ledger {
+        &self.ledger
+    }
+
+    pub fn get_ledger_entries(&self, player_id: PlayerID) -> Vec<&LedgerEntry> {
+        self.ledger.entries_for(player_id)
+    }
+
+    // Hands out every event not handed out before. They stay queued until acknowledged with
+    // ack_events, so a client that missed some can catch up through replay_events.
+    pub fn get_other_events(&mut self) -> HashMap<PlayerID, Vec<SequencedEvent>> {
+        self.events_to_send
+            .iter_mut()
+            .map(|(player_id, queue)| (*player_id, queue.take_undelivered()))
+            .filter(|(_, events)| !events.is_empty())
+            .collect()
+    }
+
+    pub fn ack_events(&mut self, session: &Session, seq: u64) -> Result<(), CoordinatorError> {
+        Self::check_session(session, self.now())?;
+        if let Some(queue) = self.events_to_send.get_mut(&session.get_player()) {
+            queue.ack(seq);
+        }
+        Ok(())
+    }
+
+    pub fn replay_events(
+        &self,
+        session: &Session,
+        last_seen: u64,
+    ) -> Result<Replay, CoordinatorError> {
+        Self::check_session(session, self.now())?;
+        Ok(self
+            .events_to_send
+            .get(&session.get_player())
+            .map(|queue| queue.replay(last_seen))
+            .unwrap_or_else(|| Replay::Events(Vec::new())))
     }
 
     pub fn get_available_players(&self) -> &Vec<PlayerID> {
         &self.available_players
     }
 
-    pub fn get_current_games(&self) -> &Vec<GameState> {
+    fn lookup_player_game<'a>(
+        player_tables: &HashMap<PlayerID, GameID>,
+        current_games: &'a mut HashMap<GameID, GameState>,
+        player_id: PlayerID,
+    ) -> Result<&'a mut GameState, CoordinatorError> {
+        player_tables
+            .get(&player_id)
+            .and_then(move |id| current_games.get_mut(id))
+            .ok_or(CoordinatorError::PlayerNotFound)
+    }
+
+    pub fn get_game(&self, id: GameID) -> Result<&GameState, CoordinatorError> {
+        self.current_games
+            .get(&id)
+            .ok_or(CoordinatorError::TableNotFound)
+    }
+
+    pub fn get_mut_game(&mut self, id: GameID) -> Result<&mut GameState, CoordinatorError> {
+        self.current_games
+            .get_mut(&id)
+            .ok_or(CoordinatorError::TableNotFound)
+    }
+
+    pub fn get_player_table(&self, player_id: PlayerID) -> Option<GameID> {
+        self.player_tables.get(&player_id).copied()
+    }
+
+    pub fn get_player_game(&self, player_id: PlayerID) -> Result<&GameState, CoordinatorError> {
+        self.player_tables
+            .get(&player_id)
+            .and_then(|id| self.current_games.get(id))
+            .ok_or(CoordinatorError::PlayerNotFound)
+    }
+
+    pub fn get_mut_player_game(
+        &mut self,
+        player_id: PlayerID,
+    ) -> Result<&mut GameState, CoordinatorError> {
+        Self::lookup_player_game(&self.player_tables, &mut self.current_games, player_id)
+    }
+
+    pub fn get_current_games(&self) -> &HashMap<GameID, GameState> {
         &self.current_games
     }
 
-    pub fn get_mut_current_games(&mut self) -> &mut Vec<GameState> {
+    // Seating changes made through this reference bypass the player index, use join_table instead
+    pub fn get_mut_current_games(&mut self) -> &mut HashMap<GameID, GameState> {
         &mut self.current_games
     }
 
-    pub fn get_last_player_input(&self) -> &HashMap<PlayerID, Time> {
+    // When each player last acted at their table
+    pub fn get_last_player_input(&self) -> &HashMap<PlayerID, OffsetDateTime> {
         &self.last_player_input
     }
 
     pub fn get_player_money(&self) -> &HashMap<PlayerID, ChipPile> {
-        &self.player_money
+        self.ledger.get_wallets()
+    }
+
+    pub fn get_table_config(&self) -> &TableConfig {
+        &self.table_config
+    }
+
+    pub fn get_countdown_started(&self) -> Option<OffsetDateTime> {
+        self.countdown_started
+    }
+
+    // The most recently closed tables, oldest first
+    pub fn get_archived_games(&self) -> &Vec<ArchivedTable> {
+        &self.archived_games
     }
 
-    pub fn get_events_to_send(&self) -> &HashMap<PlayerID, Vec<ClientEvent>> {
+    pub fn get_archive_limit(&self) -> usize {
+        self.archive_limit
+    }
+
+    pub fn set_archive_limit(&mut self, limit: usize) {
+        self.archive_limit = limit;
+        self.trim_archive();
+    }
+
+    fn trim_archive(&mut self) {
+        let over = self.archived_games.len().saturating_sub(self.archive_limit);
+        self.archived_games.drain(..over);
+    }
+
+    pub fn get_spectators(&self, id: GameID) -> Vec<PlayerID> {
+        self.spectators.get(&id).cloned().unwrap_or_default()
+    }
+
+    pub fn get_disconnected(&self) -> &HashMap<PlayerID, OffsetDateTime> {
+        &self.disconnected
+    }
+
+    pub fn get_events_to_send(&self) -> &HashMap<PlayerID, EventQueue> {
         &self.events_to_send
     }
+
+    pub fn get_event_queue_limit(&self) -> usize {
+        self.event_queue_limit
+    }
+
+    pub fn set_event_queue_limit(&mut self, limit: usize) {
+        self.event_queue_limit = limit;
+    }
 }
@@ -1,16 +1,28 @@
 #![allow(dead_code)]
-use crate::card::Card;
+use crate::analysis::{Analyzer, HandAnalysis, Shoe};
+use crate::basicstrategy::{recommend, DoubleRule, Play, StrategyRules};
+use crate::card::{Card, Deck};
+use crate::dealer::{DealerPolicy, HandValue};
+use crate::observer::{GameObserver, Observers, Veto};
+use crate::profile::PublicProfile;
+use crate::strategy::PlayerView;
+use crate::tableconfig::{BuyInLimits, DealerRule, DeparturePolicy, TableConfig};
+use crate::trainer::{self, Grade, TrainingStats};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::option::Option;
+use std::sync::{Arc, OnceLock};
+use time::OffsetDateTime;
 use uuid::Uuid;
 
 pub type ChipPile = f32;
 pub type Hand = Vec<Card>;
 pub type PlayerID = Uuid;
+pub type GameID = u64;
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct GameState {
+    id: GameID,
     current_player: Option<PlayerID>,
     player_list: Vec<PlayerID>,
     player_hand: HashMap<PlayerID, Hand>,
@@ -19,21 +31,53 @@ pub struct GameState {
     player_round_over: Vec<PlayerID>,
     dealer_hand: Hand,
     deck: Vec<Card>,
+    shoe_seed: u64,
+    last_settlement: Vec<Settlement>,
+    config: TableConfig,
+    // The seq of the last event stamped at this table
+    #[serde(default)]
+    event_seq: u64,
+    // Players who opted in to training, with how they have done so far
+    #[serde(default)]
+    training: HashMap<PlayerID, TrainingStats>,
+    // The other hand of every player who split their pair this round
+    #[serde(default)]
+    split_hands: HashMap<PlayerID, SplitHand>,
+    #[serde(skip)]
+    observers: Observers,
+    // Plays the dealer instead of the config's dealer_rule. Code like the observers, so it is not
+    // saved with the table and has to be given again to a table that was loaded.
+    #[serde(skip)]
+    dealer_policy: Option<Arc<dyn DealerPolicy>>,
+    // Made on the first analysis and kept for its cache until the dealer's play changes
+    #[serde(skip)]
+    analyzer: OnceLock<Analyzer>,
 }
 
-#[derive(Debug, Deserialize, Serialize, Copy, Clone)]
+#[derive(Debug, Deserialize, Serialize, Copy, Clone, PartialEq)]
 pub enum GameAction {
     Hit,
     Stand,
     Double,
+    Split,
     AddMoney(ChipPile),
     StartingBet(ChipPile),
+    BuyIn(ChipPile),
+    CashOut,
 }
 
 #[derive(Debug, PartialEq)]
 pub enum GameError {
     MissingPlayerID,
     InvaildAction,
+    // An observer refused the action, nothing at the table changed
+    Vetoed(Veto),
+}
+
+impl From<Veto> for GameError {
+    fn from(veto: Veto) -> Self {
+        GameError::Vetoed(veto)
+    }
 }
 
 #[derive(Debug, Deserialize, Serialize, PartialEq, Clone)]
@@ -42,17 +86,103 @@ pub enum FromPlayer {
     Player(PlayerID),
 }
 
+// What a player staked in the last finished round and what was paid back to them
+#[derive(Debug, Deserialize, Serialize, PartialEq, Clone, Copy)]
+pub struct Settlement {
+    pub player: PlayerID,
+    pub stake: ChipPile,
+    pub payout: ChipPile,
+}
+
+// The second hand of a player who split a pair, with its own bet. It waits while the first hand
+// is played and then the two swap places, so the player's hand and bet are always the ones in
+// play. finished tells that this is the first hand again, played out already.
+#[derive(Debug, Deserialize, Serialize, PartialEq, Clone)]
+pub struct SplitHand {
+    pub hand: Hand,
+    pub bet: ChipPile,
+    pub finished: bool,
+}
+
+// What a player takes with them when they leave a table. settlement holds what they had riding
+// on the round, both hands' bets if they split, events are whatever their leaving set off at
+// the table.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Departure {
+    pub money: ChipPile,
+    pub settlement: Option<Settlement>,
+    pub events: Vec<ClientEvent>,
+}
+
+// Everything that happens at a table. GameState::action emits them in this order:
+// - A bet is Betting and BalanceChanged. The last bet starts the round with RoundStarted, then
+//   the cards go out one at a time in seat order with the dealer last, each as CardRevealed and
+//   HandTotal apart from the dealer's face down hole card. Every natural follows as
+//   PlayerBlackjack and PlayerRoundOver, in seat order.
+// - A hit is CardRevealed and HandTotal, then PlayerBusted and PlayerRoundOver if it went over 21
+// - A stand is PlayerRoundOver
+// - A double is BalanceChanged for the doubled stake, then a hit and a stand
+// - A split is BalanceChanged for the second hand's stake and HandSplit, then a hit on the first
+//   hand. Once the first hand is finished SplitHandStarted comes instead of PlayerRoundOver and
+//   the second hand is hit. Split aces take that one card each and are finished.
+// - Once the cards are out and after every finished hand, TurnChanged names who acts next. When
+//   nobody is left the dealer plays instead: HoleCardRevealed and HandTotal, then CardRevealed
+//   and HandTotal for every card drawn. Every hand is settled in seat order with Payout, each
+//   player's BalanceChanged follows the payout of their last hand, and RoundOver comes last.
+// - ShoeShuffled comes right before the card that needed a fresh shoe
+// - Leaving is PlayerLeft, followed by anything the departure set off
+// - For a player in training every hit, stand, double and split starts with DecisionGraded
 #[derive(Debug, Deserialize, Serialize, PartialEq, Clone)]
 pub enum ClientEvent {
-    PlayerRoundOver,
+    // The player's hand is finished for this round
+    PlayerRoundOver(PlayerID),
     RoundOver,
     CardRevealed(FromPlayer, Card),
     Betting(PlayerID, ChipPile),
+    BuyIn(PlayerID, ChipPile),
+    CashOut(PlayerID, ChipPile),
+    TableClosed(GameID),
+    // Someone sat down at the table, including the players a new table starts with
+    PlayerJoined(PublicProfile),
+    PlayerLeft(PlayerID),
+    TurnChanged(PlayerID),
+    RoundStarted,
+    // The dealer's face down card, turned over once every player is done
+    HoleCardRevealed(Card),
+    // The total of a hand after a card was added to it
+    HandTotal(FromPlayer, i8),
+    PlayerBusted(PlayerID),
+    PlayerBlackjack(PlayerID),
+    // What a bet paid back, 0 when it lost
+    Payout(PlayerID, ChipPile),
+    // The player's stack at the table after it changed
+    BalanceChanged(PlayerID, ChipPile),
+    ShoeShuffled,
+    // How a decision of a player in training compared to basic strategy
+    DecisionGraded(Grade),
+    // The player split their pair, the hand in play is now the first of the two
+    HandSplit(PlayerID),
+    // The player's first split hand is finished and their second is in play
+    SplitHandStarted(PlayerID),
+}
+
+// An event as it left the table. seq counts every event the table has emitted, so events from
+// one table can always be put back in order, and at is when it happened in UTC.
+#[derive(Debug, Deserialize, Serialize, PartialEq, Clone)]
+pub struct TableEvent {
+    pub seq: u64,
+    pub at: OffsetDateTime,
+    pub event: ClientEvent,
 }
 
 impl GameState {
     pub fn new(users: Vec<PlayerID>) -> Self {
-        Self {
+        Self::with_config(0, users, TableConfig::default())
+    }
+
+    pub fn with_config(id: GameID, users: Vec<PlayerID>, config: TableConfig) -> Self {
+        let mut game = Self {
+            id,
             current_player: None,
             player_list: users.to_vec(),
             player_hand: HashMap::new(),
@@ -61,7 +191,18 @@ impl GameState {
             dealer_hand: Vec::new(),
             player_round_over: Vec::new(),
             deck: Vec::new(),
-        }
+            shoe_seed: id,
+            last_settlement: Vec::new(),
+            config,
+            event_seq: 0,
+            training: HashMap::new(),
+            split_hands: HashMap::new(),
+            observers: Observers::default(),
+            dealer_policy: None,
+            analyzer: OnceLock::new(),
+        };
+        game.create_users_hand();
+        game
     }
 
     pub fn create_users_hand(&mut self) {
@@ -71,31 +212,291 @@ impl GameState {
         }
     }
 
-    pub fn remove_user(&mut self, player: PlayerID) -> PlayerID {
+    // New players can only sit down between rounds while there is an open seat
+    pub fn add_user(&mut self, player: PlayerID) -> Result<(), GameError> {
+        if !self.has_open_seat() || self.player_list.contains(&player) {
+            return Err(GameError::InvaildAction);
+        }
+        self.player_list.push(player);
+        self.player_hand.insert(player, Vec::new());
+        self.player_money.insert(player, 0.0);
+        Ok(())
+    }
+
+    pub fn has_open_seat(&self) -> bool {
+        self.is_between_rounds() && self.player_list.len() < self.config.max_seats
+    }
+
+    // A player can leave at any point without holding up the table. Their bet is settled by the
+    // departure policy once the cards are dealt, and handed back before that. Their turn passes
+    // on and the dealer plays out if nobody is left to act. During betting the cards are dealt
+    // if everyone still seated has already bet.
+    pub fn remove_user(&mut self, player: PlayerID) -> Result<Departure, GameError> {
+        if !self.player_list.contains(&player) {
+            return Err(GameError::MissingPlayerID);
+        }
+        let dealt = self.is_dealt();
+        let split_bet = self
+            .split_hands
+            .remove(&player)
+            .map_or(0.0, |split| split.bet);
+        let settlement = self.player_bet.remove(&player).map(|bet| {
+            let stake = bet + split_bet;
+            let payout = match self.config.departure_policy {
+                DeparturePolicy::Refund => stake,
+                DeparturePolicy::Forfeit if !dealt => stake,
+                DeparturePolicy::Forfeit => 0.0,
+            };
+            Settlement {
+                player,
+                stake,
+                payout,
+            }
+        });
+        if let Some(settlement) = settlement {
+            *self.get_mut_player_money(player)? += settlement.payout;
+        }
+
+        let mut events: Vec<ClientEvent> = vec![ClientEvent::PlayerLeft(player)];
+        if dealt && self.current_player == Some(player) {
+            self.player_round_over.push(player);
+            events.extend(self.advance_turn()?);
+        }
+        let money = self.get_player_money(player)?;
         self.player_list.retain(|&x| x != player);
         self.player_hand.retain(|x, _| *x != player);
         self.player_money.retain(|x, _| *x != player);
-        self.player_bet.retain(|x, _| *x != player);
         self.player_round_over.retain(|&x| x != player);
+        self.training.remove(&player);
+        if self.current_player == Some(player) {
+            self.current_player = None;
+        }
+        if !dealt && !self.is_between_rounds() && self.player_list.len() == self.player_bet.len() {
+            events.extend(self.deal()?);
+        }
+        self.observers.on_settlement(self, settlement.as_slice());
+        self.observers.on_events(self, &events);
+
+        Ok(Departure {
+            money,
+            settlement,
+            events,
+        })
+    }
+
+    // Money can only move on or off the table while no bets are in play
+    pub fn is_between_rounds(&self) -> bool {
+        self.player_bet.is_empty()
+    }
+
+    // The cards are out and the seated players are taking their turns
+    pub fn is_dealt(&self) -> bool {
+        !self.is_between_rounds() && self.current_player.is_some()
+    }
+
+    // The player's hand is the one in play. current_player still names the last to act once the
+    // round is settled, so it only counts while their bet is out.
+    fn is_turn(&self, player: PlayerID) -> bool {
+        self.is_dealt()
+            && self.current_player == Some(player)
+            && self.player_bet.contains_key(&player)
+    }
+
+    // A first two card 9, 10 or 11 can be doubled while the player can match their bet, but not
+    // once they split
+    fn can_double(&self, player: PlayerID) -> bool {
+        match (self.get_player_hand(player), self.get_player_bet(player)) {
+            (Ok(hand), Ok(bet)) => {
+                hand.len() == 2
+                    && (9..=11).contains(&HandValue::of(hand).total)
+                    && !self.split_hands.contains_key(&player)
+                    && self
+                        .get_player_money(player)
+                        .is_ok_and(|money| bet <= money)
+            }
+            _ => false,
+        }
+    }
+
+    // A first two card pair of the same value can be split once while the player can match their
+    // bet, there is no resplitting
+    fn can_split(&self, player: PlayerID) -> bool {
+        match (self.get_player_hand(player), self.get_player_bet(player)) {
+            (Ok(hand), Ok(bet)) => {
+                hand.len() == 2
+                    && hand[0].get_value() == hand[1].get_value()
+                    && !self.split_hands.contains_key(&player)
+                    && self
+                        .get_player_money(player)
+                        .is_ok_and(|money| bet <= money)
+            }
+            _ => false,
+        }
+    }
+
+    // Everything the player may do in the round right now. A bet is listed at the table minimum,
+    // any amount the table and the player's stack allow is accepted as well.
+    pub fn legal_actions(&self, player: PlayerID) -> Vec<GameAction> {
+        let money = match self.get_player_money(player) {
+            Ok(money) => money,
+            Err(_) => return Vec::new(),
+        };
+        if self.is_dealt() {
+            if self.current_player != Some(player) || self.player_round_over.contains(&player) {
+                return Vec::new();
+            }
+            let mut actions = vec![GameAction::Hit, GameAction::Stand];
+            if self.can_double(player) {
+                actions.push(GameAction::Double);
+            }
+            if self.can_split(player) {
+                actions.push(GameAction::Split);
+            }
+            actions
+        } else if !self.player_bet.contains_key(&player) && money >= self.config.min_bet {
+            vec![GameAction::StartingBet(self.config.min_bet)]
+        } else {
+            Vec::new()
+        }
+    }
+
+    // The table as the player sees it, the dealer's hole card stays hidden
+    pub fn get_player_view(&self, player: PlayerID) -> Result<PlayerView, GameError> {
+        let hand = self.get_player_hand(player)?.clone();
+        Ok(PlayerView {
+            player,
+            value: HandValue::of(&hand),
+            hand,
+            bet: self.player_bet.get(&player).copied(),
+            dealer_up_card: self.dealer_hand.first().copied(),
+            rules: self.config.clone(),
+        })
+    }
+
+    // The basic strategy play for the player's hand against the dealer's up card, only while it
+    // is their move. A double or split is only recommended when the player can make it.
+    pub fn hint(&self, player: PlayerID) -> Result<Play, GameError> {
+        let (up_card, rules) = self.advice_for(player)?;
+        Ok(recommend(self.get_player_hand(player)?, up_card, &rules))
+    }
 
-        player
+    // The expected value of every play the player could make, worked out exactly from the cards
+    // not seen yet, the dealer's hole card among them. Only while it is their move.
+    pub fn get_hand_analysis(&self, player: PlayerID) -> Result<HandAnalysis, GameError> {
+        let (up_card, _) = self.advice_for(player)?;
+        let unseen = Shoe::of(self.deck.iter().chain(self.dealer_hand.iter().skip(1)));
+        let analyzer = self.analyzer.get_or_init(|| match &self.dealer_policy {
+            Some(policy) => Analyzer::with_policy(self.config.clone(), policy.clone()),
+            None => Analyzer::new(self.config.clone()),
+        });
+        // Only the plays the player could make now, the table offers no surrender
+        let legal = self.legal_actions(player);
+        Ok(analyzer
+            .analyze(self.get_player_hand(player)?, up_card, &unseen)
+            .offered(|play| {
+                play.to_action()
+                    .is_some_and(|action| legal.contains(&action))
+            }))
+    }
+
+    // The dealer's up card and the rules basic strategy is worked out under for the player
+    fn advice_for(&self, player: PlayerID) -> Result<(Card, StrategyRules), GameError> {
+        if !self.legal_actions(player).contains(&GameAction::Hit) {
+            return Err(GameError::InvaildAction);
+        }
+        let up_card = *self.dealer_hand.first().ok_or(GameError::InvaildAction)?;
+        let mut rules = StrategyRules::for_table(&self.config);
+        if !self.can_double(player) {
+            rules.double = DoubleRule::Never;
+        }
+        rules.split = self.can_split(player);
+        Ok((up_card, rules))
+    }
+
+    // Training grades every hit, stand, double and split the player makes against basic strategy with
+    // a DecisionGraded event and keeps their stats until they stop or leave the table
+    pub fn set_training(&mut self, player: PlayerID, training: bool) -> Result<(), GameError> {
+        if !self.player_list.contains(&player) {
+            return Err(GameError::MissingPlayerID);
+        }
+        if !training {
+            self.training.remove(&player);
+        } else {
+            self.training.entry(player).or_default();
+        }
+        Ok(())
+    }
+
+    pub fn get_training_stats(&self, player: PlayerID) -> Option<&TrainingStats> {
+        self.training.get(&player)
+    }
+
+    // The grade for the action when the player is in training and it is a decision on their hand
+    fn grade_action(&self, action: GameAction, player: PlayerID) -> Option<Grade> {
+        if !self.training.contains_key(&player) {
+            return None;
+        }
+        let taken = match action {
+            GameAction::Hit => Play::Hit,
+            GameAction::Stand => Play::Stand,
+            GameAction::Double => Play::Double,
+            GameAction::Split => Play::Split,
+            _ => return None,
+        };
+        let (up_card, rules) = self.advice_for(player).ok()?;
+        Some(trainer::grade(
+            player,
+            self.get_player_hand(player).ok()?,
+            up_card,
+            &rules,
+            taken,
+            self.get_player_bet(player).ok()?,
+            || self.get_hand_analysis(player).ok(),
+        ))
+    }
+
+    pub fn can_buy_in(&self, player: PlayerID, value: ChipPile) -> bool {
+        self.is_between_rounds()
+            && value >= self.config.buy_in_limits.min
+            && self
+                .get_player_money(player)
+                .map(|money| money + value <= self.config.buy_in_limits.max)
+                .unwrap_or(false)
+    }
+
+    // Clears the hands left over from the previous round before the first new bet is placed
+    fn reset_round(&mut self) {
+        if self.is_between_rounds() && !self.dealer_hand.is_empty() {
+            for hand in self.player_hand.values_mut() {
+                hand.clear();
+            }
+            self.dealer_hand.clear();
+            self.split_hands.clear();
+            self.player_round_over.clear();
+            self.current_player = None;
+        }
     }
 
     pub fn start_game(&mut self) -> Result<Vec<ClientEvent>, GameError> {
         let mut events: Vec<ClientEvent> = Vec::new();
-        if self.current_player == None {
+        if self.current_player.is_none() {
+            events.push(ClientEvent::RoundStarted);
             // Deals cards in a staggered way
             while self.dealer_hand.len() < 2 {
                 for player in self.player_list.clone() {
                     self.current_player = Some(player);
-                    events.extend(self.action(GameAction::Hit, player)?);
+                    events.extend(self.play(GameAction::Hit, player)?);
                 }
-                self.dealer_draw();
+                events.extend(self.dealer_draw());
                 if self.dealer_hand.len() == 1 {
                     events.push(ClientEvent::CardRevealed(
                         FromPlayer::Dealer,
                         self.dealer_hand[0],
                     ));
+                    events.push(ClientEvent::HandTotal(
+                        FromPlayer::Dealer,
+                        self.sum_dealer(),
+                    ));
                 }
             }
             self.current_player = Some(self.player_list[0]);
@@ -103,12 +504,95 @@ impl GameState {
         Ok(events)
     }
 
+    // Deals once every seated player has bet, the turn goes to the first hand that can act
+    fn deal(&mut self) -> Result<Vec<ClientEvent>, GameError> {
+        let mut events = self.start_game()?;
+        events.extend(self.check_natural_blackjack()?);
+        events.extend(self.advance_turn()?);
+        Ok(events)
+    }
+
+    // Passes the turn on while the current hand is finished, once there is no hand left to act
+    // the dealer plays and the bets are settled
+    fn advance_turn(&mut self) -> Result<Vec<ClientEvent>, GameError> {
+        let mut events: Vec<ClientEvent> = Vec::new();
+        while self
+            .player_round_over
+            .contains(&self.get_result_current_player()?)
+        {
+            let next_player = self
+                .next_current_player(self.get_result_current_player()?)
+                .ok();
+            if next_player.is_none() {
+                events.extend(self.dealer_draw_final()?);
+                self.compare_hands().ok();
+                self.return_bet().ok();
+                let settlements = self.last_settlement.clone();
+                for (index, settlement) in settlements.iter().enumerate() {
+                    events.push(ClientEvent::Payout(settlement.player, settlement.payout));
+                    // A player who split has both hands paid before their stack is shown
+                    if settlements
+                        .get(index + 1)
+                        .is_none_or(|next| next.player != settlement.player)
+                    {
+                        events.push(ClientEvent::BalanceChanged(
+                            settlement.player,
+                            self.get_player_money(settlement.player)?,
+                        ));
+                    }
+                }
+                events.push(ClientEvent::RoundOver);
+                return Ok(events);
+            }
+        }
+        events.push(ClientEvent::TurnChanged(self.get_result_current_player()?));
+        Ok(events)
+    }
+
+    // Ends the player's turn and passes it on. A player who split moves on to their second hand
+    // instead, which is dealt its second card at once.
+    fn finish_hand(&mut self, player: PlayerID) -> Result<Vec<ClientEvent>, GameError> {
+        let waiting = self
+            .split_hands
+            .get(&player)
+            .filter(|split| !split.finished);
+        if let Some(waiting) = waiting.cloned() {
+            let hand = std::mem::replace(self.get_mut_player_hand(player)?, waiting.hand);
+            let bet = std::mem::replace(self.get_mut_player_bet(player)?, waiting.bet);
+            self.split_hands.insert(
+                player,
+                SplitHand {
+                    hand,
+                    bet,
+                    finished: true,
+                },
+            );
+            let mut events = vec![ClientEvent::SplitHandStarted(player)];
+            events.extend(self.hit_split_hand(player)?);
+            return Ok(events);
+        }
+        self.player_round_over.push(player);
+        let mut events = vec![ClientEvent::PlayerRoundOver(player)];
+        events.extend(self.advance_turn()?);
+        Ok(events)
+    }
+
+    // Deals a hand split off a pair its second card, split aces take that card and are done
+    fn hit_split_hand(&mut self, player: PlayerID) -> Result<Vec<ClientEvent>, GameError> {
+        let mut events = self.play(GameAction::Hit, player)?;
+        if self.get_player_hand(player)?[0].is_ace() {
+            events.extend(self.finish_hand(player)?);
+        }
+        Ok(events)
+    }
+
     pub fn check_natural_blackjack(&mut self) -> Result<Vec<ClientEvent>, GameError> {
         let mut events: Vec<ClientEvent> = Vec::new();
         for player in self.player_list.clone() {
             if self.sum_hand(player)? == 21 {
                 self.player_round_over.push(player);
-                events.push(ClientEvent::PlayerRoundOver);
+                events.push(ClientEvent::PlayerBlackjack(player));
+                events.push(ClientEvent::PlayerRoundOver(player));
             }
         }
         Ok(events)
@@ -118,7 +602,7 @@ impl GameState {
         let mut iter = self.player_list.iter();
         let _ = iter.by_ref().find(|&&id| player == id);
         self.current_player = Some(*iter.next().ok_or(GameError::MissingPlayerID)?);
-        Ok(self.get_result_current_player()?)
+        self.get_result_current_player()
     }
 
     pub fn get_current_player(&self) -> Option<PlayerID> {
@@ -137,6 +621,27 @@ impl GameState {
         &mut self.deck
     }
 
+    // Deals from the top of the deck, shuffling in a fresh shoe once it runs out
+    fn draw_card(&mut self, events: &mut Vec<ClientEvent>) -> Card {
+        if self.deck.is_empty() {
+            let mut shoe = Deck::new(self.config.decks);
+            shoe.shuffle(self.shoe_seed);
+            self.shoe_seed = self.shoe_seed.wrapping_add(1);
+            self.deck = shoe.into_cards();
+            events.push(ClientEvent::ShoeShuffled);
+        }
+        self.deck.remove(0)
+    }
+
+    pub fn get_shoe_seed(&self) -> u64 {
+        self.shoe_seed
+    }
+
+    // The seed the next shoe is shuffled with, each new shoe moves on to the following seed
+    pub fn set_shoe_seed(&mut self, seed: u64) {
+        self.shoe_seed = seed;
+    }
+
     pub fn get_player_list(&self) -> &Vec<PlayerID> {
         &self.player_list
     }
@@ -151,6 +656,20 @@ impl GameState {
             .ok_or(GameError::MissingPlayerID)
     }
 
+    // Every hand the player holds this round in the order they are played, two once they split
+    pub fn get_player_hands(&self, player: PlayerID) -> Result<Vec<&Hand>, GameError> {
+        let hand = self.get_player_hand(player)?;
+        Ok(match self.split_hands.get(&player) {
+            Some(split) if split.finished => vec![&split.hand, hand],
+            Some(split) => vec![hand, &split.hand],
+            None => vec![hand],
+        })
+    }
+
+    pub fn get_split_hand(&self, player: PlayerID) -> Option<&SplitHand> {
+        self.split_hands.get(&player)
+    }
+
     pub fn get_mut_player_hand(&mut self, player: PlayerID) -> Result<&mut Hand, GameError> {
         self.player_hand
             .get_mut(&player)
@@ -161,7 +680,7 @@ impl GameState {
         self.player_money
             .get(&player)
             .ok_or(GameError::MissingPlayerID)
-            .map(|money| *money)
+            .copied()
     }
 
     pub fn get_mut_player_money(&mut self, player: PlayerID) -> Result<&mut ChipPile, GameError> {
@@ -174,7 +693,13 @@ impl GameState {
         self.player_bet
             .get(&player)
             .ok_or(GameError::MissingPlayerID)
-            .map(|bet| *bet)
+            .copied()
+    }
+
+    // Everything the player has riding on the round, the bets of both hands once they split
+    pub fn get_player_stake(&self, player: PlayerID) -> Result<ChipPile, GameError> {
+        let split_bet = self.split_hands.get(&player).map_or(0.0, |split| split.bet);
+        Ok(self.get_player_bet(player)? + split_bet)
     }
 
     pub fn get_mut_player_bet(&mut self, player: PlayerID) -> Result<&mut ChipPile, GameError> {
@@ -183,138 +708,281 @@ impl GameState {
             .ok_or(GameError::MissingPlayerID)
     }
 
+    pub fn get_id(&self) -> GameID {
+        self.id
+    }
+
+    pub fn get_name(&self) -> &str {
+        &self.config.name
+    }
+
+    pub fn get_config(&self) -> &TableConfig {
+        &self.config
+    }
+
+    pub fn get_buy_in_limits(&self) -> BuyInLimits {
+        self.config.buy_in_limits
+    }
+
+    pub fn set_buy_in_limits(&mut self, limits: BuyInLimits) {
+        self.config.buy_in_limits = limits;
+    }
+
+    pub fn get_last_settlement(&self) -> &Vec<Settlement> {
+        &self.last_settlement
+    }
+
+    // Numbers the events in the order they are given, continuing from the last one stamped
+    pub fn stamp(&mut self, events: Vec<ClientEvent>, at: OffsetDateTime) -> Vec<TableEvent> {
+        events
+            .into_iter()
+            .map(|event| {
+                self.event_seq += 1;
+                TableEvent {
+                    seq: self.event_seq,
+                    at,
+                    event,
+                }
+            })
+            .collect()
+    }
+
+    pub fn get_event_seq(&self) -> u64 {
+        self.event_seq
+    }
+
     pub fn get_player_round_over(&self) -> &Vec<PlayerID> {
         &self.player_round_over
     }
 
     pub fn is_round_over(&mut self, event: Vec<ClientEvent>) -> bool {
-        event.iter().any(|x| match x {
-            ClientEvent::RoundOver => true,
-            _ => false,
-        })
+        event.iter().any(|x| matches!(x, ClientEvent::RoundOver))
     }
 
-    fn ace_conversion(&mut self, player: PlayerID, mut sum: i8) -> Result<i8, GameError> {
-        let ace_count = self
-            .get_player_hand(player)?
-            .iter()
-            .filter(|&n| n.get_value() == 11)
-            .count();
-        while sum > 21 && ace_count > 0 {
-            sum -= 10;
-        }
-        Ok(sum)
+    pub fn sum_hand(&mut self, player: PlayerID) -> Result<i8, GameError> {
+        Ok(HandValue::of(self.get_player_hand(player)?).total)
     }
 
-    pub fn sum_hand(&mut self, player: PlayerID) -> Result<i8, GameError> {
-        let mut sum: i8 = self
-            .get_player_hand(player)?
-            .iter()
-            .map(|c| c.get_value())
-            .sum();
+    pub fn sum_dealer(&mut self) -> i8 {
+        HandValue::of(&self.dealer_hand).total
+    }
 
-        if sum > 21 {
-            sum = self.ace_conversion(player, sum)?;
+    pub fn get_dealer_policy(&self) -> &dyn DealerPolicy {
+        match &self.dealer_policy {
+            Some(policy) => policy.as_ref(),
+            None => &self.config.dealer_rule,
         }
-
-        Ok(sum)
     }
 
-    pub fn sum_dealer(&mut self) -> i8 {
-        let sum: i8 = self.dealer_hand.iter().map(|c| c.get_value()).sum();
+    // Changes how the dealer plays from the next round, so a round is always played under the
+    // rules its bets were placed on. The rule is part of the config, so it is saved with the
+    // table and shown in its rules_summary, and it takes over from any policy set before.
+    pub fn set_dealer_rule(&mut self, dealer_rule: DealerRule) -> Result<(), GameError> {
+        let config = TableConfig {
+            dealer_rule,
+            ..self.config.clone()
+        };
+        if !config.is_valid() || !self.is_between_rounds() {
+            return Err(GameError::InvaildAction);
+        }
+        self.config = config;
+        self.dealer_policy = None;
+        self.analyzer = OnceLock::new();
+        Ok(())
+    }
 
-        sum
+    // Plays the dealer by a policy the config's dealer_rule cannot describe, such as a house
+    // experiment, from the next round
+    pub fn set_dealer_policy(&mut self, policy: Arc<dyn DealerPolicy>) -> Result<(), GameError> {
+        if !self.is_between_rounds() {
+            return Err(GameError::InvaildAction);
+        }
+        self.dealer_policy = Some(policy);
+        self.analyzer = OnceLock::new();
+        Ok(())
     }
 
-    pub fn dealer_draw(&mut self) {
-        let new_card = self.deck.remove(0);
+    // Adds a card to the dealer's hand without revealing it
+    pub fn dealer_draw(&mut self) -> Vec<ClientEvent> {
+        let mut events: Vec<ClientEvent> = Vec::new();
+        let new_card = self.draw_card(&mut events);
         self.dealer_hand.push(new_card);
+        events
     }
 
     pub fn dealer_draw_final(&mut self) -> Result<Vec<ClientEvent>, GameError> {
         let mut events: Vec<ClientEvent> = Vec::new();
-        events.push(ClientEvent::CardRevealed(
+        events.push(ClientEvent::HoleCardRevealed(self.dealer_hand[1]));
+        events.push(ClientEvent::HandTotal(
             FromPlayer::Dealer,
-            self.dealer_hand[1],
+            self.sum_dealer(),
         ));
-        while self.sum_dealer() < 17 {
-            let new_card = self.deck.remove(0);
+        // Whatever the policy says, a dealer on 21 or more is done
+        while self.sum_dealer() < 21 && self.get_dealer_policy().should_hit(&self.dealer_hand) {
+            let new_card = self.draw_card(&mut events);
             self.dealer_hand.push(new_card);
             events.push(ClientEvent::CardRevealed(FromPlayer::Dealer, new_card));
+            events.push(ClientEvent::HandTotal(
+                FromPlayer::Dealer,
+                self.sum_dealer(),
+            ));
         }
         Ok(events)
     }
 
+    // Every hand is settled on its own, the two hands of a player who split in the order they
+    // were played
     pub fn compare_hands(&mut self) -> Result<(), GameError> {
-        let dealer_sum = self.sum_dealer();
-
-        for key in self.player_bet.clone().keys() {
-            let player_sum = self.sum_hand(*key)?;
-            if dealer_sum <= 21 && player_sum < dealer_sum || player_sum > 21 {
-                *self.get_mut_player_bet(*key)? *= 0.0;
-            } else if self.sum_hand(*key)? == 21 && self.get_player_hand(*key)?.len() == 2 {
-                *self.get_mut_player_bet(*key)? *= 2.5;
-            } else {
-                *self.get_mut_player_bet(*key)? *= 2.0;
+        self.last_settlement.clear();
+
+        // Seat order, so the settlement reads the same every time
+        let bettors: Vec<PlayerID> = self
+            .player_list
+            .iter()
+            .filter(|id| self.player_bet.contains_key(id))
+            .copied()
+            .collect();
+        for key in &bettors {
+            let split = self.split_hands.get(key).cloned();
+            let stake = self.get_player_bet(*key)?;
+            let payout =
+                stake * self.payout_multiplier(self.get_player_hand(*key)?, split.is_some());
+            *self.get_mut_player_bet(*key)? = payout;
+            let settlement = Settlement {
+                player: *key,
+                stake,
+                payout,
+            };
+            match split {
+                Some(split) => {
+                    let split_payout = split.bet * self.payout_multiplier(&split.hand, true);
+                    let split_settlement = Settlement {
+                        player: *key,
+                        stake: split.bet,
+                        payout: split_payout,
+                    };
+                    if let Some(split) = self.split_hands.get_mut(key) {
+                        split.bet = split_payout;
+                    }
+                    if split.finished {
+                        self.last_settlement.push(split_settlement);
+                        self.last_settlement.push(settlement);
+                    } else {
+                        self.last_settlement.push(settlement);
+                        self.last_settlement.push(split_settlement);
+                    }
+                }
+                None => self.last_settlement.push(settlement),
             }
         }
         Ok(())
     }
 
+    // What a finished hand pays back per unit bet against the dealer's hand. A natural beats any
+    // other 21 and a tie hands the bet back, a split hand's 21 is never a natural.
+    fn payout_multiplier(&self, hand: &Hand, split: bool) -> ChipPile {
+        let dealer_sum = HandValue::of(&self.dealer_hand).total;
+        let dealer_natural = dealer_sum == 21 && self.dealer_hand.len() == 2;
+        let player_sum = HandValue::of(hand).total;
+        let natural = player_sum == 21 && hand.len() == 2 && !split;
+        if player_sum > 21 {
+            0.0
+        } else if natural && dealer_natural {
+            1.0
+        } else if natural {
+            2.5
+        } else if dealer_natural || dealer_sum <= 21 && player_sum < dealer_sum {
+            0.0
+        } else if player_sum == dealer_sum {
+            1.0
+        } else {
+            2.0
+        }
+    }
+
+    // Hands back what every bet came to, a split hand's bet goes with the bet of the hand in play
     pub fn return_bet(&mut self) -> Result<(), GameError> {
         let clone_bet = self.player_bet.clone();
 
-        for (key, val) in clone_bet.iter() {
-            *self.get_mut_player_money(*key)? += val;
+        for key in clone_bet.keys() {
+            let stake = self.get_player_stake(*key)?;
+            *self.get_mut_player_money(*key)? += stake;
         }
-        Ok(self.player_bet.clear())
+        self.player_bet.clear();
+        Ok(())
     }
 
+    // Hands every outstanding bet back untouched, used when a table closes mid-round
+    pub fn refund_bets(&mut self) -> Result<(), GameError> {
+        self.last_settlement = self
+            .player_bet
+            .keys()
+            .map(|player| {
+                let stake = self.get_player_stake(*player).unwrap_or(0.0);
+                Settlement {
+                    player: *player,
+                    stake,
+                    payout: stake,
+                }
+            })
+            .collect();
+        self.observers.on_settlement(self, &self.last_settlement);
+        self.return_bet()
+    }
+
+    pub fn add_observer(&mut self, observer: Arc<dyn GameObserver>) {
+        self.observers.push(observer);
+    }
+
+    // Tells the observers about events that reached the table from outside, such as a
+    // coordinator seating players or closing it
+    pub fn notify(&self, events: &[ClientEvent]) {
+        self.observers.on_events(self, events);
+    }
+
+    // Runs a player's action past the observers, any of whom can refuse it, and tells them
+    // what it set off
     pub fn action(
         &mut self,
-        event: GameAction,
+        action: GameAction,
         player: PlayerID,
     ) -> Result<Vec<ClientEvent>, GameError> {
+        self.observers.before_action(self, player, action)?;
+        let grade = self.grade_action(action, player);
+        let mut events = self.play(action, player)?;
+        // A double the table refused changed nothing, so there was no decision to grade
+        if let Some(grade) = grade.filter(|_| !events.is_empty()) {
+            if let Some(stats) = self.training.get_mut(&player) {
+                stats.record(&grade);
+            }
+            events.insert(0, ClientEvent::DecisionGraded(grade));
+        }
+        self.observers.on_action(self, player, action, &events);
+        Ok(events)
+    }
+
+    // Dealing hits every hand through here as well, without it counting as the player's action
+    fn play(&mut self, event: GameAction, player: PlayerID) -> Result<Vec<ClientEvent>, GameError> {
         match event {
-            GameAction::Hit if self.current_player == Some(player) => {
+            GameAction::Hit if self.is_turn(player) => {
                 let mut events: Vec<ClientEvent> = Vec::new();
-                let new_card = self.deck.remove(0);
+                let new_card = self.draw_card(&mut events);
                 self.get_mut_player_hand(player)?.push(new_card);
                 events.push(ClientEvent::CardRevealed(
                     FromPlayer::Player(player),
                     new_card,
                 ));
-                if self.sum_hand(player)? > 21 {
-                    events.extend(self.action(GameAction::Stand, player)?);
-                }
-                Ok(events)
-            }
-            GameAction::Stand if self.current_player == Some(player) => {
-                // Make sure the next_current_player hand has playable actions, else cycle through player_list, if reaches end of list then end game
-                // Error checking for natural blackjacks
-                let mut events: Vec<ClientEvent> = Vec::new();
-                self.player_round_over.push(player);
-                events.push(ClientEvent::PlayerRoundOver);
-                while self
-                    .player_round_over
-                    .contains(&self.get_result_current_player()?)
-                {
-                    let next_player = self
-                        .next_current_player(self.get_result_current_player()?)
-                        .ok();
-                    if next_player == None {
-                        events.extend(self.dealer_draw_final()?);
-                        self.compare_hands().ok();
-                        self.return_bet().ok();
-                        events.push(ClientEvent::RoundOver);
-                        break;
-                    }
+                let total = self.sum_hand(player)?;
+                events.push(ClientEvent::HandTotal(FromPlayer::Player(player), total));
+                if total > 21 {
+                    events.push(ClientEvent::PlayerBusted(player));
+                    events.extend(self.finish_hand(player)?);
                 }
                 Ok(events)
             }
+            GameAction::Stand if self.is_turn(player) => self.finish_hand(player),
             GameAction::Double
-                if self.current_player == Some(player)
-                    && self.get_player_hand(player)?.len() == 2 =>
+                if self.is_turn(player) && self.get_player_hand(player)?.len() == 2 =>
             {
                 // First two cards equal to 9, 10, or 11
                 let mut events: Vec<ClientEvent> = Vec::new();
@@ -324,42 +992,77 @@ impl GameState {
                     if bet <= self.get_player_money(player)? {
                         *self.get_mut_player_money(player)? -= bet;
                         *self.get_mut_player_bet(player)? *= 2.0;
-                        events.extend(self.action(GameAction::Hit, player)?);
-                        events.extend(self.action(GameAction::Stand, player)?);
+                        events.push(ClientEvent::BalanceChanged(
+                            player,
+                            self.get_player_money(player)?,
+                        ));
+                        events.extend(self.play(GameAction::Hit, player)?);
+                        // A bust already finished the hand
+                        if !self.player_round_over.contains(&player) {
+                            events.extend(self.finish_hand(player)?);
+                        }
                     }
                 }
                 Ok(events)
             }
+            GameAction::Split if self.is_turn(player) && self.can_split(player) => {
+                let bet = self.get_player_bet(player)?;
+                *self.get_mut_player_money(player)? -= bet;
+                let hand = self.get_mut_player_hand(player)?.split_off(1);
+                self.split_hands.insert(
+                    player,
+                    SplitHand {
+                        hand,
+                        bet,
+                        finished: false,
+                    },
+                );
+                let mut events = vec![
+                    ClientEvent::BalanceChanged(player, self.get_player_money(player)?),
+                    ClientEvent::HandSplit(player),
+                ];
+                events.extend(self.hit_split_hand(player)?);
+                Ok(events)
+            }
             GameAction::AddMoney(value) if value > 0.0 => {
                 *self.get_mut_player_money(player)? += value;
-                Ok(vec![ClientEvent::Betting(player, value)])
+                Ok(vec![
+                    ClientEvent::Betting(player, value),
+                    ClientEvent::BalanceChanged(player, self.get_player_money(player)?),
+                ])
+            }
+            GameAction::BuyIn(value) if self.can_buy_in(player, value) => {
+                *self.get_mut_player_money(player)? += value;
+                Ok(vec![
+                    ClientEvent::BuyIn(player, value),
+                    ClientEvent::BalanceChanged(player, self.get_player_money(player)?),
+                ])
+            }
+            GameAction::CashOut if self.is_between_rounds() => {
+                let money = self.get_player_money(player)?;
+                *self.get_mut_player_money(player)? = 0.0;
+                Ok(vec![
+                    ClientEvent::CashOut(player, money),
+                    ClientEvent::BalanceChanged(player, 0.0),
+                ])
             }
             GameAction::StartingBet(bet)
-                if bet > 0.0 && bet <= self.get_player_money(player)? =>
+                if bet >= self.config.min_bet
+                    && bet <= self.config.max_bet
+                    && bet <= self.get_player_money(player)?
+                    && !self.player_bet.contains_key(&player) =>
             {
+                self.reset_round();
                 let mut events: Vec<ClientEvent> = Vec::new();
                 self.player_bet.insert(player, bet);
                 *self.get_mut_player_money(player)? -= bet;
                 events.push(ClientEvent::Betting(player, bet));
+                events.push(ClientEvent::BalanceChanged(
+                    player,
+                    self.get_player_money(player)?,
+                ));
                 if self.player_list.len() == self.player_bet.len() {
-                    events.extend(self.start_game()?);
-                    self.check_natural_blackjack().ok();
-                    // Make sure the next_current_player hand has playable actions, else cycle through player_list, if reaches end of list then end game
-                    while self
-                        .player_round_over
-                        .contains(&self.get_result_current_player()?)
-                    {
-                        let next_player = self
-                            .next_current_player(self.get_result_current_player()?)
-                            .ok();
-                        if next_player == None {
-                            events.extend(self.dealer_draw_final()?);
-                            self.compare_hands().ok();
-                            self.return_bet().ok();
-                            events.push(ClientEvent::RoundOver);
-                            break;
-                        }
-                    }
+                    events.extend(self.deal()?);
                 }
                 Ok(events)
             }
@@ -0,0 +1,182 @@
+use crate::gamestate::{ChipPile, PlayerID};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+// Money enters through the Cashier, is held in a player's Wallet, moves to their Table
+// stack to play and is staked against the House
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Account {
+    Cashier,
+    House,
+    Wallet(PlayerID),
+    Table(PlayerID),
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EntryKind {
+    Deposit,
+    BuyIn,
+    CashOut,
+    Bet,
+    Payout,
+    Refund,
+    Adjustment,
+}
+
+// A single double-entry record, the amount leaves one account and enters the other
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct LedgerEntry {
+    pub id: u64,
+    pub kind: EntryKind,
+    pub from: Account,
+    pub to: Account,
+    pub amount: ChipPile,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum LedgerError {
+    InvalidAmount,
+    InsufficientFunds,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct Ledger {
+    entries: Vec<LedgerEntry>,
+    cashier: ChipPile,
+    house: ChipPile,
+    wallets: HashMap<PlayerID, ChipPile>,
+    tables: HashMap<PlayerID, ChipPile>,
+}
+
+impl LedgerEntry {
+    pub fn involves(&self, player: PlayerID) -> bool {
+        [self.from, self.to].iter().any(|account| match account {
+            Account::Wallet(id) | Account::Table(id) => *id == player,
+            _ => false,
+        })
+    }
+}
+
+impl Ledger {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn balance(&self, account: Account) -> ChipPile {
+        match account {
+            Account::Cashier => self.cashier,
+            Account::House => self.house,
+            Account::Wallet(id) => *self.wallets.get(&id).unwrap_or(&0.0),
+            Account::Table(id) => *self.tables.get(&id).unwrap_or(&0.0),
+        }
+    }
+
+    fn balance_mut(&mut self, account: Account) -> &mut ChipPile {
+        match account {
+            Account::Cashier => &mut self.cashier,
+            Account::House => &mut self.house,
+            Account::Wallet(id) => self.wallets.entry(id).or_insert(0.0),
+            Account::Table(id) => self.tables.entry(id).or_insert(0.0),
+        }
+    }
+
+    // Player accounts can never go negative, the Cashier and House act as the outside world
+    pub fn transfer(
+        &mut self,
+        kind: EntryKind,
+        from: Account,
+        to: Account,
+        amount: ChipPile,
+    ) -> Result<&LedgerEntry, LedgerError> {
+        if amount <= 0.0 || !amount.is_finite() {
+            return Err(LedgerError::InvalidAmount);
+        }
+        if let Account::Wallet(_) | Account::Table(_) = from {
+            if self.balance(from) < amount {
+                return Err(LedgerError::InsufficientFunds);
+            }
+        }
+        *self.balance_mut(from) -= amount;
+        *self.balance_mut(to) += amount;
+        self.entries.push(LedgerEntry {
+            id: self.entries.len() as u64,
+            kind,
+            from,
+            to,
+            amount,
+        });
+        Ok(&self.entries[self.entries.len() - 1])
+    }
+
+    pub fn get_entries(&self) -> &Vec<LedgerEntry> {
+        &self.entries
+    }
+
+    pub fn entries_for(&self, player: PlayerID) -> Vec<&LedgerEntry> {
+        self.entries
+            .iter()
+            .filter(|entry| entry.involves(player))
+            .collect()
+    }
+
+    pub fn entries_of_kind(&self, kind: EntryKind) -> Vec<&LedgerEntry> {
+        self.entries
+            .iter()
+            .filter(|entry| entry.kind == kind)
+            .collect()
+    }
+
+    pub fn get_wallets(&self) -> &HashMap<PlayerID, ChipPile> {
+        &self.wallets
+    }
+
+    pub fn get_tables(&self) -> &HashMap<PlayerID, ChipPile> {
+        &self.tables
+    }
+
+    // Every entry moves money between two accounts, so all balances must sum to zero
+    pub fn total(&self) -> ChipPile {
+        self.cashier
+            + self.house
+            + self.wallets.values().sum::<ChipPile>()
+            + self.tables.values().sum::<ChipPile>()
+    }
+}
+
+#[test]
+fn ledger_transfers_balance() {
+    let player = PlayerID::new_v4();
+    let mut ledger = Ledger::new();
+    ledger
+        .transfer(
+            EntryKind::Deposit,
+            Account::Cashier,
+            Account::Wallet(player),
+            100.0,
+        )
+        .unwrap();
+    assert_eq!(
+        Err(LedgerError::InsufficientFunds),
+        ledger
+            .transfer(
+                EntryKind::BuyIn,
+                Account::Wallet(player),
+                Account::Table(player),
+                150.0,
+            )
+            .map(|entry| entry.id)
+    );
+    ledger
+        .transfer(
+            EntryKind::BuyIn,
+            Account::Wallet(player),
+            Account::Table(player),
+            60.0,
+        )
+        .unwrap();
+    assert_eq!(40.0, ledger.balance(Account::Wallet(player)));
+    assert_eq!(60.0, ledger.balance(Account::Table(player)));
+    assert_eq!(-100.0, ledger.balance(Account::Cashier));
+    assert_eq!(0.0, ledger.total());
+    assert_eq!(2, ledger.entries_for(player).len());
+}
@@ -1,3 +1,21 @@
+pub mod analysis;
+pub mod auth;
+pub mod basicstrategy;
 pub mod card;
+pub mod clock;
+pub mod concurrentcoordinator;
+pub mod dealer;
+pub mod eventqueue;
 pub mod gamecoordinator;
 pub mod gamestate;
+pub mod ledger;
+pub mod observer;
+pub mod profile;
+pub mod protocol;
+pub mod server;
+pub mod simulation;
+pub mod storage;
+pub mod strategy;
+pub mod tableconfig;
+pub mod terminal;
+pub mod trainer;
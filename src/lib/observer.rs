@@ -0,0 +1,333 @@
+use crate::gamestate::{
+    ChipPile, ClientEvent, GameAction, GameID, GameState, PlayerID, Settlement,
+};
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+// Why an observer refused an action, the player gets it back as GameError::Vetoed
+#[derive(Debug, Clone, PartialEq)]
+pub struct Veto(pub String);
+
+// Sees everything that happens at a table. Every call gets the table as it is right after what
+// it describes, apart from before_action which sees it right before. Observers are called in the
+// order they were added, on whichever thread runs the table, so they should not block.
+pub trait GameObserver: fmt::Debug + Send + Sync {
+    // Refuses a player's action before the table applies it, the first veto wins
+    fn before_action(
+        &self,
+        _game: &GameState,
+        _player: PlayerID,
+        _action: GameAction,
+    ) -> Result<(), Veto> {
+        Ok(())
+    }
+
+    // A player's action along with every event it set off, before on_event is called for them
+    fn on_action(
+        &self,
+        _game: &GameState,
+        _player: PlayerID,
+        _action: GameAction,
+        _events: &[ClientEvent],
+    ) {
+    }
+
+    // Every event the table emits, in order, including players joining and the table closing
+    fn on_event(&self, _game: &GameState, _event: &ClientEvent) {}
+
+    // Right after ShoeShuffled, the table already holds the fresh shoe
+    fn on_shuffle(&self, _game: &GameState) {}
+
+    // Bets paid out at RoundOver, settled for a player who left or refunded when a table closes
+    fn on_settlement(&self, _game: &GameState, _settlement: &[Settlement]) {}
+}
+
+// Observers are code rather than data, so they are not persisted and a restored table has none
+// until they are added again
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Observers(Vec<Arc<dyn GameObserver>>);
+
+impl Observers {
+    pub(crate) fn push(&mut self, observer: Arc<dyn GameObserver>) {
+        self.0.push(observer);
+    }
+
+    pub(crate) fn iter(&self) -> impl Iterator<Item = &Arc<dyn GameObserver>> {
+        self.0.iter()
+    }
+
+    pub(crate) fn before_action(
+        &self,
+        game: &GameState,
+        player: PlayerID,
+        action: GameAction,
+    ) -> Result<(), Veto> {
+        for observer in &self.0 {
+            observer.before_action(game, player, action)?;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn on_action(
+        &self,
+        game: &GameState,
+        player: PlayerID,
+        action: GameAction,
+        events: &[ClientEvent],
+    ) {
+        for observer in &self.0 {
+            observer.on_action(game, player, action, events);
+        }
+        self.on_events(game, events);
+    }
+
+    pub(crate) fn on_events(&self, game: &GameState, events: &[ClientEvent]) {
+        for observer in &self.0 {
+            for event in events {
+                observer.on_event(game, event);
+                match event {
+                    ClientEvent::ShoeShuffled => observer.on_shuffle(game),
+                    ClientEvent::RoundOver => {
+                        observer.on_settlement(game, game.get_last_settlement())
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    pub(crate) fn on_settlement(&self, game: &GameState, settlement: &[Settlement]) {
+        if settlement.is_empty() {
+            return;
+        }
+        for observer in &self.0 {
+            observer.on_settlement(game, settlement);
+        }
+    }
+}
+
+// Keeps a line for every action and event a table saw. Clones share the same log, so one can be
+// added to the tables and another kept to read it.
+#[derive(Debug, Clone, Default)]
+pub struct EventLog {
+    lines: Arc<Mutex<Vec<String>>>,
+}
+
+impl EventLog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get_lines(&self) -> Vec<String> {
+        self.lines.lock().unwrap().clone()
+    }
+
+    fn log(&self, game: &GameState, line: String) {
+        self.lines
+            .lock()
+            .unwrap()
+            .push(format!("table {}: {}", game.get_id(), line));
+    }
+}
+
+impl GameObserver for EventLog {
+    fn on_action(
+        &self,
+        game: &GameState,
+        player: PlayerID,
+        action: GameAction,
+        _events: &[ClientEvent],
+    ) {
+        self.log(game, format!("{} {:?}", player, action));
+    }
+
+    fn on_event(&self, game: &GameState, event: &ClientEvent) {
+        self.log(game, format!("{:?}", event));
+    }
+}
+
+// What happened at a table since it was first observed
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Stats {
+    pub actions: u64,
+    pub rounds: u64,
+    pub shuffles: u64,
+    pub busts: u64,
+    pub blackjacks: u64,
+    pub staked: ChipPile,
+    pub paid_out: ChipPile,
+}
+
+// Counts rounds, busts, blackjacks and money moved per table
+#[derive(Debug, Clone, Default)]
+pub struct TableStats {
+    stats: Arc<Mutex<HashMap<GameID, Stats>>>,
+}
+
+impl TableStats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get_stats(&self, id: GameID) -> Stats {
+        self.stats
+            .lock()
+            .unwrap()
+            .get(&id)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    fn update(&self, game: &GameState, update: impl FnOnce(&mut Stats)) {
+        update(self.stats.lock().unwrap().entry(game.get_id()).or_default());
+    }
+}
+
+impl GameObserver for TableStats {
+    fn on_action(
+        &self,
+        game: &GameState,
+        _player: PlayerID,
+        _action: GameAction,
+        _events: &[ClientEvent],
+    ) {
+        self.update(game, |stats| stats.actions += 1);
+    }
+
+    fn on_event(&self, game: &GameState, event: &ClientEvent) {
+        match event {
+            ClientEvent::RoundOver => self.update(game, |stats| stats.rounds += 1),
+            ClientEvent::PlayerBusted(_) => self.update(game, |stats| stats.busts += 1),
+            ClientEvent::PlayerBlackjack(_) => self.update(game, |stats| stats.blackjacks += 1),
+            _ => {}
+        }
+    }
+
+    fn on_shuffle(&self, game: &GameState) {
+        self.update(game, |stats| stats.shuffles += 1);
+    }
+
+    fn on_settlement(&self, game: &GameState, settlement: &[Settlement]) {
+        self.update(game, |stats| {
+            for settled in settlement {
+                stats.staked += settled.stake;
+                stats.paid_out += settled.payout;
+            }
+        });
+    }
+}
+
+// Refuses a player's bet once it could take their losses, across every table it observes, past
+// the limit
+#[derive(Debug, Clone)]
+pub struct LossLimit {
+    limit: ChipPile,
+    net: Arc<Mutex<HashMap<PlayerID, ChipPile>>>,
+}
+
+impl LossLimit {
+    pub fn new(limit: ChipPile) -> Self {
+        Self {
+            limit,
+            net: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    // What the player has won, or lost when negative, over every settled bet
+    pub fn get_net(&self, player: PlayerID) -> ChipPile {
+        self.net
+            .lock()
+            .unwrap()
+            .get(&player)
+            .copied()
+            .unwrap_or(0.0)
+    }
+}
+
+impl GameObserver for LossLimit {
+    fn before_action(
+        &self,
+        _game: &GameState,
+        player: PlayerID,
+        action: GameAction,
+    ) -> Result<(), Veto> {
+        match action {
+            GameAction::StartingBet(bet) if bet - self.get_net(player) > self.limit => {
+                Err(Veto(format!(
+                    "betting {} would pass the loss limit of {}",
+                    bet, self.limit
+                )))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn on_settlement(&self, _game: &GameState, settlement: &[Settlement]) {
+        let mut net = self.net.lock().unwrap();
+        for settled in settlement {
+            *net.entry(settled.player).or_insert(0.0) += settled.payout - settled.stake;
+        }
+    }
+}
+
+#[test]
+fn observers_see_the_whole_round() {
+    use crate::card::{Card, CardSuit, Value};
+    use crate::gamestate::GameError;
+
+    let player = PlayerID::new_v4();
+    let mut game = GameState::new(vec![player]);
+    let log = EventLog::new();
+    let stats = TableStats::new();
+    let limit = LossLimit::new(15.0);
+    game.add_observer(Arc::new(log.clone()));
+    game.add_observer(Arc::new(stats.clone()));
+    game.add_observer(Arc::new(limit.clone()));
+    game.action(GameAction::BuyIn(100.0), player).unwrap();
+
+    // The player busts on a third card, the dealer's cards are never drawn
+    game.get_mut_deck().append(&mut vec![
+        Card::new(CardSuit::Hearts, Value::Ten),
+        Card::new(CardSuit::Spades, Value::Nine),
+        Card::new(CardSuit::Clubs, Value::Six),
+        Card::new(CardSuit::Diamonds, Value::Ten),
+        Card::new(CardSuit::Hearts, Value::King),
+    ]);
+    game.action(GameAction::StartingBet(10.0), player).unwrap();
+    let events = game.action(GameAction::Hit, player).unwrap();
+    assert_eq!(Some(&ClientEvent::RoundOver), events.last());
+    assert_eq!(
+        Stats {
+            actions: 3,
+            rounds: 1,
+            shuffles: 0,
+            busts: 1,
+            blackjacks: 0,
+            staked: 10.0,
+            paid_out: 0.0,
+        },
+        stats.get_stats(0)
+    );
+    // Only the player's own actions are logged as actions, the dealing shows up as events
+    let lines = log.get_lines();
+    assert_eq!(format!("table 0: {} BuyIn(100.0)", player), lines[0]);
+    assert_eq!(
+        3,
+        lines
+            .iter()
+            .filter(|line| line.starts_with(&format!("table 0: {}", player)))
+            .count()
+    );
+    assert_eq!(Some(&"table 0: RoundOver".to_string()), lines.last());
+
+    // Down 10, a bet of 6 could pass the limit of 15 and is refused without touching the table
+    assert_eq!(-10.0, limit.get_net(player));
+    assert!(matches!(
+        game.action(GameAction::StartingBet(6.0), player),
+        Err(GameError::Vetoed(_))
+    ));
+    assert_eq!(Ok(90.0), game.get_player_money(player));
+    assert_eq!(3, stats.get_stats(0).actions);
+    assert!(game.action(GameAction::StartingBet(5.0), player).is_ok());
+}
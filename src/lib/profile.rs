@@ -0,0 +1,272 @@
+use crate::gamestate::{ChipPile, PlayerID};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+use time::OffsetDateTime;
+
+// Display names are trimmed and must be between these lengths
+pub const MIN_NAME_LENGTH: usize = 3;
+pub const MAX_NAME_LENGTH: usize = 20;
+
+pub const DEFAULT_AVATAR: &str = "default";
+
+// Everything known about a player, only the public part is shown to other players
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct PlayerProfile {
+    pub display_name: String,
+    // Names one of the avatars the client ships with, the server never stores images
+    pub avatar: String,
+    pub preferred_stakes: Option<ChipPile>,
+    pub created_at: OffsetDateTime,
+}
+
+// What a player asks to change, created_at is set the first time a profile is saved
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct ProfileUpdate {
+    pub display_name: String,
+    pub avatar: String,
+    pub preferred_stakes: Option<ChipPile>,
+}
+
+// The part of a profile every other player at the table sees
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct PublicProfile {
+    pub player: PlayerID,
+    pub display_name: String,
+    pub avatar: String,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ProfileError {
+    InvalidName,
+    NameTaken,
+    NameRejected,
+    InvalidStakes,
+}
+
+// A rule every display name has to pass before it is saved. profiles holds everyone already
+// registered, the player being named included.
+pub trait NameCheck: fmt::Debug + Send + Sync {
+    fn check(
+        &self,
+        player: PlayerID,
+        name: &str,
+        profiles: &HashMap<PlayerID, PlayerProfile>,
+    ) -> Result<(), ProfileError>;
+}
+
+// No two players may share a name, ignoring case
+#[derive(Debug, Clone, Copy, Default)]
+pub struct UniqueNames;
+
+impl NameCheck for UniqueNames {
+    fn check(
+        &self,
+        player: PlayerID,
+        name: &str,
+        profiles: &HashMap<PlayerID, PlayerProfile>,
+    ) -> Result<(), ProfileError> {
+        let taken = profiles
+            .iter()
+            .any(|(id, profile)| *id != player && profile.display_name.eq_ignore_ascii_case(name));
+        if taken {
+            return Err(ProfileError::NameTaken);
+        }
+        Ok(())
+    }
+}
+
+// Refuses names containing any of the words, ignoring case and anything between the letters
+#[derive(Debug, Clone)]
+pub struct BlockedWords {
+    words: Vec<String>,
+}
+
+impl BlockedWords {
+    pub fn new(words: Vec<String>) -> Self {
+        Self {
+            words: words.iter().map(|word| Self::normalise(word)).collect(),
+        }
+    }
+
+    fn normalise(text: &str) -> String {
+        text.chars()
+            .filter(|c| c.is_alphanumeric())
+            .flat_map(char::to_lowercase)
+            .collect()
+    }
+}
+
+impl Default for BlockedWords {
+    fn default() -> Self {
+        Self::new(
+            ["fuck", "shit", "cunt", "bitch", "asshole", "bastard"]
+                .iter()
+                .map(|word| word.to_string())
+                .collect(),
+        )
+    }
+}
+
+impl NameCheck for BlockedWords {
+    fn check(
+        &self,
+        _player: PlayerID,
+        name: &str,
+        _profiles: &HashMap<PlayerID, PlayerProfile>,
+    ) -> Result<(), ProfileError> {
+        let name = Self::normalise(name);
+        if self.words.iter().any(|word| name.contains(word.as_str())) {
+            return Err(ProfileError::NameRejected);
+        }
+        Ok(())
+    }
+}
+
+// The checks are code rather than data, so they are not persisted and a restored registry
+// starts from the defaults until they are set again
+#[derive(Debug, Clone)]
+struct NameChecks(Vec<Arc<dyn NameCheck>>);
+
+impl Default for NameChecks {
+    fn default() -> Self {
+        Self(vec![
+            Arc::new(UniqueNames),
+            Arc::new(BlockedWords::default()),
+        ])
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct ProfileRegistry {
+    profiles: HashMap<PlayerID, PlayerProfile>,
+    #[serde(skip)]
+    name_checks: NameChecks,
+}
+
+impl ProfileRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Saves a player's profile once the name passes every check, keeping when it was created
+    pub fn update(
+        &mut self,
+        player: PlayerID,
+        update: ProfileUpdate,
+        now: OffsetDateTime,
+    ) -> Result<&PlayerProfile, ProfileError> {
+        let name = update.display_name.trim();
+        if !(MIN_NAME_LENGTH..=MAX_NAME_LENGTH).contains(&name.chars().count())
+            || !name
+                .chars()
+                .all(|c| c.is_alphanumeric() || c == ' ' || c == '_' || c == '-')
+        {
+            return Err(ProfileError::InvalidName);
+        }
+        if update.preferred_stakes.is_some_and(|stakes| stakes <= 0.0) {
+            return Err(ProfileError::InvalidStakes);
+        }
+        for check in &self.name_checks.0 {
+            check.check(player, name, &self.profiles)?;
+        }
+
+        let created_at = self
+            .profiles
+            .get(&player)
+            .map_or(now, |profile| profile.created_at);
+        let profile = PlayerProfile {
+            display_name: name.to_string(),
+            avatar: update.avatar,
+            preferred_stakes: update.preferred_stakes,
+            created_at,
+        };
+        self.profiles.insert(player, profile);
+        Ok(&self.profiles[&player])
+    }
+
+    pub fn get(&self, player: PlayerID) -> Option<&PlayerProfile> {
+        self.profiles.get(&player)
+    }
+
+    // Players who never saved a profile are shown by the start of their id
+    pub fn get_public(&self, player: PlayerID) -> PublicProfile {
+        match self.profiles.get(&player) {
+            Some(profile) => PublicProfile {
+                player,
+                display_name: profile.display_name.clone(),
+                avatar: profile.avatar.clone(),
+            },
+            None => PublicProfile {
+                player,
+                display_name: format!("Player {}", &player.to_simple().to_string()[..8]),
+                avatar: DEFAULT_AVATAR.to_string(),
+            },
+        }
+    }
+
+    pub fn add_name_check(&mut self, check: Arc<dyn NameCheck>) {
+        self.name_checks.0.push(check);
+    }
+
+    // Replaces every check, the defaults included
+    pub fn set_name_checks(&mut self, checks: Vec<Arc<dyn NameCheck>>) {
+        self.name_checks = NameChecks(checks);
+    }
+}
+
+#[test]
+fn display_names_pass_every_check() {
+    let now = OffsetDateTime::now_utc();
+    let update = |name: &str| ProfileUpdate {
+        display_name: name.to_string(),
+        avatar: "spade".to_string(),
+        preferred_stakes: Some(25.0),
+    };
+    let first = PlayerID::new_v4();
+    let second = PlayerID::new_v4();
+    let mut profiles = ProfileRegistry::new();
+    assert_eq!(
+        "Lucky Ace",
+        profiles
+            .update(first, update("  Lucky Ace "), now)
+            .unwrap()
+            .display_name
+    );
+    assert_eq!(
+        Err(ProfileError::NameTaken),
+        profiles
+            .update(second, update("lucky ace"), now)
+            .map(|_| ())
+    );
+    assert_eq!(
+        Err(ProfileError::NameRejected),
+        profiles
+            .update(second, update("Sh-it Happens"), now)
+            .map(|_| ())
+    );
+    assert_eq!(
+        Err(ProfileError::InvalidName),
+        profiles.update(second, update("<b>"), now).map(|_| ())
+    );
+
+    // Renaming keeps the creation time and frees the old name
+    let later = now + time::Duration::hours(1);
+    assert_eq!(
+        now,
+        profiles
+            .update(first, update("High Roller"), later)
+            .unwrap()
+            .created_at
+    );
+    assert!(profiles.update(second, update("Lucky Ace"), later).is_ok());
+
+    // The checks can be swapped out, here to allow duplicate names
+    profiles.set_name_checks(Vec::new());
+    assert!(profiles
+        .update(second, update("High Roller"), later)
+        .is_ok());
+    let stranger = PlayerID::new_v4();
+    assert_eq!(DEFAULT_AVATAR, profiles.get_public(stranger).avatar);
+}
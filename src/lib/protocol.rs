@@ -0,0 +1,431 @@
+use crate::auth::{AuthError, SessionToken};
+use crate::card::Card;
+use crate::eventqueue::SequencedEvent;
+use crate::gamecoordinator::{CoordinatorError, TableListing};
+use crate::gamestate::{
+    ChipPile, GameAction, GameID, GameState, Hand, PlayerID, SplitHand, TableEvent,
+};
+use crate::profile::{PlayerProfile, ProfileUpdate, PublicProfile};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+// Compatibility rules:
+// - Peers can talk when their major versions match, a message with any other major version
+//   is answered with an UnsupportedVersion error and otherwise ignored
+// - A minor bump may only add fields or message variants. Unknown fields are ignored, an
+//   unknown variant is answered with an UnsupportedMessage error instead of closing the connection
+// - Removing or renaming anything, or changing what a field means, needs a major bump
+//
+// 1.1 added Ack, Resume, Pushed and TableSnapshot::last_event_seq
+// 1.2 added Session
+// 1.3 added SetProfile, Profile, InvalidProfile, SeatSnapshot::profile and ClientEvent::PlayerJoined
+// 2.0 made the Session token a signed, expiring string instead of a uuid, made PlayerRoundOver
+//     name the player and wrapped every event in a TableEvent carrying its table seq and UTC
+//     time. It added Register, Login, Registered, LoggedIn, SessionExpired, InvalidCredentials,
+//     InvalidRegistration, the table lifecycle events from PlayerLeft to ShoeShuffled,
+//     ClientEvent::DecisionGraded, GameAction::Split, ClientEvent::HandSplit,
+//     ClientEvent::SplitHandStarted and SeatSnapshot::split
+pub const PROTOCOL_VERSION: ProtocolVersion = ProtocolVersion { major: 2, minor: 0 };
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+pub struct ProtocolVersion {
+    pub major: u16,
+    pub minor: u16,
+}
+
+impl ProtocolVersion {
+    pub fn is_compatible(&self, other: &ProtocolVersion) -> bool {
+        self.major == other.major
+    }
+}
+
+impl fmt::Display for ProtocolVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}", self.major, self.minor)
+    }
+}
+
+// Every message a client sends, seq increases by one with each message so replies can name
+// the request they answer
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct ClientMessage {
+    pub version: ProtocolVersion,
+    pub seq: u64,
+    pub body: ClientRequest,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub enum ClientRequest {
+    JoinTable(GameID),
+    Action(GameAction),
+    Chat(String),
+    // Asks for a full StateSnapshot, used after reconnecting or missing events
+    Resync,
+    Ping,
+    // Every pushed event up to and including this seq has been processed, never answered
+    Ack(u64),
+    // Replays the pushed events after the last one seen, or sends a StateSnapshot if they are gone
+    Resume(u64),
+    // Answered with the saved Profile
+    SetProfile(ProfileUpdate),
+    // Lets the connection's player log in with these from now on, answered with Registered
+    Register(Credentials),
+    // Switches the connection to the player the credentials belong to, answered with LoggedIn
+    Login(Credentials),
+}
+
+#[derive(Deserialize, Serialize, Clone, PartialEq)]
+pub struct Credentials {
+    pub username: String,
+    pub password: String,
+}
+
+// Every message the server sends. reply_to holds the seq of the client message being answered
+// and is empty for messages pushed by the server on its own.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct ServerMessage {
+    pub version: ProtocolVersion,
+    pub seq: u64,
+    pub reply_to: Option<u64>,
+    pub body: ServerPayload,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub enum ServerPayload {
+    // Sent once after connecting with the id the connection plays as
+    Welcome(PlayerID),
+    // Follows the welcome, reconnecting with ?session=<token> before the token expires reclaims
+    // the same PlayerID and seat
+    Session(SessionToken),
+    Registered(String),
+    LoggedIn(PlayerID, SessionToken),
+    // The caller's own events in answer to an action
+    Events(Vec<TableEvent>),
+    // Events caused by others, numbered per player so gaps can be noticed and resumed
+    Pushed(Vec<SequencedEvent>),
+    Chat(PlayerID, String),
+    Error(ProtocolError),
+    StateSnapshot(TableSnapshot),
+    Profile(PlayerProfile),
+    Pong,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorCode {
+    UnsupportedVersion,
+    UnsupportedMessage,
+    Malformed,
+    NotSeated,
+    TableNotFound,
+    TableFull,
+    RoundInProgress,
+    SessionNotFound,
+    SessionExpired,
+    InvalidCredentials,
+    InvalidRegistration,
+    InvalidProfile,
+    Rejected,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct ProtocolError {
+    pub code: ErrorCode,
+    pub message: String,
+}
+
+// A seat as every other player sees it
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct SeatSnapshot {
+    pub player: PlayerID,
+    pub hand: Hand,
+    pub money: ChipPile,
+    pub bet: Option<ChipPile>,
+    // Left empty by from_game, the server fills it in from the coordinator's profiles
+    #[serde(default)]
+    pub profile: Option<PublicProfile>,
+    // The seat's other hand once it split a pair this round
+    #[serde(default)]
+    pub split: Option<SplitHand>,
+}
+
+// Everything needed to redraw a table from scratch. The dealer's hole card is left out
+// while a round is being played.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct TableSnapshot {
+    pub table: TableListing,
+    pub seats: Vec<SeatSnapshot>,
+    pub dealer_hand: Vec<Card>,
+    pub current_player: Option<PlayerID>,
+    // The seq of the last pushed event already reflected in this snapshot
+    #[serde(default)]
+    pub last_event_seq: u64,
+}
+
+// A message that could not be decoded, seq is known whenever the envelope itself was readable
+#[derive(Debug, Clone, PartialEq)]
+pub struct DecodeError {
+    pub seq: Option<u64>,
+    pub error: ProtocolError,
+}
+
+// Keeps passwords out of logs
+impl fmt::Debug for Credentials {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Credentials")
+            .field("username", &self.username)
+            .field("password", &"***")
+            .finish()
+    }
+}
+
+impl ProtocolError {
+    pub fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+impl From<CoordinatorError> for ProtocolError {
+    fn from(error: CoordinatorError) -> Self {
+        let code = match error {
+            CoordinatorError::PlayerNotFound => ErrorCode::NotSeated,
+            CoordinatorError::TableNotFound => ErrorCode::TableNotFound,
+            CoordinatorError::TableFull => ErrorCode::TableFull,
+            CoordinatorError::RoundInProgress => ErrorCode::RoundInProgress,
+            CoordinatorError::AuthError(AuthError::InvalidToken) => ErrorCode::SessionNotFound,
+            CoordinatorError::AuthError(AuthError::TokenExpired) => ErrorCode::SessionExpired,
+            CoordinatorError::AuthError(AuthError::InvalidCredentials) => {
+                ErrorCode::InvalidCredentials
+            }
+            CoordinatorError::AuthError(_) => ErrorCode::InvalidRegistration,
+            CoordinatorError::ProfileError(_) => ErrorCode::InvalidProfile,
+            _ => ErrorCode::Rejected,
+        };
+        Self::new(code, format!("{:?}", error))
+    }
+}
+
+impl TableSnapshot {
+    pub fn from_game(game: &GameState) -> Self {
+        let seats = game
+            .get_player_list()
+            .iter()
+            .map(|player| SeatSnapshot {
+                player: *player,
+                hand: game.get_player_hand(*player).cloned().unwrap_or_default(),
+                money: game.get_player_money(*player).unwrap_or(0.0),
+                bet: game.get_player_bet(*player).ok(),
+                profile: None,
+                split: game.get_split_hand(*player).cloned(),
+            })
+            .collect();
+        let dealer_hand = game.get_dealer_hand();
+        let dealer_hand = if game.is_between_rounds() {
+            dealer_hand.clone()
+        } else {
+            dealer_hand.iter().take(1).copied().collect()
+        };
+        Self {
+            table: TableListing::from_game(game),
+            seats,
+            dealer_hand,
+            current_player: game.get_current_player(),
+            last_event_seq: 0,
+        }
+    }
+}
+
+// Reads the version and seq before the rest of the message, so an incompatible or unknown
+// message can still be answered with an error naming the request it refers to
+fn decode<T: serde::de::DeserializeOwned>(text: &str) -> Result<T, DecodeError> {
+    #[derive(Deserialize)]
+    struct Header {
+        version: ProtocolVersion,
+        seq: u64,
+    }
+
+    let header: Header = serde_json::from_str(text).map_err(|error| DecodeError {
+        seq: None,
+        error: ProtocolError::new(ErrorCode::Malformed, error.to_string()),
+    })?;
+    if !PROTOCOL_VERSION.is_compatible(&header.version) {
+        return Err(DecodeError {
+            seq: Some(header.seq),
+            error: ProtocolError::new(
+                ErrorCode::UnsupportedVersion,
+                format!(
+                    "Protocol {} is not compatible with {}",
+                    header.version, PROTOCOL_VERSION
+                ),
+            ),
+        });
+    }
+    serde_json::from_str(text).map_err(|error| {
+        let code = if error.is_data() {
+            ErrorCode::UnsupportedMessage
+        } else {
+            ErrorCode::Malformed
+        };
+        DecodeError {
+            seq: Some(header.seq),
+            error: ProtocolError::new(code, error.to_string()),
+        }
+    })
+}
+
+impl ClientMessage {
+    pub fn new(seq: u64, body: ClientRequest) -> Self {
+        Self {
+            version: PROTOCOL_VERSION,
+            seq,
+            body,
+        }
+    }
+
+    pub fn encode(&self) -> String {
+        serde_json::to_string(self).expect("client messages always serialize")
+    }
+
+    pub fn decode(text: &str) -> Result<Self, DecodeError> {
+        decode(text)
+    }
+}
+
+impl ServerMessage {
+    pub fn new(seq: u64, reply_to: Option<u64>, body: ServerPayload) -> Self {
+        Self {
+            version: PROTOCOL_VERSION,
+            seq,
+            reply_to,
+            body,
+        }
+    }
+
+    pub fn encode(&self) -> String {
+        serde_json::to_string(self).expect("server messages always serialize")
+    }
+
+    pub fn decode(text: &str) -> Result<Self, DecodeError> {
+        decode(text)
+    }
+}
+
+#[test]
+fn every_message_round_trips() {
+    use crate::card::{CardSuit, Value};
+    use crate::gamestate::{ClientEvent, FromPlayer};
+
+    let player = PlayerID::new_v4();
+    let requests = vec![
+        ClientRequest::JoinTable(3),
+        ClientRequest::Action(GameAction::StartingBet(25.0)),
+        ClientRequest::Action(GameAction::Hit),
+        ClientRequest::Action(GameAction::Split),
+        ClientRequest::Chat("good luck".to_string()),
+        ClientRequest::Resync,
+        ClientRequest::Ping,
+        ClientRequest::Ack(12),
+        ClientRequest::Resume(10),
+        ClientRequest::SetProfile(ProfileUpdate {
+            display_name: "Lucky Ace".to_string(),
+            avatar: "spade".to_string(),
+            preferred_stakes: Some(25.0),
+        }),
+        ClientRequest::Register(Credentials {
+            username: "lucky".to_string(),
+            password: "correct horse".to_string(),
+        }),
+        ClientRequest::Login(Credentials {
+            username: "lucky".to_string(),
+            password: "correct horse".to_string(),
+        }),
+    ];
+    for (seq, body) in requests.into_iter().enumerate() {
+        let message = ClientMessage::new(seq as u64, body);
+        assert_eq!(
+            Ok(message.clone()),
+            ClientMessage::decode(&message.encode())
+        );
+    }
+
+    let mut game = GameState::new(vec![player]);
+    game.action(GameAction::BuyIn(100.0), player).unwrap();
+    game.action(GameAction::StartingBet(10.0), player).ok();
+    let card = Card::new(CardSuit::Hearts, Value::Ace);
+    let payloads = vec![
+        ServerPayload::Welcome(player),
+        ServerPayload::Session(SessionToken::new("token")),
+        ServerPayload::Registered("lucky".to_string()),
+        ServerPayload::LoggedIn(player, SessionToken::new("token")),
+        ServerPayload::Events(game.stamp(
+            vec![
+                ClientEvent::Betting(player, 10.0),
+                ClientEvent::CardRevealed(FromPlayer::Player(player), card),
+                ClientEvent::HandTotal(FromPlayer::Player(player), 11),
+                ClientEvent::TurnChanged(player),
+                ClientEvent::HandSplit(player),
+                ClientEvent::SplitHandStarted(player),
+                ClientEvent::HoleCardRevealed(card),
+                ClientEvent::Payout(player, 25.0),
+                ClientEvent::PlayerRoundOver(player),
+                ClientEvent::RoundOver,
+                ClientEvent::PlayerJoined(PublicProfile {
+                    player,
+                    display_name: "Lucky Ace".to_string(),
+                    avatar: "spade".to_string(),
+                }),
+            ],
+            time::OffsetDateTime::from_unix_timestamp(1_600_000_000),
+        )),
+        ServerPayload::Pushed(vec![SequencedEvent {
+            seq: 4,
+            event: TableEvent {
+                seq: 12,
+                at: time::OffsetDateTime::from_unix_timestamp(1_600_000_000),
+                event: ClientEvent::CashOut(player, 90.0),
+            },
+        }]),
+        ServerPayload::Chat(player, "hello".to_string()),
+        ServerPayload::Error(ProtocolError::new(ErrorCode::TableFull, "TableFull")),
+        ServerPayload::StateSnapshot(TableSnapshot::from_game(&game)),
+        ServerPayload::Profile(PlayerProfile {
+            display_name: "Lucky Ace".to_string(),
+            avatar: "spade".to_string(),
+            preferred_stakes: None,
+            created_at: time::OffsetDateTime::from_unix_timestamp(1_600_000_000),
+        }),
+        ServerPayload::Pong,
+    ];
+    for (seq, body) in payloads.into_iter().enumerate() {
+        let message = ServerMessage::new(seq as u64, Some(seq as u64 + 1), body);
+        assert_eq!(
+            Ok(message.clone()),
+            ServerMessage::decode(&message.encode())
+        );
+    }
+}
+
+#[test]
+fn incompatible_messages_are_refused_with_their_seq() {
+    let newer_minor = r#"{"version":{"major":2,"minor":4},"seq":7,"body":"Ping","extra":true}"#;
+    assert_eq!(
+        Ok(ClientRequest::Ping),
+        ClientMessage::decode(newer_minor).map(|message| message.body)
+    );
+
+    let newer_major = r#"{"version":{"major":3,"minor":0},"seq":8,"body":"Ping"}"#;
+    let error = ClientMessage::decode(newer_major).unwrap_err();
+    assert_eq!(Some(8), error.seq);
+    assert_eq!(ErrorCode::UnsupportedVersion, error.error.code);
+
+    let unknown_variant = r#"{"version":{"major":2,"minor":0},"seq":9,"body":"Wave"}"#;
+    let error = ClientMessage::decode(unknown_variant).unwrap_err();
+    assert_eq!(Some(9), error.seq);
+    assert_eq!(ErrorCode::UnsupportedMessage, error.error.code);
+
+    let error = ClientMessage::decode("not json").unwrap_err();
+    assert_eq!(None, error.seq);
+    assert_eq!(ErrorCode::Malformed, error.error.code);
+}
@@ -0,0 +1,334 @@
+use crate::auth::{AuthError, Session, SessionToken};
+use crate::eventqueue::Replay;
+use crate::gamecoordinator::{CoordinatorError, GameCoordinator};
+use crate::gamestate::{ChipPile, PlayerID};
+use crate::protocol::{ClientMessage, ClientRequest, ServerMessage, ServerPayload, TableSnapshot};
+use crate::tableconfig::TableConfig;
+use std::collections::HashMap;
+use std::io;
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use tungstenite::handshake::server::{Request, Response};
+use tungstenite::{Message, WebSocket};
+use uuid::Uuid;
+
+#[derive(Debug, Clone)]
+pub struct ServerConfig {
+    pub table_config: TableConfig,
+    pub starting_balance: ChipPile,
+    pub tick_interval: Duration,
+}
+
+// Tables seat whoever is waiting once the countdown runs out, so a lone player still gets a
+// game, and start at once when four are waiting
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            table_config: TableConfig {
+                min_seats: 1,
+                max_seats: 4,
+                start_countdown: Duration::from_secs(10),
+                ..TableConfig::default()
+            },
+            starting_balance: 1000.0,
+            tick_interval: Duration::from_secs(1),
+        }
+    }
+}
+
+// The websocket error is boxed as it is large
+type ConnectionError = Box<tungstenite::Error>;
+
+// The outbox of each player's latest connection, tagged so a replaced connection can tell
+type Connections = Arc<Mutex<HashMap<PlayerID, (Uuid, Sender<ServerPayload>)>>>;
+
+// Each connection is a player. A connection is handed to on_new_user after the handshake, or
+// resumes its session, and to on_dropped_user once it closes. Every text frame in between is
+// a ClientMessage, answered on behalf of the connection's verified session.
+pub struct Server {
+    listener: TcpListener,
+    coordinator: Arc<Mutex<GameCoordinator>>,
+    connections: Connections,
+    config: ServerConfig,
+}
+
+impl Server {
+    pub fn bind<A: ToSocketAddrs>(addr: A, config: ServerConfig) -> io::Result<Self> {
+        Ok(Self {
+            listener: TcpListener::bind(addr)?,
+            coordinator: Arc::new(Mutex::new(GameCoordinator::with_config(
+                config.table_config.clone(),
+            ))),
+            connections: Arc::new(Mutex::new(HashMap::new())),
+            config,
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    pub fn get_coordinator(&self) -> Arc<Mutex<GameCoordinator>> {
+        self.coordinator.clone()
+    }
+
+    // Accepts connections until the listener fails, serving each one on its own thread
+    pub fn run(self) -> io::Result<()> {
+        let coordinator = self.coordinator.clone();
+        let connections = self.connections.clone();
+        let tick_interval = self.config.tick_interval;
+        thread::spawn(move || loop {
+            thread::sleep(tick_interval);
+            let mut coordinator = coordinator.lock().unwrap();
+            coordinator.tick();
+            push_other_events(&mut coordinator, &connections);
+        });
+
+        for stream in self.listener.incoming() {
+            let stream = stream?;
+            let coordinator = self.coordinator.clone();
+            let connections = self.connections.clone();
+            let starting_balance = self.config.starting_balance;
+            thread::spawn(move || {
+                serve_connection(stream, coordinator, connections, starting_balance).ok();
+            });
+        }
+        Ok(())
+    }
+}
+
+// Hands every queued event to the connection of the player it is meant for
+fn push_other_events(coordinator: &mut GameCoordinator, connections: &Connections) {
+    let connections = connections.lock().unwrap();
+    for (player_id, events) in coordinator.get_other_events() {
+        if let Some((_, connection)) = connections.get(&player_id) {
+            connection.send(ServerPayload::Pushed(events)).ok();
+        }
+    }
+}
+
+// The handshake callback's error type is fixed by tungstenite
+#[allow(clippy::result_large_err)]
+fn serve_connection(
+    stream: TcpStream,
+    coordinator: Arc<Mutex<GameCoordinator>>,
+    connections: Connections,
+    starting_balance: ChipPile,
+) -> Result<(), ConnectionError> {
+    // A client reconnecting after a dropped connection names its session in the url,
+    // e.g. ws://host/?session=<token>
+    let mut session = None;
+    let socket = tungstenite::accept_hdr(stream, |request: &Request, response: Response| {
+        session = request
+            .uri()
+            .query()
+            .into_iter()
+            .flat_map(|query| query.split('&'))
+            .find_map(|pair| pair.strip_prefix("session="))
+            .map(SessionToken::new);
+        Ok(response)
+    })
+    .map_err(|error| match error {
+        tungstenite::HandshakeError::Failure(error) => error,
+        tungstenite::HandshakeError::Interrupted(_) => tungstenite::Error::ConnectionClosed,
+    })?;
+    // Reads time out so pushed events are delivered while the client is idle
+    socket
+        .get_ref()
+        .set_read_timeout(Some(Duration::from_millis(50)))
+        .map_err(tungstenite::Error::from)?;
+
+    let (pushed, outbox) = channel();
+    let connection_id = Uuid::new_v4();
+    let mut session = {
+        let mut coordinator = coordinator.lock().unwrap();
+        let resumed = session.and_then(|token| coordinator.resume(&token).ok());
+        let session = resumed.unwrap_or_else(|| {
+            let session = coordinator.on_new_user();
+            if starting_balance > 0.0 {
+                coordinator
+                    .deposit(session.get_player(), starting_balance)
+                    .ok();
+            }
+            session
+        });
+        connections
+            .lock()
+            .unwrap()
+            .insert(session.get_player(), (connection_id, pushed));
+        session
+    };
+
+    let mut connection = Connection { socket, seq: 0 };
+    let result = play(
+        &mut connection,
+        &mut session,
+        &coordinator,
+        &connections,
+        &outbox,
+    );
+    let player_id = session.get_player();
+
+    // Only the player's latest connection marks them disconnected, an older one replaced by a
+    // reconnect closes quietly
+    let mut coordinator = coordinator.lock().unwrap();
+    let latest = {
+        let mut connections = connections.lock().unwrap();
+        let latest = connections
+            .get(&player_id)
+            .is_some_and(|(id, _)| *id == connection_id);
+        if latest {
+            connections.remove(&player_id);
+        }
+        latest
+    };
+    if latest {
+        coordinator.on_dropped_user(player_id);
+        push_other_events(&mut coordinator, &connections);
+    }
+    result
+}
+
+// Stamps every outgoing message with the connection's next seq
+struct Connection {
+    socket: WebSocket<TcpStream>,
+    seq: u64,
+}
+
+impl Connection {
+    fn send(&mut self, reply_to: Option<u64>, body: ServerPayload) -> Result<(), ConnectionError> {
+        self.seq += 1;
+        let message = ServerMessage::new(self.seq, reply_to, body);
+        self.socket.send(Message::Text(message.encode()))?;
+        Ok(())
+    }
+}
+
+fn play(
+    connection: &mut Connection,
+    session: &mut Session,
+    coordinator: &Mutex<GameCoordinator>,
+    connections: &Connections,
+    outbox: &Receiver<ServerPayload>,
+) -> Result<(), ConnectionError> {
+    connection.send(None, ServerPayload::Welcome(session.get_player()))?;
+    connection.send(None, ServerPayload::Session(session.get_token().clone()))?;
+    loop {
+        for payload in outbox.try_iter() {
+            connection.send(None, payload)?;
+        }
+        let text = match connection.socket.read() {
+            Ok(Message::Text(text)) => text,
+            Ok(Message::Close(_)) => return Ok(()),
+            Ok(_) => continue,
+            Err(tungstenite::Error::Io(error))
+                if error.kind() == io::ErrorKind::WouldBlock
+                    || error.kind() == io::ErrorKind::TimedOut =>
+            {
+                continue
+            }
+            Err(tungstenite::Error::ConnectionClosed) => return Ok(()),
+            Err(error) => return Err(error.into()),
+        };
+        match ClientMessage::decode(&text) {
+            Ok(message) => {
+                if let Some(payload) = answer(message.body, session, coordinator, connections) {
+                    connection.send(Some(message.seq), payload)?;
+                }
+            }
+            Err(error) => connection.send(error.seq, ServerPayload::Error(error.error))?,
+        }
+    }
+}
+
+fn answer(
+    request: ClientRequest,
+    session: &mut Session,
+    coordinator: &Mutex<GameCoordinator>,
+    connections: &Connections,
+) -> Option<ServerPayload> {
+    let mut coordinator = coordinator.lock().unwrap();
+    let player_id = session.get_player();
+    let expired = session.is_expired(coordinator.now());
+    let payload =
+        match request {
+            // Only logging in again works once the session has expired
+            ClientRequest::Login(credentials) => coordinator
+                .login(&credentials.username, &credentials.password)
+                .map(|login| {
+                    // The connection now belongs to the account, the guest it replaces is dropped
+                    if login.get_player() != player_id {
+                        let mut connections = connections.lock().unwrap();
+                        if let Some(connection) = connections.remove(&player_id) {
+                            connections.insert(login.get_player(), connection);
+                        }
+                        drop(connections);
+                        coordinator.on_dropped_user(player_id);
+                    }
+                    *session = login;
+                    ServerPayload::LoggedIn(session.get_player(), session.get_token().clone())
+                }),
+            _ if expired => Err(AuthError::TokenExpired.into()),
+            ClientRequest::Register(credentials) => coordinator
+                .register(session, &credentials.username, &credentials.password)
+                .map(|_| ServerPayload::Registered(credentials.username)),
+            ClientRequest::JoinTable(id) => coordinator
+                .join_table(session, id)
+                .and_then(|_| snapshot(&coordinator, player_id)),
+            ClientRequest::Action(action) => coordinator
+                .handle_action(session, action)
+                .map(ServerPayload::Events),
+            ClientRequest::Chat(text) => coordinator.get_player_game(player_id).map(|game| {
+                // Chat goes to everyone at the table, spectators included
+                let connections = connections.lock().unwrap();
+                game.get_player_list()
+                    .iter()
+                    .chain(coordinator.get_spectators(game.get_id()).iter())
+                    .filter(|id| **id != player_id)
+                    .filter_map(|id| connections.get(id))
+                    .for_each(|(_, connection)| {
+                        connection
+                            .send(ServerPayload::Chat(player_id, text.clone()))
+                            .ok();
+                    });
+                ServerPayload::Chat(player_id, text)
+            }),
+            ClientRequest::Resync => snapshot(&coordinator, player_id),
+            ClientRequest::Ping => Ok(ServerPayload::Pong),
+            // Acks are never answered, an expired session was already refused above
+            ClientRequest::Ack(seq) => {
+                coordinator.ack_events(session, seq).ok();
+                return None;
+            }
+            ClientRequest::Resume(last_seen) => coordinator
+                .replay_events(session, last_seen)
+                .and_then(|replay| match replay {
+                    Replay::Events(events) => Ok(ServerPayload::Pushed(events)),
+                    Replay::Snapshot(_) => snapshot(&coordinator, player_id),
+                }),
+            ClientRequest::SetProfile(update) => coordinator
+                .set_profile(session, update)
+                .map(ServerPayload::Profile),
+        };
+    push_other_events(&mut coordinator, connections);
+    Some(payload.unwrap_or_else(|error| ServerPayload::Error(error.into())))
+}
+
+// The player's table with everyone's public profile and the last pushed event it already reflects
+fn snapshot(
+    coordinator: &GameCoordinator,
+    player_id: PlayerID,
+) -> Result<ServerPayload, CoordinatorError> {
+    let mut snapshot = TableSnapshot::from_game(coordinator.get_player_game(player_id)?);
+    for seat in &mut snapshot.seats {
+        seat.profile = Some(coordinator.get_public_profile(seat.player));
+    }
+    snapshot.last_event_seq = coordinator
+        .get_events_to_send()
+        .get(&player_id)
+        .map_or(0, |queue| queue.get_last_seq());
+    Ok(ServerPayload::StateSnapshot(snapshot))
+}
@@ -0,0 +1,393 @@
+use crate::basicstrategy::BasicStrategy;
+use crate::gamestate::{ChipPile, ClientEvent, GameAction, GameState, PlayerID};
+use crate::strategy::PlayerStrategy;
+use crate::tableconfig::TableConfig;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+// Rounds are played in chunks of this many, each on its own table with its own shoe seed, so
+// the results only depend on the seed and not on how the chunks were spread over the threads
+const CHUNK_ROUNDS: u64 = 1_000;
+
+// Every seat at the table is played by strategy, which bets at least the table minimum
+#[derive(Debug, Clone)]
+pub struct SimulationConfig {
+    pub rules: TableConfig,
+    pub strategy: Arc<dyn PlayerStrategy>,
+    pub rounds: u64,
+    pub seats: usize,
+    pub seed: u64,
+    pub threads: usize,
+}
+
+impl Default for SimulationConfig {
+    fn default() -> Self {
+        Self {
+            rules: TableConfig::default(),
+            strategy: Arc::new(BasicStrategy),
+            rounds: 1_000_000,
+            seats: 1,
+            seed: 0,
+            threads: thread::available_parallelism().map_or(1, |threads| threads.get()),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum SimulationError {
+    InvalidRules,
+    InvalidSeats,
+    // A strategy would not bet or act, so a round could not be finished
+    Stuck,
+}
+
+// How every hand ended, apart from dealer_busts which counts rounds. Busts are counted with the
+// losses as well, and a push of two naturals as a push. Both hands of a split are counted, so a
+// split adds a hand to the round.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Outcomes {
+    pub blackjacks: u64,
+    pub wins: u64,
+    pub pushes: u64,
+    pub losses: u64,
+    pub busts: u64,
+    pub doubles: u64,
+    pub splits: u64,
+    pub dealer_busts: u64,
+}
+
+// Results are kept in units of the first bet of each hand, so tables with different stakes
+// compare directly
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct SimulationReport {
+    pub rounds: u64,
+    pub hands: u64,
+    pub net: f64,
+    pub net_squared: f64,
+    pub outcomes: Outcomes,
+}
+
+impl SimulationReport {
+    // What the house keeps of every bet, negative when the players come out ahead
+    pub fn house_edge(&self) -> f64 {
+        -self.net / self.hands.max(1) as f64
+    }
+
+    // Of the result of a single hand
+    pub fn std_dev(&self) -> f64 {
+        let hands = self.hands.max(1) as f64;
+        let mean = self.net / hands;
+        (self.net_squared / hands - mean * mean).max(0.0).sqrt()
+    }
+
+    // Of the house edge, how far it could be off from the true value by chance alone
+    pub fn std_error(&self) -> f64 {
+        self.std_dev() / (self.hands.max(1) as f64).sqrt()
+    }
+
+    // The share of hands an outcome count stands for
+    pub fn frequency(&self, count: u64) -> f64 {
+        count as f64 / self.hands.max(1) as f64
+    }
+
+    fn merge(&mut self, other: &SimulationReport) {
+        self.rounds += other.rounds;
+        self.hands += other.hands;
+        self.net += other.net;
+        self.net_squared += other.net_squared;
+        let (outcomes, other) = (&mut self.outcomes, other.outcomes);
+        outcomes.blackjacks += other.blackjacks;
+        outcomes.wins += other.wins;
+        outcomes.pushes += other.pushes;
+        outcomes.losses += other.losses;
+        outcomes.busts += other.busts;
+        outcomes.doubles += other.doubles;
+        outcomes.splits += other.splits;
+        outcomes.dealer_busts += other.dealer_busts;
+    }
+}
+
+impl fmt::Display for SimulationReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let percent = |count| 100.0 * self.frequency(count);
+        let outcomes = self.outcomes;
+        writeln!(f, "{} rounds, {} hands", self.rounds, self.hands)?;
+        writeln!(
+            f,
+            "House edge {:.3}% ± {:.3}%, standard deviation {:.3} bets per hand",
+            100.0 * self.house_edge(),
+            100.0 * self.std_error(),
+            self.std_dev()
+        )?;
+        write!(
+            f,
+            "Blackjacks {:.2}%, wins {:.2}%, pushes {:.2}%, losses {:.2}%, busts {:.2}%, \
+             doubles {:.2}%, splits {:.2}%, dealer busts {:.2}% of rounds",
+            percent(outcomes.blackjacks),
+            percent(outcomes.wins),
+            percent(outcomes.pushes),
+            percent(outcomes.losses),
+            percent(outcomes.busts),
+            percent(outcomes.doubles),
+            percent(outcomes.splits),
+            100.0 * outcomes.dealer_busts as f64 / self.rounds.max(1) as f64
+        )
+    }
+}
+
+// Plays config.rounds rounds through GameState, spread over config.threads threads. The same
+// config and seed always give the same report.
+pub fn simulate(config: &SimulationConfig) -> Result<SimulationReport, SimulationError> {
+    if !config.rules.is_valid() {
+        return Err(SimulationError::InvalidRules);
+    }
+    if config.seats == 0 || config.seats > config.rules.max_seats {
+        return Err(SimulationError::InvalidSeats);
+    }
+    let chunks = config.rounds.div_ceil(CHUNK_ROUNDS);
+    let next_chunk = AtomicU64::new(0);
+    let results = Mutex::new(HashMap::new());
+    thread::scope(|scope| {
+        for _ in 0..config.threads.max(1) {
+            scope.spawn(|| loop {
+                let chunk = next_chunk.fetch_add(1, Ordering::Relaxed);
+                if chunk >= chunks {
+                    return;
+                }
+                let rounds = CHUNK_ROUNDS.min(config.rounds - chunk * CHUNK_ROUNDS);
+                let result = play_chunk(config, chunk, rounds);
+                results.lock().unwrap().insert(chunk, result);
+            });
+        }
+    });
+
+    // Added up in chunk order, so the floating point sums come out the same every time
+    let mut results = results.into_inner().unwrap();
+    let mut report = SimulationReport::default();
+    for chunk in 0..chunks {
+        report.merge(&results.remove(&chunk).unwrap()?);
+    }
+    Ok(report)
+}
+
+// A rule change to measure against the base rules
+#[derive(Debug, Clone)]
+pub struct RuleVariant {
+    pub name: String,
+    pub rules: TableConfig,
+}
+
+#[derive(Debug, Clone)]
+pub struct RuleDelta {
+    pub name: String,
+    pub report: SimulationReport,
+    // How much the variant adds to the house edge of the base rules
+    pub house_edge_delta: f64,
+}
+
+// Simulates the base rules and then every variant with the same seed. Sharing the shoes keeps
+// the deltas far less noisy than the house edges they are taken from.
+pub fn compare_rules(
+    config: &SimulationConfig,
+    variants: &[RuleVariant],
+) -> Result<(SimulationReport, Vec<RuleDelta>), SimulationError> {
+    let base = simulate(config)?;
+    let deltas = variants
+        .iter()
+        .map(|variant| {
+            let report = simulate(&SimulationConfig {
+                rules: variant.rules.clone(),
+                ..config.clone()
+            })?;
+            Ok(RuleDelta {
+                name: variant.name.clone(),
+                house_edge_delta: report.house_edge() - base.house_edge(),
+                report,
+            })
+        })
+        .collect::<Result<_, _>>()?;
+    Ok((base, deltas))
+}
+
+// Spreads the chunks' shoe seeds far apart, each chunk counts up from its own
+fn chunk_seed(seed: u64, chunk: u64) -> u64 {
+    let mut z = seed ^ chunk.wrapping_add(1).wrapping_mul(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+fn play_chunk(
+    config: &SimulationConfig,
+    chunk: u64,
+    rounds: u64,
+) -> Result<SimulationReport, SimulationError> {
+    let players: Vec<PlayerID> = (0..config.seats as u128).map(PlayerID::from_u128).collect();
+    let mut game = GameState::with_config(chunk, players.clone(), config.rules.clone());
+    game.set_shoe_seed(chunk_seed(config.seed, chunk));
+    // Enough to bet the maximum and double it, topped up before every round
+    let bankroll = 2.0 * config.rules.max_bet;
+    let mut report = SimulationReport::default();
+    for _ in 0..rounds {
+        for player in &players {
+            let money = game.get_player_money(*player).unwrap_or(0.0);
+            if money < bankroll {
+                game.action(GameAction::AddMoney(bankroll - money), *player)
+                    .map_err(|_| SimulationError::Stuck)?;
+            }
+        }
+        play_round(&mut game, config.strategy.as_ref(), &players, &mut report)?;
+    }
+    Ok(report)
+}
+
+fn play_round(
+    game: &mut GameState,
+    strategy: &dyn PlayerStrategy,
+    players: &[PlayerID],
+    report: &mut SimulationReport,
+) -> Result<(), SimulationError> {
+    let mut bets: HashMap<PlayerID, ChipPile> = HashMap::new();
+    let mut busts = 0;
+    loop {
+        let next = players.iter().find_map(|player| {
+            let legal = game.legal_actions(*player);
+            if legal.is_empty() {
+                return None;
+            }
+            let view = game.get_player_view(*player).ok()?;
+            let bankroll = game.get_player_money(*player).unwrap_or(0.0);
+            let action = strategy.decide(&view, bankroll, &legal);
+            Some((*player, action, legal.contains(&GameAction::Stand)))
+        });
+        let (player, action, can_stand) = next.ok_or(SimulationError::Stuck)?;
+        let (action, events) = match game.action(action, player) {
+            Err(_) if can_stand => (GameAction::Stand, game.action(GameAction::Stand, player)),
+            result => (action, result),
+        };
+        let events = events.map_err(|_| SimulationError::Stuck)?;
+        match action {
+            GameAction::StartingBet(bet) => {
+                bets.insert(player, bet);
+            }
+            GameAction::Double => report.outcomes.doubles += 1,
+            GameAction::Split => report.outcomes.splits += 1,
+            _ => {}
+        }
+        // Counted as they happen, the hand a split player busted is no longer in play at the end
+        busts += events
+            .iter()
+            .filter(|event| matches!(event, ClientEvent::PlayerBusted(_)))
+            .count() as u64;
+        if events.contains(&ClientEvent::RoundOver) {
+            break;
+        }
+    }
+
+    report.rounds += 1;
+    if game.sum_dealer() > 21 {
+        report.outcomes.dealer_busts += 1;
+    }
+    report.outcomes.busts += busts;
+    for settlement in game.get_last_settlement().to_vec() {
+        let bet = bets[&settlement.player] as f64;
+        let net = (settlement.payout - settlement.stake) as f64 / bet;
+        report.hands += 1;
+        report.net += net;
+        report.net_squared += net * net;
+        let outcomes = &mut report.outcomes;
+        if settlement.payout > 2.0 * settlement.stake {
+            outcomes.blackjacks += 1;
+        } else if settlement.payout > settlement.stake {
+            outcomes.wins += 1;
+        } else if settlement.payout == settlement.stake {
+            outcomes.pushes += 1;
+        } else {
+            outcomes.losses += 1;
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn simulations_are_reproducible() {
+    let config = SimulationConfig {
+        rounds: 3_000,
+        seats: 2,
+        seed: 7,
+        threads: 3,
+        ..SimulationConfig::default()
+    };
+    let report = simulate(&config).unwrap();
+    assert_eq!(
+        Ok(report),
+        simulate(&SimulationConfig {
+            threads: 1,
+            ..config.clone()
+        })
+    );
+    assert_ne!(
+        Ok(report),
+        simulate(&SimulationConfig {
+            seed: 8,
+            ..config.clone()
+        })
+    );
+    let outcomes = report.outcomes;
+    assert_eq!(
+        (3_000, 6_000 + outcomes.splits),
+        (report.rounds, report.hands)
+    );
+    assert!(outcomes.splits > 0);
+    assert_eq!(
+        report.hands,
+        outcomes.blackjacks + outcomes.wins + outcomes.pushes + outcomes.losses
+    );
+    assert_eq!(
+        Err(SimulationError::InvalidSeats),
+        simulate(&SimulationConfig { seats: 5, ..config })
+    );
+}
+
+// Takes a few seconds in a release build, run it with cargo test --release -- --ignored
+#[test]
+#[ignore]
+fn basic_strategy_keeps_the_house_edge_small() {
+    use crate::strategy::MimicTheDealer;
+    use crate::tableconfig::DealerRule;
+
+    let config = SimulationConfig {
+        rounds: 200_000,
+        seats: 2,
+        seed: 7,
+        ..SimulationConfig::default()
+    };
+    let report = simulate(&config).unwrap();
+    assert!(report.house_edge() > 0.0 && report.house_edge() < 0.03);
+    assert!(report.std_dev() > 1.0 && report.std_dev() < 1.3);
+    assert!((0.04..0.05).contains(&report.frequency(report.outcomes.blackjacks)));
+
+    // Playing like the dealer costs several percent more, and hitting soft 17 a little
+    let mimic = simulate(&SimulationConfig {
+        strategy: Arc::new(MimicTheDealer),
+        ..config.clone()
+    })
+    .unwrap();
+    assert!(mimic.house_edge() - report.house_edge() > 0.02);
+    let (base, deltas) = compare_rules(
+        &config,
+        &[RuleVariant {
+            name: "dealer hits soft 17".to_string(),
+            rules: TableConfig {
+                dealer_rule: DealerRule::HitSoft17,
+                ..TableConfig::default()
+            },
+        }],
+    )
+    .unwrap();
+    assert_eq!(report, base);
+    assert!(deltas[0].house_edge_delta > 0.0 && deltas[0].house_edge_delta < 0.01);
+}
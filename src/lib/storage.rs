@@ -0,0 +1,555 @@
+use crate::auth::{AuthError, Credential, Session, SessionToken};
+use crate::clock::{Clock, ManualClock};
+use crate::eventqueue::SequencedEvent;
+use crate::gamecoordinator::{BotMove, CoordinatorError, GameCoordinator};
+use crate::gamestate::{ChipPile, GameAction, GameID, PlayerID, TableEvent};
+use crate::profile::{PlayerProfile, ProfileUpdate};
+use crate::strategy::BotStrategy;
+use crate::tableconfig::TableConfig;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use time::OffsetDateTime;
+
+// Every mutation of the coordinator is written to the journal before it takes effect, a
+// mutation the coordinator refuses is never written. Recovery loads the last snapshot and
+// replays the journal entries written after it. Every move a bot makes is journaled as an
+// Action of its own, so replaying never asks a strategy again.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub enum Mutation {
+    NewUser(PlayerID),
+    DroppedUser(PlayerID, OffsetDateTime),
+    Action(PlayerID, GameAction),
+    Deposit(PlayerID, ChipPile),
+    Adjust(PlayerID, ChipPile),
+    Tick(OffsetDateTime),
+    OpenTable(TableConfig),
+    JoinTable(PlayerID, GameID),
+    JoinByStakes(PlayerID, ChipPile),
+    Spectate(PlayerID, GameID),
+    TakeEvents,
+    AckEvents(PlayerID, u64),
+    Register(String, Credential),
+    Reconnect(PlayerID),
+    SetProfile(PlayerID, ProfileUpdate, OffsetDateTime),
+    SeatBot(PlayerID, GameID, BotStrategy, ChipPile),
+    RemoveBot(PlayerID),
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct JournalEntry {
+    pub seq: u64,
+    pub mutation: Mutation,
+    // When the mutation was first applied, journals written before this was recorded have none
+    #[serde(default)]
+    pub at: Option<OffsetDateTime>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct Snapshot {
+    pub seq: u64,
+    pub coordinator: GameCoordinator,
+}
+
+#[derive(Debug)]
+pub enum StorageError {
+    Io(io::Error),
+    Corrupt(serde_json::Error),
+}
+
+impl From<io::Error> for StorageError {
+    fn from(error: io::Error) -> Self {
+        StorageError::Io(error)
+    }
+}
+
+impl From<serde_json::Error> for StorageError {
+    fn from(error: serde_json::Error) -> Self {
+        StorageError::Corrupt(error)
+    }
+}
+
+pub trait Storage {
+    fn load_snapshot(&mut self) -> Result<Option<Snapshot>, StorageError>;
+    fn save_snapshot(&mut self, snapshot: &Snapshot) -> Result<(), StorageError>;
+    fn append(&mut self, entry: &JournalEntry) -> Result<(), StorageError>;
+    fn read_journal(&mut self) -> Result<Vec<JournalEntry>, StorageError>;
+    fn clear_journal(&mut self) -> Result<(), StorageError>;
+}
+
+// Stores a snapshot file and a newline delimited JSON journal inside a directory
+#[derive(Debug)]
+pub struct FileStorage {
+    dir: PathBuf,
+    journal: File,
+}
+
+impl FileStorage {
+    pub fn open<P: AsRef<Path>>(dir: P) -> Result<Self, StorageError> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)?;
+        let journal = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(dir.join("journal.log"))?;
+        Ok(Self { dir, journal })
+    }
+
+    fn snapshot_path(&self) -> PathBuf {
+        self.dir.join("snapshot.json")
+    }
+
+    fn journal_path(&self) -> PathBuf {
+        self.dir.join("journal.log")
+    }
+}
+
+impl Storage for FileStorage {
+    fn load_snapshot(&mut self) -> Result<Option<Snapshot>, StorageError> {
+        match fs::read(self.snapshot_path()) {
+            Ok(bytes) => Ok(Some(serde_json::from_slice(&bytes)?)),
+            Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(error) => Err(error.into()),
+        }
+    }
+
+    fn save_snapshot(&mut self, snapshot: &Snapshot) -> Result<(), StorageError> {
+        // Write to a temporary file first so a crash never leaves a half written snapshot
+        let tmp_path = self.dir.join("snapshot.json.tmp");
+        let mut tmp = File::create(&tmp_path)?;
+        tmp.write_all(&serde_json::to_vec(snapshot)?)?;
+        tmp.sync_all()?;
+        fs::rename(&tmp_path, self.snapshot_path())?;
+        Ok(())
+    }
+
+    fn append(&mut self, entry: &JournalEntry) -> Result<(), StorageError> {
+        let mut line = serde_json::to_vec(entry)?;
+        line.push(b'\n');
+        self.journal.write_all(&line)?;
+        self.journal.sync_data()?;
+        Ok(())
+    }
+
+    fn read_journal(&mut self) -> Result<Vec<JournalEntry>, StorageError> {
+        let file = File::open(self.journal_path())?;
+        let lines: Vec<String> = BufReader::new(file).lines().collect::<Result<_, _>>()?;
+        let mut entries = Vec::new();
+        for (index, line) in lines.iter().enumerate() {
+            match serde_json::from_str(line) {
+                Ok(entry) => entries.push(entry),
+                // A torn final line means the process died while appending, before the caller was answered
+                Err(_) if index == lines.len() - 1 => break,
+                Err(error) => return Err(error.into()),
+            }
+        }
+        Ok(entries)
+    }
+
+    fn clear_journal(&mut self) -> Result<(), StorageError> {
+        self.journal.set_len(0)?;
+        self.journal.sync_all()?;
+        Ok(())
+    }
+}
+
+// Wraps a GameCoordinator so every mutation it accepts is kept in the journal
+#[derive(Debug)]
+pub struct PersistentCoordinator<S: Storage> {
+    coordinator: GameCoordinator,
+    storage: S,
+    seq: u64,
+}
+
+impl<S: Storage> PersistentCoordinator<S> {
+    // Recovers the last consistent state from storage, starting empty if nothing was saved
+    pub fn open(mut storage: S) -> Result<Self, StorageError> {
+        let (mut coordinator, mut seq) = match storage.load_snapshot()? {
+            Some(snapshot) => (snapshot.coordinator, snapshot.seq),
+            None => (GameCoordinator::new(), 0),
+        };
+        coordinator.defer_bot_turns();
+        // The tables where the last mutation gave bots a turn they have not finished, which only
+        // happens when the process died before all their moves were journaled
+        let mut turns = Vec::new();
+        for entry in storage.read_journal()? {
+            // Entries already covered by the snapshot are skipped
+            if entry.seq <= seq {
+                continue;
+            }
+            let bot_table = match entry.mutation {
+                Mutation::Action(player, _) if coordinator.is_bot(player) => {
+                    coordinator.get_player_table(player)
+                }
+                _ => None,
+            };
+            let result = Self::apply_at(&mut coordinator, entry.mutation, entry.at);
+            let noted = coordinator.take_bot_turns();
+            match bot_table {
+                None => turns = noted,
+                Some(id) => {
+                    // Bots play one table after another, so those before this one are done
+                    if let Some(index) = turns.iter().position(|turn| *turn == id) {
+                        turns.drain(..index);
+                    }
+                    if matches!(&result, Ok(table_event) if GameCoordinator::is_round_over(table_event))
+                    {
+                        turns.retain(|turn| *turn != id);
+                    }
+                }
+            }
+            seq = entry.seq;
+        }
+        let mut persistent = Self {
+            coordinator,
+            storage,
+            seq,
+        };
+        persistent.play_bots(turns)?;
+        persistent.checkpoint()?;
+        Ok(persistent)
+    }
+
+    // Holds the coordinator's clock at the time the mutation was first applied, so replaying it
+    // stamps its events and records activity exactly as it did the first time
+    fn apply_at(
+        coordinator: &mut GameCoordinator,
+        mutation: Mutation,
+        at: Option<OffsetDateTime>,
+    ) -> Result<Vec<TableEvent>, CoordinatorError> {
+        let at = match at {
+            Some(at) => at,
+            None => return Self::apply(coordinator, mutation),
+        };
+        let clock = coordinator.get_clock();
+        coordinator.set_clock(Arc::new(ManualClock::new(at)));
+        let result = Self::apply(coordinator, mutation);
+        coordinator.set_clock(clock);
+        result
+    }
+
+    fn apply(
+        coordinator: &mut GameCoordinator,
+        mutation: Mutation,
+    ) -> Result<Vec<TableEvent>, CoordinatorError> {
+        match mutation {
+            Mutation::NewUser(id) => {
+                coordinator.add_user(id);
+                Ok(Vec::new())
+            }
+            Mutation::DroppedUser(id, now) => {
+                coordinator.on_dropped_user_at(id, now);
+                Ok(Vec::new())
+            }
+            Mutation::Action(id, action) => {
+                coordinator.handle_action(&Session::trusted(id), action)
+            }
+            Mutation::Deposit(id, amount) => {
+                coordinator.deposit(id, amount)?;
+                Ok(Vec::new())
+            }
+            Mutation::Adjust(id, amount) => {
+                coordinator.adjust(id, amount)?;
+                Ok(Vec::new())
+            }
+            Mutation::Tick(now) => {
+                coordinator.tick_at(now);
+                Ok(Vec::new())
+            }
+            Mutation::OpenTable(config) => {
+                coordinator.open_table(config)?;
+                Ok(Vec::new())
+            }
+            Mutation::JoinTable(id, game_id) => {
+                coordinator.join_table(&Session::trusted(id), game_id)?;
+                Ok(Vec::new())
+            }
+            Mutation::JoinByStakes(id, preferred_bet) => {
+                coordinator.join_by_stakes(&Session::trusted(id), preferred_bet)?;
+                Ok(Vec::new())
+            }
+            Mutation::Spectate(id, game_id) => {
+                coordinator.spectate(&Session::trusted(id), game_id)?;
+                Ok(Vec::new())
+            }
+            Mutation::TakeEvents => {
+                coordinator.get_other_events();
+                Ok(Vec::new())
+            }
+            Mutation::AckEvents(id, seq) => {
+                coordinator.ack_events(&Session::trusted(id), seq)?;
+                Ok(Vec::new())
+            }
+            Mutation::Register(username, credential) => {
+                coordinator.add_credential(&username, credential)?;
+                Ok(Vec::new())
+            }
+            Mutation::Reconnect(id) => {
+                coordinator.reconnect(&Session::trusted(id))?;
+                Ok(Vec::new())
+            }
+            Mutation::SetProfile(id, update, now) => {
+                coordinator.set_profile_at(&Session::trusted(id), update, now)?;
+                Ok(Vec::new())
+            }
+            Mutation::SeatBot(bot, id, strategy, buy_in) => {
+                coordinator.seat_bot_as(bot, id, strategy, buy_in)?;
+                Ok(Vec::new())
+            }
+            Mutation::RemoveBot(bot) => {
+                coordinator.remove_bot(bot)?;
+                Ok(Vec::new())
+            }
+        }
+    }
+
+    // Applies and journals the mutation, then the moves of any bots it gave a turn
+    fn record(&mut self, mutation: Mutation) -> Result<Vec<TableEvent>, CoordinatorError> {
+        let events = self.journal(mutation)?;
+        let turns = self.coordinator.take_bot_turns();
+        self.play_bots(turns)?;
+        Ok(events)
+    }
+
+    // Plays the bots at each table like GameCoordinator::play_bots, journaling every move.
+    // Only failing to write the journal stops the bots early.
+    fn play_bots(&mut self, turns: Vec<GameID>) -> Result<(), StorageError> {
+        for id in turns {
+            while let Some(BotMove {
+                bot,
+                action,
+                fallback,
+            }) = self.coordinator.get_bot_move(id)
+            {
+                let mut result = self.journal(Mutation::Action(bot, action));
+                if let (Err(error), Some(fallback)) = (&result, fallback) {
+                    if !matches!(error, CoordinatorError::StorageError(_)) {
+                        result = self.journal(Mutation::Action(bot, fallback));
+                    }
+                }
+                // The bot's own move only noted this table again
+                self.coordinator.take_bot_turns();
+                match result {
+                    Ok(table_event) if !GameCoordinator::is_round_over(&table_event) => {}
+                    Err(CoordinatorError::StorageError(error)) => return Err(error),
+                    _ => break,
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Observers and name checks are not saved, so a mutation one of them refused would succeed
+    // on replay. The mutation is tried on a copy of the coordinator first, which keeps refused
+    // mutations out of the journal, and the copy only takes over once the entry is written, so
+    // a failed write leaves the coordinator as it is on disk.
+    fn journal(&mut self, mutation: Mutation) -> Result<Vec<TableEvent>, CoordinatorError> {
+        let at = Some(self.coordinator.now());
+        let mut applied = self.coordinator.clone();
+        let events = Self::apply_at(&mut applied, mutation.clone(), at)?;
+        let entry = JournalEntry {
+            seq: self.seq + 1,
+            mutation,
+            at,
+        };
+        self.storage.append(&entry)?;
+        self.coordinator = applied;
+        self.seq = entry.seq;
+        Ok(events)
+    }
+
+    // The clock is not part of the saved state, a reopened coordinator is back on the system clock
+    pub fn set_clock(&mut self, clock: Arc<dyn Clock>) {
+        self.coordinator.set_clock(clock);
+    }
+
+    // Writes a full snapshot and truncates the journal
+    pub fn checkpoint(&mut self) -> Result<(), StorageError> {
+        self.storage.save_snapshot(&Snapshot {
+            seq: self.seq,
+            coordinator: self.coordinator.clone(),
+        })?;
+        self.storage.clear_journal()
+    }
+
+    pub fn on_new_user(&mut self) -> Result<Session, CoordinatorError> {
+        let id = PlayerID::new_v4();
+        self.record(Mutation::NewUser(id))?;
+        Ok(self
+            .coordinator
+            .get_auth()
+            .issue(id, self.coordinator.now()))
+    }
+
+    pub fn on_dropped_user(&mut self, player_id: PlayerID) -> Result<(), CoordinatorError> {
+        self.record(Mutation::DroppedUser(player_id, self.coordinator.now()))?;
+        Ok(())
+    }
+
+    // Only the hash of the password reaches the journal
+    pub fn register(
+        &mut self,
+        session: &Session,
+        username: &str,
+        password: &str,
+    ) -> Result<(), CoordinatorError> {
+        self.check_session(session)?;
+        let auth = self.coordinator.get_auth();
+        if auth.is_username_taken(username) {
+            return Err(AuthError::UsernameTaken.into());
+        }
+        let credential = auth.hash_password(session.get_player(), password)?;
+        self.record(Mutation::Register(username.to_string(), credential))?;
+        Ok(())
+    }
+
+    pub fn login(&mut self, username: &str, password: &str) -> Result<Session, CoordinatorError> {
+        let session =
+            self.coordinator
+                .get_auth()
+                .login(username, password, self.coordinator.now())?;
+        self.record(Mutation::Reconnect(session.get_player()))?;
+        Ok(session)
+    }
+
+    pub fn resume(&mut self, token: &SessionToken) -> Result<Session, CoordinatorError> {
+        let session = self.coordinator.verify(token)?;
+        self.record(Mutation::Reconnect(session.get_player()))?;
+        Ok(session)
+    }
+
+    pub fn set_profile(
+        &mut self,
+        session: &Session,
+        update: ProfileUpdate,
+    ) -> Result<PlayerProfile, CoordinatorError> {
+        self.check_session(session)?;
+        let player_id = session.get_player();
+        self.record(Mutation::SetProfile(
+            player_id,
+            update,
+            self.coordinator.now(),
+        ))?;
+        self.coordinator
+            .get_profile(player_id)
+            .cloned()
+            .ok_or(CoordinatorError::PlayerNotFound)
+    }
+
+    pub fn handle_action(
+        &mut self,
+        session: &Session,
+        action: GameAction,
+    ) -> Result<Vec<TableEvent>, CoordinatorError> {
+        self.check_session(session)?;
+        self.record(Mutation::Action(session.get_player(), action))
+    }
+
+    pub fn seat_bot(
+        &mut self,
+        id: GameID,
+        strategy: BotStrategy,
+        buy_in: ChipPile,
+    ) -> Result<PlayerID, CoordinatorError> {
+        let bot = PlayerID::new_v4();
+        self.record(Mutation::SeatBot(bot, id, strategy, buy_in))?;
+        Ok(bot)
+    }
+
+    pub fn remove_bot(&mut self, bot: PlayerID) -> Result<(), CoordinatorError> {
+        self.record(Mutation::RemoveBot(bot))?;
+        Ok(())
+    }
+
+    pub fn deposit(
+        &mut self,
+        player_id: PlayerID,
+        amount: ChipPile,
+    ) -> Result<(), CoordinatorError> {
+        self.record(Mutation::Deposit(player_id, amount))?;
+        Ok(())
+    }
+
+    pub fn adjust(
+        &mut self,
+        player_id: PlayerID,
+        amount: ChipPile,
+    ) -> Result<(), CoordinatorError> {
+        self.record(Mutation::Adjust(player_id, amount))?;
+        Ok(())
+    }
+
+    pub fn tick(&mut self) -> Result<(), CoordinatorError> {
+        self.record(Mutation::Tick(self.coordinator.now()))?;
+        Ok(())
+    }
+
+    pub fn open_table(&mut self, config: TableConfig) -> Result<(), CoordinatorError> {
+        self.record(Mutation::OpenTable(config))?;
+        Ok(())
+    }
+
+    pub fn join_table(&mut self, session: &Session, id: GameID) -> Result<(), CoordinatorError> {
+        self.check_session(session)?;
+        self.record(Mutation::JoinTable(session.get_player(), id))?;
+        Ok(())
+    }
+
+    pub fn join_by_stakes(
+        &mut self,
+        session: &Session,
+        preferred_bet: ChipPile,
+    ) -> Result<(), CoordinatorError> {
+        self.check_session(session)?;
+        self.record(Mutation::JoinByStakes(session.get_player(), preferred_bet))?;
+        Ok(())
+    }
+
+    pub fn spectate(&mut self, session: &Session, id: GameID) -> Result<(), CoordinatorError> {
+        self.check_session(session)?;
+        self.record(Mutation::Spectate(session.get_player(), id))?;
+        Ok(())
+    }
+
+    pub fn get_other_events(
+        &mut self,
+    ) -> Result<HashMap<PlayerID, Vec<SequencedEvent>>, CoordinatorError> {
+        let events = self
+            .coordinator
+            .get_events_to_send()
+            .iter()
+            .map(|(player_id, queue)| (*player_id, queue.get_undelivered()))
+            .filter(|(_, events)| !events.is_empty())
+            .collect();
+        self.record(Mutation::TakeEvents)?;
+        Ok(events)
+    }
+
+    pub fn ack_events(&mut self, session: &Session, seq: u64) -> Result<(), CoordinatorError> {
+        self.check_session(session)?;
+        self.record(Mutation::AckEvents(session.get_player(), seq))?;
+        Ok(())
+    }
+
+    // The journal only holds the player a mutation was made for, so sessions are checked before
+    // anything is written
+    fn check_session(&self, session: &Session) -> Result<(), CoordinatorError> {
+        GameCoordinator::check_session(session, self.coordinator.now())
+    }
+
+    pub fn get_coordinator(&self) -> &GameCoordinator {
+        &self.coordinator
+    }
+
+    // Changes made through this reference are not journaled, call checkpoint afterwards to keep them
+    pub fn get_mut_coordinator(&mut self) -> &mut GameCoordinator {
+        &mut self.coordinator
+    }
+
+    pub fn get_seq(&self) -> u64 {
+        self.seq
+    }
+}
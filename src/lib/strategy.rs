@@ -0,0 +1,127 @@
+use crate::basicstrategy::BasicStrategy;
+use crate::card::Card;
+use crate::dealer::HandValue;
+use crate::gamestate::{ChipPile, GameAction, Hand, PlayerID};
+use crate::tableconfig::TableConfig;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+// What a player can see of the table when it is their move: their own hand and bet, the
+// dealer's face up card once the cards are out and the rules of the table
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlayerView {
+    pub player: PlayerID,
+    pub hand: Hand,
+    pub value: HandValue,
+    pub bet: Option<ChipPile>,
+    pub dealer_up_card: Option<Card>,
+    pub rules: TableConfig,
+}
+
+// Decides a computer player's next move. legal is never empty and holds everything the player
+// may do right now, a bet is listed at the table minimum and any amount the table and bankroll
+// allow is accepted. An action the table refuses is replaced with a stand when that is legal.
+pub trait PlayerStrategy: fmt::Debug + Send + Sync {
+    fn decide(&self, view: &PlayerView, bankroll: ChipPile, legal: &[GameAction]) -> GameAction;
+}
+
+// The bet on offer when betting is open
+pub(crate) fn offered_bet(legal: &[GameAction]) -> Option<GameAction> {
+    legal
+        .iter()
+        .copied()
+        .find(|action| matches!(action, GameAction::StartingBet(_)))
+}
+
+// Bets the minimum and plays like the dealer, hitting anything below 17
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MimicTheDealer;
+
+impl PlayerStrategy for MimicTheDealer {
+    fn decide(&self, view: &PlayerView, _bankroll: ChipPile, legal: &[GameAction]) -> GameAction {
+        if let Some(bet) = offered_bet(legal) {
+            return bet;
+        }
+        if view.value.total < 17 {
+            GameAction::Hit
+        } else {
+            GameAction::Stand
+        }
+    }
+}
+
+// Bets the minimum and never takes a card that could bust the hand
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NeverBust;
+
+impl PlayerStrategy for NeverBust {
+    fn decide(&self, view: &PlayerView, _bankroll: ChipPile, legal: &[GameAction]) -> GameAction {
+        if let Some(bet) = offered_bet(legal) {
+            return bet;
+        }
+        if view.value.total <= 11 || view.value.soft && view.value.total < 18 {
+            GameAction::Hit
+        } else {
+            GameAction::Stand
+        }
+    }
+}
+
+// The strategies the coordinator can seat a bot with. Bots are saved and journaled with the
+// coordinator, so only a strategy that can be named can play at a table.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+pub enum BotStrategy {
+    MimicTheDealer,
+    NeverBust,
+    BasicStrategy,
+}
+
+impl PlayerStrategy for BotStrategy {
+    fn decide(&self, view: &PlayerView, bankroll: ChipPile, legal: &[GameAction]) -> GameAction {
+        match self {
+            BotStrategy::MimicTheDealer => MimicTheDealer.decide(view, bankroll, legal),
+            BotStrategy::NeverBust => NeverBust.decide(view, bankroll, legal),
+            BotStrategy::BasicStrategy => BasicStrategy.decide(view, bankroll, legal),
+        }
+    }
+}
+
+#[test]
+fn built_in_strategies() {
+    use crate::card::{CardSuit, Value};
+
+    let view = |values: &[Value]| {
+        let hand: Hand = values
+            .iter()
+            .map(|value| Card::new(CardSuit::Clubs, *value))
+            .collect();
+        PlayerView {
+            player: PlayerID::new_v4(),
+            value: HandValue::of(&hand),
+            hand,
+            bet: Some(10.0),
+            dealer_up_card: Some(Card::new(CardSuit::Hearts, Value::Ten)),
+            rules: TableConfig::default(),
+        }
+    };
+    let play = [GameAction::Hit, GameAction::Stand];
+    let sixteen = view(&[Value::Ten, Value::Six]);
+    let soft_seventeen = view(&[Value::Ace, Value::Six]);
+    assert_eq!(
+        GameAction::Hit,
+        MimicTheDealer.decide(&sixteen, 90.0, &play)
+    );
+    assert_eq!(
+        GameAction::Stand,
+        MimicTheDealer.decide(&soft_seventeen, 90.0, &play)
+    );
+    assert_eq!(GameAction::Stand, NeverBust.decide(&sixteen, 90.0, &play));
+    assert_eq!(
+        GameAction::Hit,
+        NeverBust.decide(&soft_seventeen, 90.0, &play)
+    );
+    assert_eq!(
+        GameAction::StartingBet(5.0),
+        NeverBust.decide(&view(&[]), 90.0, &[GameAction::StartingBet(5.0)])
+    );
+}
@@ -0,0 +1,118 @@
+use crate::gamestate::ChipPile;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::time::Duration;
+
+// The smallest amount a single buy-in may be and the largest stack a player may hold at the table
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq)]
+pub struct BuyInLimits {
+    pub min: ChipPile,
+    pub max: ChipPile,
+}
+
+impl Default for BuyInLimits {
+    fn default() -> Self {
+        Self {
+            min: 1.0,
+            max: 10000.0,
+        }
+    }
+}
+
+// Everything needed to open a table. A table starts once min_seats players are waiting
+// and start_countdown has passed, and accepts newcomers between rounds up to max_seats.
+// Unless persistent, it is closed once empty for empty_grace or idle for abandoned_after.
+// Cards are dealt from a shoe of the given number of decks, reshuffled once it runs out.
+// A player who loses their connection keeps their seat for reconnect_grace before being removed.
+// A player leaving after the cards are dealt has their bet settled by the departure_policy.
+// The dealer plays out their hand by the dealer_rule.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct TableConfig {
+    pub name: String,
+    pub min_bet: ChipPile,
+    pub max_bet: ChipPile,
+    pub min_seats: usize,
+    pub max_seats: usize,
+    pub start_countdown: Duration,
+    pub buy_in_limits: BuyInLimits,
+    pub persistent: bool,
+    pub empty_grace: Duration,
+    pub abandoned_after: Duration,
+    pub decks: usize,
+    pub reconnect_grace: Duration,
+    pub departure_policy: DeparturePolicy,
+    pub dealer_rule: DealerRule,
+}
+
+// What happens to the bet of a player who leaves once the cards are dealt. A bet placed before
+// the deal is always handed back.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq)]
+pub enum DeparturePolicy {
+    // The house keeps the bet, as if the hand had lost
+    Forfeit,
+    Refund,
+}
+
+// When the dealer stops drawing, see DealerPolicy in lib/dealer.rs
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq)]
+pub enum DealerRule {
+    // Stands on every 17, soft or hard
+    StandSoft17,
+    // Draws to a soft 17 and stands on a hard one
+    HitSoft17,
+    // Stands on any total from this one up, such as a dealer stands on 16 promotion
+    StandOn(i8),
+}
+
+impl fmt::Display for DealerRule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DealerRule::StandSoft17 => write!(f, "dealer stands on soft 17"),
+            DealerRule::HitSoft17 => write!(f, "dealer hits soft 17"),
+            DealerRule::StandOn(total) => write!(f, "dealer stands on {}", total),
+        }
+    }
+}
+
+impl Default for TableConfig {
+    fn default() -> Self {
+        Self {
+            name: "Table".to_string(),
+            min_bet: 1.0,
+            max_bet: 1000.0,
+            min_seats: 4,
+            max_seats: 4,
+            start_countdown: Duration::from_secs(0),
+            buy_in_limits: BuyInLimits::default(),
+            persistent: false,
+            empty_grace: Duration::from_secs(60),
+            abandoned_after: Duration::from_secs(30 * 60),
+            decks: 6,
+            reconnect_grace: Duration::from_secs(60),
+            departure_policy: DeparturePolicy::Forfeit,
+            dealer_rule: DealerRule::StandSoft17,
+        }
+    }
+}
+
+impl TableConfig {
+    pub fn is_valid(&self) -> bool {
+        self.min_seats > 0
+            && self.decks > 0
+            && self.min_seats <= self.max_seats
+            && self.buy_in_limits.min <= self.buy_in_limits.max
+            && self.min_bet > 0.0
+            && self.min_bet <= self.max_bet
+            && match self.dealer_rule {
+                DealerRule::StandOn(total) => (12..=21).contains(&total),
+                _ => true,
+            }
+    }
+
+    pub fn rules_summary(&self) -> String {
+        format!(
+            "Bets {}-{}, blackjack pays 3:2, {}, double on 9-11, split pairs once",
+            self.min_bet, self.max_bet, self.dealer_rule
+        )
+    }
+}
@@ -0,0 +1,292 @@
+use crate::dealer::HandValue;
+use crate::gamestate::{ChipPile, ClientEvent, GameAction, GameState, Hand, PlayerID};
+use crate::tableconfig::TableConfig;
+use std::io::{self, BufRead, Write};
+
+const HELP: &str = "Commands: bet <amount>, hit, stand, double, split, help, quit";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Command {
+    Bet(ChipPile),
+    Hit,
+    Stand,
+    Double,
+    Split,
+    Help,
+    Quit,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct TerminalOptions {
+    pub seats: usize,
+    pub seed: u64,
+    pub starting_balance: ChipPile,
+}
+
+impl Default for TerminalOptions {
+    fn default() -> Self {
+        Self {
+            seats: 1,
+            seed: 0,
+            starting_balance: 1000.0,
+        }
+    }
+}
+
+pub fn parse_command(line: &str) -> Result<Command, String> {
+    let words: Vec<&str> = line.split_whitespace().collect();
+    match words.as_slice() {
+        ["bet", amount] => amount
+            .parse()
+            .map(Command::Bet)
+            .map_err(|_| format!("{} is not an amount", amount)),
+        ["bet"] => Err("How much? e.g. bet 50".to_string()),
+        ["hit"] => Ok(Command::Hit),
+        ["stand"] => Ok(Command::Stand),
+        ["double"] => Ok(Command::Double),
+        ["split"] => Ok(Command::Split),
+        ["help"] => Ok(Command::Help),
+        ["quit"] | ["exit"] => Ok(Command::Quit),
+        _ => Err(format!("Unknown command. {}", HELP)),
+    }
+}
+
+// Plays local seats against the dealer on a single GameState, reading commands line by line
+// so a script piped into stdin plays the same game every time for a given seed
+pub struct TerminalGame<R: BufRead, W: Write> {
+    input: R,
+    output: W,
+    game: GameState,
+    seats: Vec<PlayerID>,
+}
+
+impl<R: BufRead, W: Write> TerminalGame<R, W> {
+    pub fn new(input: R, output: W, options: TerminalOptions) -> Self {
+        let seats: Vec<PlayerID> = (0..options.seats).map(|_| PlayerID::new_v4()).collect();
+        let config = TableConfig {
+            name: "Terminal".to_string(),
+            min_seats: options.seats,
+            max_seats: options.seats,
+            ..TableConfig::default()
+        };
+        let mut game = GameState::with_config(0, seats.clone(), config);
+        game.set_shoe_seed(options.seed);
+        for seat in &seats {
+            *game.get_mut_player_money(*seat).unwrap() = options.starting_balance;
+        }
+        Self {
+            input,
+            output,
+            game,
+            seats,
+        }
+    }
+
+    pub fn get_game(&self) -> &GameState {
+        &self.game
+    }
+
+    // Plays rounds until every seat is out of chips, the player quits or the input ends
+    pub fn run(&mut self) -> io::Result<()> {
+        writeln!(self.output, "{}", self.game.get_config().rules_summary())?;
+        writeln!(self.output, "{}", HELP)?;
+        while self.take_bets()? && self.play_round()? {}
+        writeln!(self.output, "Goodbye")?;
+        Ok(())
+    }
+
+    fn seat_name(&self, player: PlayerID) -> String {
+        let seat = self.seats.iter().position(|&id| id == player).unwrap_or(0);
+        format!("Seat {}", seat + 1)
+    }
+
+    // Reads the next command, None once the input ends or the player quits
+    fn prompt(&mut self, player: PlayerID) -> io::Result<Option<Command>> {
+        loop {
+            write!(self.output, "{}> ", self.seat_name(player))?;
+            self.output.flush()?;
+            let mut line = String::new();
+            if self.input.read_line(&mut line)? == 0 {
+                writeln!(self.output)?;
+                return Ok(None);
+            }
+            match parse_command(&line) {
+                Ok(Command::Quit) => return Ok(None),
+                Ok(Command::Help) => writeln!(self.output, "{}", HELP)?,
+                Ok(command) => return Ok(Some(command)),
+                Err(message) => writeln!(self.output, "{}", message)?,
+            }
+        }
+    }
+
+    // Seats that can no longer cover the minimum bet leave, the rest each place a bet
+    fn take_bets(&mut self) -> io::Result<bool> {
+        let min_bet = self.game.get_config().min_bet;
+        for player in self.game.get_player_list().clone() {
+            if self.game.get_player_money(player).unwrap_or(0.0) < min_bet {
+                writeln!(
+                    self.output,
+                    "{} is out of chips and leaves",
+                    self.seat_name(player)
+                )?;
+                self.game.remove_user(player).ok();
+            }
+        }
+        if self.game.get_player_list().is_empty() {
+            writeln!(self.output, "Everyone is out of chips")?;
+            return Ok(false);
+        }
+
+        for player in self.game.get_player_list().clone() {
+            writeln!(
+                self.output,
+                "{} has {}, place a bet",
+                self.seat_name(player),
+                self.game.get_player_money(player).unwrap_or(0.0)
+            )?;
+            loop {
+                match self.prompt(player)? {
+                    None => return Ok(false),
+                    Some(Command::Bet(amount)) => {
+                        match self.game.action(GameAction::StartingBet(amount), player) {
+                            Ok(events) => {
+                                if events.contains(&ClientEvent::RoundOver) {
+                                    self.render_table(true)?;
+                                    self.render_results()?;
+                                } else if self.game.get_current_player().is_some() {
+                                    self.render_table(false)?;
+                                }
+                                break;
+                            }
+                            Err(_) => {
+                                let config = self.game.get_config();
+                                writeln!(
+                                    self.output,
+                                    "Bets must be between {} and {} and no more than your chips",
+                                    config.min_bet, config.max_bet
+                                )?;
+                            }
+                        }
+                    }
+                    Some(_) => writeln!(self.output, "Place a bet first, e.g. bet 50")?,
+                }
+            }
+        }
+        Ok(true)
+    }
+
+    // Lets each seat act in turn until the dealer has played and the bets are settled
+    fn play_round(&mut self) -> io::Result<bool> {
+        while !self.game.is_between_rounds() {
+            let player = match self.game.get_current_player() {
+                Some(player) => player,
+                None => break,
+            };
+            let action = match self.prompt(player)? {
+                None => return Ok(false),
+                Some(Command::Hit) => GameAction::Hit,
+                Some(Command::Stand) => GameAction::Stand,
+                Some(Command::Double) => GameAction::Double,
+                Some(Command::Split) => GameAction::Split,
+                Some(_) => {
+                    writeln!(self.output, "Bets are in, hit, stand, double or split")?;
+                    continue;
+                }
+            };
+            match self.game.action(action, player) {
+                Ok(events) if events.contains(&ClientEvent::RoundOver) => {
+                    self.render_table(true)?;
+                    self.render_results()?;
+                }
+                Ok(events) if events.is_empty() => writeln!(
+                    self.output,
+                    "You can only double your first two cards on 9, 10 or 11 with chips to cover it"
+                )?,
+                Ok(_) => self.render_table(false)?,
+                Err(_) if action == GameAction::Split => writeln!(
+                    self.output,
+                    "You can only split a first two card pair once, with chips to cover it"
+                )?,
+                Err(_) => writeln!(self.output, "You can't do that right now")?,
+            }
+        }
+        Ok(true)
+    }
+
+    fn render_hand(hand: &Hand) -> String {
+        hand.iter()
+            .map(|card| card.to_string())
+            .collect::<Vec<String>>()
+            .join(" ")
+    }
+
+    // The dealer's hole card stays hidden until the dealer plays
+    fn render_table(&mut self, reveal_dealer: bool) -> io::Result<()> {
+        let dealer_hand = self.game.get_dealer_hand().clone();
+        if reveal_dealer {
+            let total = self.game.sum_dealer();
+            writeln!(
+                self.output,
+                "Dealer: {} ({})",
+                Self::render_hand(&dealer_hand),
+                total
+            )?;
+        } else {
+            writeln!(
+                self.output,
+                "Dealer: {} ??",
+                Self::render_hand(&dealer_hand[..1].to_vec())
+            )?;
+        }
+        // A seat that split shows both hands in the order they are played
+        for player in self.game.get_player_list().clone() {
+            let hands = self
+                .game
+                .get_player_hands(player)
+                .unwrap_or_default()
+                .iter()
+                .map(|hand| {
+                    format!(
+                        "{} ({})",
+                        Self::render_hand(hand),
+                        HandValue::of(hand).total
+                    )
+                })
+                .collect::<Vec<String>>()
+                .join(" | ");
+            let turn = if !reveal_dealer && self.game.get_current_player() == Some(player) {
+                " <- to act"
+            } else {
+                ""
+            };
+            writeln!(self.output, "{}: {}{}", self.seat_name(player), hands, turn)?;
+        }
+        Ok(())
+    }
+
+    fn render_results(&mut self) -> io::Result<()> {
+        let mut settlements = self.game.get_last_settlement().clone();
+        settlements.sort_by_key(|settlement| self.seat_name(settlement.player));
+        for settlement in settlements {
+            let outcome = if settlement.payout == 0.0 {
+                "loses"
+            } else if settlement.payout == settlement.stake {
+                "pushes"
+            } else if settlement.payout == settlement.stake * 2.5 {
+                "wins with blackjack"
+            } else {
+                "wins"
+            };
+            writeln!(
+                self.output,
+                "{} {}, bet {} paid {}, now has {}",
+                self.seat_name(settlement.player),
+                outcome,
+                settlement.stake,
+                settlement.payout,
+                self.game.get_player_money(settlement.player).unwrap_or(0.0)
+            )?;
+        }
+        Ok(())
+    }
+}
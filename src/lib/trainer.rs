@@ -0,0 +1,206 @@
+use crate::analysis::HandAnalysis;
+use crate::basicstrategy::{recommend, Play, StrategyRules};
+use crate::card::Card;
+use crate::dealer::HandValue;
+use crate::gamestate::{ChipPile, PlayerID};
+use serde::{Deserialize, Serialize};
+
+// How a hand is filed in the training stats, a pair is any first two cards of the same value
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum HandCategory {
+    Hard,
+    Soft,
+    Pair,
+}
+
+impl HandCategory {
+    pub fn of(hand: &[Card]) -> Self {
+        if hand.len() == 2 && hand[0].get_value() == hand[1].get_value() {
+            HandCategory::Pair
+        } else if HandValue::of(hand).soft {
+            HandCategory::Soft
+        } else {
+            HandCategory::Hard
+        }
+    }
+}
+
+// A decision compared to basic strategy. ev_cost is how much the player gave up in expectation by
+// not following the advice, 0 when they did.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq)]
+pub struct Grade {
+    pub player: PlayerID,
+    pub category: HandCategory,
+    pub taken: Play,
+    pub advice: Play,
+    pub correct: bool,
+    pub ev_cost: ChipPile,
+}
+
+// How a player did with one category of hands
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default, PartialEq)]
+pub struct Accuracy {
+    pub decisions: u32,
+    pub mistakes: u32,
+    pub ev_lost: ChipPile,
+}
+
+impl Accuracy {
+    // The share of decisions that followed basic strategy, 1 before the first one
+    pub fn get_accuracy(&self) -> f32 {
+        if self.decisions == 0 {
+            1.0
+        } else {
+            (self.decisions - self.mistakes) as f32 / self.decisions as f32
+        }
+    }
+
+    fn record(&mut self, grade: &Grade) {
+        self.decisions += 1;
+        if !grade.correct {
+            self.mistakes += 1;
+            self.ev_lost += grade.ev_cost;
+        }
+    }
+}
+
+// Every graded decision of a player in training, by the category of the hand it was made on
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default, PartialEq)]
+pub struct TrainingStats {
+    pub hard: Accuracy,
+    pub soft: Accuracy,
+    pub pairs: Accuracy,
+}
+
+impl TrainingStats {
+    pub fn get(&self, category: HandCategory) -> &Accuracy {
+        match category {
+            HandCategory::Hard => &self.hard,
+            HandCategory::Soft => &self.soft,
+            HandCategory::Pair => &self.pairs,
+        }
+    }
+
+    pub fn get_total(&self) -> Accuracy {
+        let all = [self.hard, self.soft, self.pairs];
+        Accuracy {
+            decisions: all.iter().map(|accuracy| accuracy.decisions).sum(),
+            mistakes: all.iter().map(|accuracy| accuracy.mistakes).sum(),
+            ev_lost: all.iter().map(|accuracy| accuracy.ev_lost).sum(),
+        }
+    }
+
+    pub fn record(&mut self, grade: &Grade) {
+        match grade.category {
+            HandCategory::Hard => self.hard.record(grade),
+            HandCategory::Soft => self.soft.record(grade),
+            HandCategory::Pair => self.pairs.record(grade),
+        }
+    }
+}
+
+// Grades taking a play on hand against the dealer's up card, for a bet of bet. The advice is
+// basic strategy for rules, which should only offer what the player could have done. A mistake
+// is costed with analyze, the same exact analysis the table's hint panel shows, so it is only
+// asked for when the play differs from the advice.
+pub fn grade(
+    player: PlayerID,
+    hand: &[Card],
+    up_card: Card,
+    rules: &StrategyRules,
+    taken: Play,
+    bet: ChipPile,
+    analyze: impl FnOnce() -> Option<HandAnalysis>,
+) -> Grade {
+    let advice = recommend(hand, up_card, rules);
+    let correct = taken == advice;
+    let ev_cost = match Some(analyze)
+        .filter(|_| !correct)
+        .and_then(|analyze| analyze())
+    {
+        Some(analysis) => {
+            let ev = |play| analysis.get(play).unwrap_or(-1.0);
+            // The charts are made for whole shoes, so the cards left can favour the other play
+            // by a hair, which is not counted against the player
+            ((ev(advice) - ev(taken)).max(0.0) * bet as f64) as ChipPile
+        }
+        None => 0.0,
+    };
+    Grade {
+        player,
+        category: HandCategory::of(hand),
+        taken,
+        advice,
+        correct,
+        ev_cost,
+    }
+}
+
+#[test]
+fn grades_against_basic_strategy() {
+    use crate::analysis::{Analyzer, Shoe};
+    use crate::basicstrategy::DoubleRule;
+    use crate::card::{CardSuit, Value};
+    use crate::tableconfig::TableConfig;
+
+    let hand = |values: &[Value]| -> Vec<Card> {
+        values
+            .iter()
+            .map(|value| Card::new(CardSuit::Clubs, *value))
+            .collect()
+    };
+    let rules = StrategyRules {
+        hit_soft_17: false,
+        double: DoubleRule::NineToEleven,
+        split: false,
+        double_after_split: false,
+        surrender: false,
+    };
+    let analyzer = Analyzer::new(TableConfig::default());
+    let analyze = |hand: &[Card], up_card: Card| {
+        let unseen = Shoe::new(6).without(up_card.get_value()).unwrap();
+        let unseen = hand
+            .iter()
+            .fold(unseen, |shoe, card| shoe.without(card.get_value()).unwrap());
+        analyzer.analyze(hand, up_card, &unseen)
+    };
+    let six = Card::new(CardSuit::Hearts, Value::Six);
+    let ten = Card::new(CardSuit::Hearts, Value::Ten);
+
+    // Following the advice costs nothing and the analysis is not even asked for
+    let player = PlayerID::new_v4();
+    let eleven = hand(&[Value::Five, Value::Six]);
+    let right = grade(player, &eleven, six, &rules, Play::Double, 10.0, || {
+        panic!("a correct play is not costed")
+    });
+    assert!(right.correct && right.ev_cost == 0.0);
+
+    // Standing on 11 against a 6 gives up the difference the hint panel shows
+    let analysis = analyze(&eleven, six);
+    let wrong = grade(player, &eleven, six, &rules, Play::Stand, 10.0, || {
+        Some(analysis)
+    });
+    assert_eq!((Play::Double, false), (wrong.advice, wrong.correct));
+    let shown = (analysis.double.unwrap() - analysis.stand) * 10.0;
+    assert!((wrong.ev_cost as f64 - shown).abs() < 1e-4);
+    assert!(wrong.ev_cost > 8.0 && wrong.ev_cost < 9.0);
+
+    let mut stats = TrainingStats::default();
+    stats.record(&right);
+    stats.record(&wrong);
+    let soft_eighteen = hand(&[Value::Ace, Value::Seven]);
+    stats.record(&grade(
+        player,
+        &soft_eighteen,
+        ten,
+        &rules,
+        Play::Hit,
+        10.0,
+        || Some(analyze(&soft_eighteen, ten)),
+    ));
+    assert_eq!(0.5, stats.get(HandCategory::Hard).get_accuracy());
+    assert_eq!(1.0, stats.get(HandCategory::Soft).get_accuracy());
+    assert_eq!(0, stats.get(HandCategory::Pair).decisions);
+    assert_eq!(3, stats.get_total().decisions);
+    assert_eq!(wrong.ev_cost, stats.get_total().ev_lost);
+}
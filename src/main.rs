@@ -4,78 +4,196 @@ use gamecoordinator::GameCoordinator;
 use gamestate::{GameAction, GameState, PlayerID};
 use lib::gamecoordinator;
 use lib::gamestate;
+use lib::server::{Server, ServerConfig};
+use lib::simulation::{compare_rules, RuleVariant, SimulationConfig};
+use lib::tableconfig::{DealerRule, TableConfig};
+use lib::terminal::{TerminalGame, TerminalOptions};
 
-fn main() {
-    todo!();
+// `black_jack serve [addr]` serves the game over WebSockets, `black_jack simulate` measures the
+// house edge, otherwise it is played in the terminal, `--seats <n>` plays several local seats
+// and `--seed <n>` replays the same shoe
+fn main() -> std::io::Result<()> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().map(String::as_str) == Some("simulate") {
+        simulate(&args[1..]);
+        return Ok(());
+    }
+    if args.first().map(String::as_str) == Some("serve") {
+        let addr = args
+            .get(1)
+            .cloned()
+            .unwrap_or_else(|| "127.0.0.1:8080".to_string());
+        let server = Server::bind(addr, ServerConfig::default())?;
+        println!("Listening on ws://{}", server.local_addr()?);
+        return server.run();
+    }
+
+    let mut options = TerminalOptions {
+        seed: time::OffsetDateTime::now_utc().unix_timestamp_nanos() as u64,
+        ..TerminalOptions::default()
+    };
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let value = args.next().and_then(|value| value.parse().ok());
+        match (arg.as_str(), value) {
+            ("--seats", Some(seats)) if (1..=4).contains(&seats) => options.seats = seats as usize,
+            ("--seed", Some(seed)) => options.seed = seed,
+            _ => {
+                eprintln!("Usage: black_jack [--seats 1-4] [--seed <n>] | black_jack serve [addr]");
+                std::process::exit(2);
+            }
+        }
+    }
+    let stdin = std::io::stdin();
+    TerminalGame::new(stdin.lock(), std::io::stdout(), options).run()
+}
+
+// Plays basic strategy under the default rules and under each rule change, `--rounds <n>`,
+// `--seats <n>`, `--seed <n>` and `--threads <n>` set up the run
+fn simulate(args: &[String]) {
+    let mut config = SimulationConfig::default();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let value = args.next().and_then(|value| value.parse::<u64>().ok());
+        match (arg.as_str(), value) {
+            ("--rounds", Some(rounds)) => config.rounds = rounds,
+            ("--seats", Some(seats)) => config.seats = seats as usize,
+            ("--seed", Some(seed)) => config.seed = seed,
+            ("--threads", Some(threads)) if threads > 0 => config.threads = threads as usize,
+            _ => {
+                eprintln!(
+                    "Usage: black_jack simulate [--rounds <n>] [--seats 1-4] [--seed <n>] [--threads <n>]"
+                );
+                std::process::exit(2);
+            }
+        }
+    }
+    let variant = |name: &str, rules: TableConfig| RuleVariant {
+        name: name.to_string(),
+        rules,
+    };
+    let variants = [
+        variant(
+            "dealer hits soft 17",
+            TableConfig {
+                dealer_rule: DealerRule::HitSoft17,
+                ..config.rules.clone()
+            },
+        ),
+        variant(
+            "single deck",
+            TableConfig {
+                decks: 1,
+                ..config.rules.clone()
+            },
+        ),
+        variant(
+            "eight decks",
+            TableConfig {
+                decks: 8,
+                ..config.rules.clone()
+            },
+        ),
+    ];
+    match compare_rules(&config, &variants) {
+        Ok((base, deltas)) => {
+            println!("{}\n{}", config.rules.rules_summary(), base);
+            for delta in deltas {
+                println!(
+                    "{}: house edge {:.3}% ({:+.3}%)",
+                    delta.name,
+                    100.0 * delta.report.house_edge(),
+                    100.0 * delta.house_edge_delta
+                );
+            }
+        }
+        Err(error) => {
+            eprintln!("Could not simulate: {:?}", error);
+            std::process::exit(2);
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use gamestate::{ClientEvent, FromPlayer, GameAction, GameError, GameState, PlayerID};
+    use gamestate::{
+        ChipPile, ClientEvent, FromPlayer, GameAction, GameError, GameID, GameState, PlayerID,
+    };
+    use lib::auth::{AuthError, Session};
+    use lib::basicstrategy::{BasicStrategy, Play};
     use lib::card::{Card, CardSuit, Value};
+    use lib::clock::ManualClock;
+    use lib::concurrentcoordinator::ConcurrentCoordinator;
+    use lib::dealer::{DealerPolicy, HandValue};
+    use lib::eventqueue::Replay;
+    use lib::gamecoordinator::{CloseReason, CoordinatorError};
+    use lib::ledger::{Account, EntryKind, LedgerError};
+    use lib::observer::{EventLog, LossLimit, TableStats};
+    use lib::profile::{BlockedWords, ProfileError, ProfileUpdate};
+    use lib::storage::{
+        FileStorage, JournalEntry, PersistentCoordinator, Snapshot, Storage, StorageError,
+    };
+    use lib::strategy::{BotStrategy, PlayerStrategy};
+    use lib::tableconfig::{BuyInLimits, DealerRule, DeparturePolicy, TableConfig};
+    use lib::terminal::{parse_command, Command, TerminalGame, TerminalOptions};
+    use lib::trainer::{Grade, HandCategory};
+    use std::process::{Command as Process, Stdio};
+    use std::sync::Arc;
 
     #[test]
-    fn gamecoordinator() -> Result<(), GameError> {
+    fn gamecoordinator() -> Result<(), CoordinatorError> {
         // Test GameCoordinator
         let mut coordinator = GameCoordinator::new();
 
         // After four players in waiting room, start new game
         assert_eq!(0, coordinator.get_mut_current_games().len());
-        let player1 = coordinator.on_new_user();
+        let player1 = coordinator.on_new_user().get_player();
         coordinator.on_new_user();
         coordinator.on_new_user();
         coordinator.on_new_user();
         assert_eq!(1, coordinator.get_mut_current_games().len());
 
         // Test GameState
-        coordinator.get_mut_current_games()[0].create_users_hand();
-        coordinator.get_mut_current_games()[0]
+        coordinator.get_mut_game(0)?.create_users_hand();
+        coordinator
+            .get_mut_game(0)?
             .get_mut_deck()
             .append(&mut vec![
                 Card::new(CardSuit::Hearts, Value::Ace),
                 Card::new(CardSuit::Hearts, Value::Nine),
             ]);
 
-        assert_eq!(
-            4,
-            coordinator.get_mut_current_games()[0]
-                .get_player_list()
-                .len()
-        );
+        assert_eq!(4, coordinator.get_mut_game(0)?.get_player_list().len());
         assert_eq!(
             vec![
                 Card::new(CardSuit::Hearts, Value::Ace),
                 Card::new(CardSuit::Hearts, Value::Nine)
             ],
-            *coordinator.get_mut_current_games()[0].get_deck()
+            *coordinator.get_mut_game(0)?.get_deck()
         );
 
         // Player 2 is in waiting room
-        let player2 = coordinator.on_new_user();
+        let player2 = coordinator.on_new_user().get_player();
 
-        assert_eq!(
-            true,
-            coordinator.get_mut_current_games()[0]
-                .get_player_list()
-                .contains(&player1)
-        );
-        assert_eq!(true, coordinator.get_available_players().contains(&player2));
+        assert!(coordinator
+            .get_mut_game(0)?
+            .get_player_list()
+            .contains(&player1));
+        assert!(coordinator.get_available_players().contains(&player2));
 
-        // Test on_dropped_user remove players
+        // Test on_dropped_user remove players, seated players once their reconnect grace is over
         coordinator.on_dropped_user(player1);
         coordinator.on_dropped_user(player2);
+        assert!(coordinator.is_disconnected(player1));
+        assert!(!coordinator.get_available_players().contains(&player2));
+        coordinator.tick_at(time::OffsetDateTime::now_utc() + std::time::Duration::from_secs(60));
 
-        assert_eq!(
-            false,
-            coordinator.get_mut_current_games()[0]
-                .get_player_list()
-                .contains(&player1)
-        );
-        assert_eq!(
-            false,
-            coordinator.get_available_players().contains(&player2)
-        );
+        assert!(!coordinator
+            .get_mut_game(0)?
+            .get_player_list()
+            .contains(&player1));
+        assert!(!coordinator.get_available_players().contains(&player2));
 
         Ok(())
     }
@@ -87,10 +205,10 @@ mod tests {
         let player2 = PlayerID::new_v4();
         let mut game: GameState = GameState::new(vec![player1, player2]);
         game.create_users_hand();
-        assert_eq!(true, game.get_player_list().contains(&player1));
-        game.remove_user(player1);
-        assert_eq!(false, game.get_player_list().contains(&player1));
-        assert_eq!(true, game.get_player_list().contains(&player2));
+        assert!(game.get_player_list().contains(&player1));
+        game.remove_user(player1)?;
+        assert!(!game.get_player_list().contains(&player1));
+        assert!(game.get_player_list().contains(&player2));
         Ok(())
     }
 
@@ -219,10 +337,13 @@ mod tests {
         let test_hit = game.action(GameAction::Hit, player1).ok();
         assert_eq!(
             test_hit,
-            Some(vec![ClientEvent::CardRevealed(
-                FromPlayer::Player(player1),
-                Card::new(CardSuit::Hearts, Value::King)
-            ),])
+            Some(vec![
+                ClientEvent::CardRevealed(
+                    FromPlayer::Player(player1),
+                    Card::new(CardSuit::Hearts, Value::King)
+                ),
+                ClientEvent::HandTotal(FromPlayer::Player(player1), 13)
+            ])
         );
 
         assert_eq!(
@@ -310,7 +431,13 @@ mod tests {
 
         // Test return value from Stand
         let test_stand = game.action(GameAction::Stand, player1).ok();
-        assert_eq!(test_stand, Some(vec![ClientEvent::PlayerRoundOver]));
+        assert_eq!(
+            test_stand,
+            Some(vec![
+                ClientEvent::PlayerRoundOver(player1),
+                ClientEvent::TurnChanged(player2)
+            ])
+        );
 
         // Cannot double without sufficient money
         assert_eq!(
@@ -339,11 +466,14 @@ mod tests {
         assert_eq!(
             test_double_3,
             Some(vec![
+                ClientEvent::BalanceChanged(player3, 0.0),
                 ClientEvent::CardRevealed(
                     FromPlayer::Player(player3),
                     Card::new(CardSuit::Spades, Value::Five)
                 ),
-                ClientEvent::PlayerRoundOver
+                ClientEvent::HandTotal(FromPlayer::Player(player3), 14),
+                ClientEvent::PlayerRoundOver(player3),
+                ClientEvent::TurnChanged(player4)
             ])
         );
         assert_eq!(0.0, game.get_player_money(player3)?);
@@ -388,6 +518,137 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn split() -> Result<(), GameError> {
+        // Each hand of a split is played in turn with its own bet and settled on its own
+        let player1 = PlayerID::new_v4();
+        let player2 = PlayerID::new_v4();
+        let mut game = GameState::with_config(
+            0,
+            vec![player1, player2],
+            TableConfig {
+                min_seats: 2,
+                max_seats: 2,
+                ..TableConfig::default()
+            },
+        );
+        game.get_mut_deck().append(&mut vec![
+            Card::new(CardSuit::Hearts, Value::Eight),
+            Card::new(CardSuit::Hearts, Value::Ace),
+            Card::new(CardSuit::Hearts, Value::Ten),
+            Card::new(CardSuit::Diamonds, Value::Eight),
+            Card::new(CardSuit::Diamonds, Value::Ace),
+            Card::new(CardSuit::Clubs, Value::Seven),
+            Card::new(CardSuit::Spades, Value::Three),
+            Card::new(CardSuit::Spades, Value::Ten),
+            Card::new(CardSuit::Clubs, Value::Eight),
+            Card::new(CardSuit::Clubs, Value::Ten),
+            Card::new(CardSuit::Hearts, Value::King),
+            Card::new(CardSuit::Diamonds, Value::Five),
+        ]);
+        for player in [player1, player2] {
+            game.action(GameAction::AddMoney(100.0), player)?;
+            game.action(GameAction::StartingBet(10.0), player)?;
+        }
+
+        // Eights against a ten are split, the first hand is dealt its second card at once
+        assert!(game.legal_actions(player1).contains(&GameAction::Split));
+        assert_eq!(Ok(Play::Split), game.hint(player1));
+        assert_eq!(
+            vec![
+                ClientEvent::BalanceChanged(player1, 80.0),
+                ClientEvent::HandSplit(player1),
+                ClientEvent::CardRevealed(
+                    FromPlayer::Player(player1),
+                    Card::new(CardSuit::Spades, Value::Three)
+                ),
+                ClientEvent::HandTotal(FromPlayer::Player(player1), 11),
+            ],
+            game.action(GameAction::Split, player1)?
+        );
+        assert_eq!(10.0, game.get_player_bet(player1)?);
+        assert_eq!(20.0, game.get_player_stake(player1)?);
+
+        // A split hand is not doubled or split again
+        assert_eq!(
+            vec![GameAction::Hit, GameAction::Stand],
+            game.legal_actions(player1)
+        );
+        assert_eq!(
+            Err(GameError::InvaildAction),
+            game.action(GameAction::Split, player1)
+        );
+        game.action(GameAction::Hit, player1)?;
+
+        // Standing moves on to the second hand, which busts and passes the turn
+        assert_eq!(
+            vec![
+                ClientEvent::SplitHandStarted(player1),
+                ClientEvent::CardRevealed(
+                    FromPlayer::Player(player1),
+                    Card::new(CardSuit::Clubs, Value::Eight)
+                ),
+                ClientEvent::HandTotal(FromPlayer::Player(player1), 16),
+            ],
+            game.action(GameAction::Stand, player1)?
+        );
+        assert_eq!(Some(player1), game.get_current_player());
+        assert!(!game.legal_actions(player1).contains(&GameAction::Split));
+        game.action(GameAction::Hit, player1)?;
+        assert_eq!(Some(player2), game.get_current_player());
+        assert_eq!(
+            vec![
+                &vec![
+                    Card::new(CardSuit::Hearts, Value::Eight),
+                    Card::new(CardSuit::Spades, Value::Three),
+                    Card::new(CardSuit::Spades, Value::Ten),
+                ],
+                &vec![
+                    Card::new(CardSuit::Diamonds, Value::Eight),
+                    Card::new(CardSuit::Clubs, Value::Eight),
+                    Card::new(CardSuit::Clubs, Value::Ten),
+                ],
+            ],
+            game.get_player_hands(player1)?
+        );
+
+        // Split aces take one card each, and an ace and a king after a split is no natural
+        assert_eq!(
+            vec![
+                ClientEvent::BalanceChanged(player2, 80.0),
+                ClientEvent::HandSplit(player2),
+                ClientEvent::CardRevealed(
+                    FromPlayer::Player(player2),
+                    Card::new(CardSuit::Hearts, Value::King)
+                ),
+                ClientEvent::HandTotal(FromPlayer::Player(player2), 21),
+                ClientEvent::SplitHandStarted(player2),
+                ClientEvent::CardRevealed(
+                    FromPlayer::Player(player2),
+                    Card::new(CardSuit::Diamonds, Value::Five)
+                ),
+                ClientEvent::HandTotal(FromPlayer::Player(player2), 16),
+                ClientEvent::PlayerRoundOver(player2),
+                ClientEvent::HoleCardRevealed(Card::new(CardSuit::Clubs, Value::Seven)),
+                ClientEvent::HandTotal(FromPlayer::Dealer, 17),
+                ClientEvent::Payout(player1, 20.0),
+                ClientEvent::Payout(player1, 0.0),
+                ClientEvent::BalanceChanged(player1, 100.0),
+                ClientEvent::Payout(player2, 20.0),
+                ClientEvent::Payout(player2, 0.0),
+                ClientEvent::BalanceChanged(player2, 100.0),
+                ClientEvent::RoundOver,
+            ],
+            game.action(GameAction::Split, player2)?
+        );
+        assert_eq!(4, game.get_last_settlement().len());
+        assert!(game
+            .get_last_settlement()
+            .iter()
+            .all(|settlement| settlement.stake == 10.0));
+        Ok(())
+    }
+
     #[test]
     fn bets_returned() -> Result<(), GameError> {
         // Tests if bets are returned
@@ -408,16 +669,76 @@ mod tests {
 
         // Test return value from AddMoney
         let test_money = game.action(GameAction::AddMoney(100.0), player1).ok();
-        assert_eq!(test_money, Some(vec![ClientEvent::Betting(player1, 100.0)]));
+        assert_eq!(
+            test_money,
+            Some(vec![
+                ClientEvent::Betting(player1, 100.0),
+                ClientEvent::BalanceChanged(player1, 100.0)
+            ])
+        );
 
         // Test return value from StartingBet
         let test_bet = game.action(GameAction::StartingBet(100.0), player1).ok();
-        assert_eq!(test_bet, Some(vec![ClientEvent::Betting(player1, 100.0)]));
+        assert_eq!(
+            test_bet,
+            Some(vec![
+                ClientEvent::Betting(player1, 100.0),
+                ClientEvent::BalanceChanged(player1, 0.0)
+            ])
+        );
 
         game.action(GameAction::AddMoney(200.0), player2).ok();
 
-        // After everyone bets start_game is run, dealing cards
-        game.action(GameAction::StartingBet(50.0), player2).ok();
+        // After everyone bets start_game is run, dealing cards one at a time with the dealer's
+        // second card face down, then naturals are called and the first hand to act is named
+        let deal = |from: FromPlayer, suit: CardSuit, value: Value, total: i8| {
+            vec![
+                ClientEvent::CardRevealed(from.clone(), Card::new(suit, value)),
+                ClientEvent::HandTotal(from, total),
+            ]
+        };
+        let expected: Vec<ClientEvent> = [
+            vec![
+                ClientEvent::Betting(player2, 50.0),
+                ClientEvent::BalanceChanged(player2, 150.0),
+                ClientEvent::RoundStarted,
+            ],
+            deal(
+                FromPlayer::Player(player1),
+                CardSuit::Hearts,
+                Value::Ace,
+                11,
+            ),
+            deal(
+                FromPlayer::Player(player2),
+                CardSuit::Spades,
+                Value::Ten,
+                10,
+            ),
+            deal(FromPlayer::Dealer, CardSuit::Diamonds, Value::Two, 2),
+            deal(
+                FromPlayer::Player(player1),
+                CardSuit::Clubs,
+                Value::Jack,
+                21,
+            ),
+            deal(
+                FromPlayer::Player(player2),
+                CardSuit::Clubs,
+                Value::Queen,
+                20,
+            ),
+            vec![
+                ClientEvent::PlayerBlackjack(player1),
+                ClientEvent::PlayerRoundOver(player1),
+                ClientEvent::TurnChanged(player2),
+            ],
+        ]
+        .concat();
+        assert_eq!(
+            expected,
+            game.action(GameAction::StartingBet(50.0), player2)?
+        );
 
         // Test dealer hand
         assert_eq!(
@@ -448,15 +769,20 @@ mod tests {
                     FromPlayer::Player(player2),
                     Card::new(CardSuit::Hearts, Value::Ten)
                 ),
-                ClientEvent::PlayerRoundOver,
-                ClientEvent::CardRevealed(
-                    FromPlayer::Dealer,
-                    Card::new(CardSuit::Hearts, Value::Eight)
-                ),
+                ClientEvent::HandTotal(FromPlayer::Player(player2), 30),
+                ClientEvent::PlayerBusted(player2),
+                ClientEvent::PlayerRoundOver(player2),
+                ClientEvent::HoleCardRevealed(Card::new(CardSuit::Hearts, Value::Eight)),
+                ClientEvent::HandTotal(FromPlayer::Dealer, 10),
                 ClientEvent::CardRevealed(
                     FromPlayer::Dealer,
                     Card::new(CardSuit::Hearts, Value::Seven)
                 ),
+                ClientEvent::HandTotal(FromPlayer::Dealer, 17),
+                ClientEvent::Payout(player1, 250.0),
+                ClientEvent::BalanceChanged(player1, 250.0),
+                ClientEvent::Payout(player2, 0.0),
+                ClientEvent::BalanceChanged(player2, 150.0),
                 ClientEvent::RoundOver
             ])
         );
@@ -485,4 +811,2026 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn persistence_crash_recovery() -> Result<(), CoordinatorError> {
+        // The child process plays a few actions and is killed before it can shut down cleanly
+        if let Ok(dir) = std::env::var("BLACKJACK_CRASH_DIR") {
+            let mut coordinator = PersistentCoordinator::open(FileStorage::open(&dir)?)?;
+            let mut players = Vec::new();
+            for _ in 0..4 {
+                players.push(coordinator.on_new_user()?);
+            }
+            let game = coordinator.get_mut_coordinator().get_mut_game(0)?;
+            game.create_users_hand();
+            game.get_mut_deck().append(&mut vec![
+                Card::new(CardSuit::Hearts, Value::Two),
+                Card::new(CardSuit::Hearts, Value::Three),
+            ]);
+            coordinator.checkpoint()?;
+            coordinator.deposit(players[0].get_player(), 100.0)?;
+            coordinator.handle_action(&players[0], GameAction::BuyIn(100.0))?;
+            coordinator.handle_action(&players[0], GameAction::StartingBet(40.0))?;
+            let players: Vec<PlayerID> = players.iter().map(Session::get_player).collect();
+            std::fs::write(
+                std::path::Path::new(&dir).join("players.json"),
+                serde_json::to_vec(&players).unwrap(),
+            )
+            .unwrap();
+            std::process::abort();
+        }
+
+        let dir = std::env::temp_dir().join(format!("blackjack-{}", PlayerID::new_v4()));
+        let status = Process::new(std::env::current_exe().unwrap())
+            .args(["tests::persistence_crash_recovery", "--exact"])
+            .env("BLACKJACK_CRASH_DIR", &dir)
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .unwrap();
+        assert!(!status.success());
+
+        let players: Vec<PlayerID> =
+            serde_json::from_slice(&std::fs::read(dir.join("players.json")).unwrap()).unwrap();
+
+        // Simulate a crash in the middle of appending a journal entry
+        let mut journal = std::fs::OpenOptions::new()
+            .append(true)
+            .open(dir.join("journal.log"))
+            .unwrap();
+        std::io::Write::write_all(&mut journal, b"{\"seq\":8,\"mutation\":{\"Act").unwrap();
+        drop(journal);
+
+        // Both journaled actions survive, the torn entry is discarded
+        let recovered = PersistentCoordinator::open(FileStorage::open(&dir)?)?;
+        assert_eq!(7, recovered.get_seq());
+        let game = recovered.get_coordinator().get_game(0)?;
+        assert_eq!(players, *game.get_player_list());
+        assert_eq!(60.0, game.get_player_money(players[0])?);
+        assert_eq!(40.0, game.get_player_bet(players[0])?);
+        assert_eq!(
+            vec![
+                Card::new(CardSuit::Hearts, Value::Two),
+                Card::new(CardSuit::Hearts, Value::Three)
+            ],
+            *game.get_deck()
+        );
+
+        std::fs::remove_dir_all(&dir).ok();
+        Ok(())
+    }
+
+    #[test]
+    fn wallet_ledger() -> Result<(), CoordinatorError> {
+        // Table stacks are funded from the wallet and every movement is recorded in the ledger
+        let mut coordinator = GameCoordinator::new();
+        let sessions: Vec<Session> = (0..4).map(|_| coordinator.on_new_user()).collect();
+        let players: Vec<PlayerID> = sessions.iter().map(Session::get_player).collect();
+        coordinator.get_mut_game(0)?.create_users_hand();
+        coordinator
+            .get_mut_game(0)?
+            .get_mut_deck()
+            .append(&mut vec![
+                Card::new(CardSuit::Hearts, Value::Ten),
+                Card::new(CardSuit::Spades, Value::Ten),
+                Card::new(CardSuit::Clubs, Value::Ten),
+                Card::new(CardSuit::Diamonds, Value::Ten),
+                Card::new(CardSuit::Hearts, Value::Jack),
+                Card::new(CardSuit::Hearts, Value::Nine),
+                Card::new(CardSuit::Spades, Value::Seven),
+                Card::new(CardSuit::Clubs, Value::Nine),
+                Card::new(CardSuit::Diamonds, Value::Ace),
+                Card::new(CardSuit::Hearts, Value::Eight),
+            ]);
+
+        for player in &players {
+            coordinator.deposit(*player, 100.0)?;
+        }
+        assert!(matches!(
+            coordinator.buy_in(&sessions[0], 150.0),
+            Err(CoordinatorError::LedgerError(
+                LedgerError::InsufficientFunds
+            ))
+        ));
+        for session in &sessions {
+            coordinator.buy_in(session, 50.0)?;
+        }
+        for session in &sessions {
+            coordinator.handle_action(session, GameAction::StartingBet(20.0))?;
+        }
+        assert_eq!(50.0, coordinator.get_wallet_balance(players[0]));
+        assert_eq!(80.0, coordinator.get_ledger().balance(Account::House));
+
+        // Player 4 has a natural blackjack, player 2 loses to the dealer's 18
+        for session in &sessions[0..3] {
+            coordinator.handle_action(session, GameAction::Stand)?;
+        }
+        let game = coordinator.get_game(0)?;
+        for (player, money) in players.iter().zip(vec![70.0, 30.0, 70.0, 80.0]) {
+            assert_eq!(money, game.get_player_money(*player)?);
+            assert_eq!(
+                money,
+                coordinator.get_ledger().balance(Account::Table(*player))
+            );
+        }
+
+        // Leaving the table returns the stack to the wallet
+        coordinator.on_dropped_user(players[0]);
+        coordinator.tick_at(time::OffsetDateTime::now_utc() + std::time::Duration::from_secs(60));
+        assert_eq!(120.0, coordinator.get_wallet_balance(players[0]));
+        assert_eq!(0.0, coordinator.get_ledger().total());
+        assert_eq!(
+            vec![
+                EntryKind::Deposit,
+                EntryKind::BuyIn,
+                EntryKind::Bet,
+                EntryKind::Payout,
+                EntryKind::CashOut
+            ],
+            coordinator
+                .get_ledger_entries(players[0])
+                .iter()
+                .map(|entry| entry.kind)
+                .collect::<Vec<EntryKind>>()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn split_bets_reach_the_ledger() -> Result<(), CoordinatorError> {
+        // The second hand's stake goes to the house when split and each hand is paid on its own
+        let mut coordinator = GameCoordinator::new();
+        let sessions: Vec<Session> = (0..4).map(|_| coordinator.on_new_user()).collect();
+        let players: Vec<PlayerID> = sessions.iter().map(Session::get_player).collect();
+        coordinator.get_mut_game(0)?.create_users_hand();
+        coordinator
+            .get_mut_game(0)?
+            .get_mut_deck()
+            .append(&mut vec![
+                Card::new(CardSuit::Hearts, Value::Eight),
+                Card::new(CardSuit::Hearts, Value::Ten),
+                Card::new(CardSuit::Spades, Value::Ten),
+                Card::new(CardSuit::Clubs, Value::Ten),
+                Card::new(CardSuit::Diamonds, Value::Ten),
+                Card::new(CardSuit::Diamonds, Value::Eight),
+                Card::new(CardSuit::Hearts, Value::Nine),
+                Card::new(CardSuit::Spades, Value::Nine),
+                Card::new(CardSuit::Clubs, Value::Nine),
+                Card::new(CardSuit::Clubs, Value::Seven),
+                Card::new(CardSuit::Spades, Value::Queen),
+                Card::new(CardSuit::Spades, Value::Two),
+            ]);
+        for (player, session) in players.iter().zip(&sessions) {
+            coordinator.deposit(*player, 100.0)?;
+            coordinator.buy_in(session, 50.0)?;
+        }
+        for session in &sessions {
+            coordinator.handle_action(session, GameAction::StartingBet(20.0))?;
+        }
+        coordinator.handle_action(&sessions[0], GameAction::Split)?;
+        assert_eq!(100.0, coordinator.get_ledger().balance(Account::House));
+
+        // The first hand's 18 beats the dealer's 17, the second hand's 10 does not
+        coordinator.handle_action(&sessions[0], GameAction::Stand)?;
+        for session in &sessions {
+            coordinator.handle_action(session, GameAction::Stand)?;
+        }
+        let game = coordinator.get_game(0)?;
+        for (player, money) in players.iter().zip(vec![50.0, 70.0, 70.0, 70.0]) {
+            assert_eq!(money, game.get_player_money(*player)?);
+            assert_eq!(
+                money,
+                coordinator.get_ledger().balance(Account::Table(*player))
+            );
+        }
+        assert_eq!(
+            vec![
+                EntryKind::Deposit,
+                EntryKind::BuyIn,
+                EntryKind::Bet,
+                EntryKind::Bet,
+                EntryKind::Payout
+            ],
+            coordinator
+                .get_ledger_entries(players[0])
+                .iter()
+                .map(|entry| entry.kind)
+                .collect::<Vec<EntryKind>>()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn buy_in_cash_out() -> Result<(), CoordinatorError> {
+        // Money only moves between the wallet and the table between rounds and within the table limits
+        let mut coordinator = GameCoordinator::new();
+        let sessions: Vec<Session> = (0..4).map(|_| coordinator.on_new_user()).collect();
+        let players: Vec<PlayerID> = sessions.iter().map(Session::get_player).collect();
+        coordinator.get_mut_game(0)?.set_buy_in_limits(BuyInLimits {
+            min: 10.0,
+            max: 200.0,
+        });
+        coordinator
+            .get_mut_game(0)?
+            .get_mut_deck()
+            .append(&mut vec![
+                Card::new(CardSuit::Hearts, Value::Ten),
+                Card::new(CardSuit::Spades, Value::Ten),
+                Card::new(CardSuit::Clubs, Value::Ten),
+                Card::new(CardSuit::Diamonds, Value::Ten),
+                Card::new(CardSuit::Hearts, Value::Jack),
+                Card::new(CardSuit::Hearts, Value::Seven),
+                Card::new(CardSuit::Spades, Value::Seven),
+                Card::new(CardSuit::Clubs, Value::Seven),
+                Card::new(CardSuit::Diamonds, Value::Seven),
+                Card::new(CardSuit::Hearts, Value::Eight),
+            ]);
+        for player in &players {
+            coordinator.deposit(*player, 500.0)?;
+        }
+
+        assert!(matches!(
+            coordinator.buy_in(&sessions[0], 5.0),
+            Err(CoordinatorError::OutsideBuyInLimits(_))
+        ));
+        assert!(matches!(
+            coordinator.buy_in(&sessions[0], 250.0),
+            Err(CoordinatorError::OutsideBuyInLimits(_))
+        ));
+        assert!(matches!(
+            coordinator.handle_action(&sessions[0], GameAction::AddMoney(100.0)),
+            Err(CoordinatorError::GameError(GameError::InvaildAction))
+        ));
+        for session in &sessions {
+            coordinator.buy_in(session, 100.0)?;
+        }
+        assert_eq!(400.0, coordinator.get_wallet_balance(players[0]));
+
+        // No buying in or cashing out once bets are down
+        coordinator.handle_action(&sessions[0], GameAction::StartingBet(20.0))?;
+        assert!(matches!(
+            coordinator.buy_in(&sessions[1], 50.0),
+            Err(CoordinatorError::RoundInProgress)
+        ));
+        assert!(matches!(
+            coordinator.cash_out(&sessions[0]),
+            Err(CoordinatorError::RoundInProgress)
+        ));
+        for session in &sessions[1..] {
+            coordinator.handle_action(session, GameAction::StartingBet(20.0))?;
+        }
+        for session in &sessions {
+            coordinator.handle_action(session, GameAction::Stand)?;
+        }
+
+        // Everyone lost to the dealer's 18, the rest of the stack returns to the wallet
+        assert_eq!(
+            vec![
+                ClientEvent::CashOut(players[0], 80.0),
+                ClientEvent::BalanceChanged(players[0], 0.0)
+            ],
+            coordinator
+                .cash_out(&sessions[0])?
+                .into_iter()
+                .map(|event| event.event)
+                .collect::<Vec<ClientEvent>>()
+        );
+        assert_eq!(480.0, coordinator.get_wallet_balance(players[0]));
+
+        // The first bet of the next round clears the previous hands
+        coordinator.handle_action(&sessions[1], GameAction::StartingBet(20.0))?;
+        let game = coordinator.get_game(0)?;
+        assert!(game.get_player_hand(players[1])?.is_empty());
+        assert!(game.get_dealer_hand().is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn matchmaking() -> Result<(), CoordinatorError> {
+        // Tables start once the minimum is waiting and the countdown expires, newcomers fill open seats
+        let mut coordinator = GameCoordinator::with_config(TableConfig {
+            min_seats: 2,
+            max_seats: 3,
+            start_countdown: std::time::Duration::from_secs(30),
+            ..TableConfig::default()
+        });
+        let player1 = coordinator.on_new_user().get_player();
+        assert_eq!(None, coordinator.get_countdown_started());
+        let player2 = coordinator.on_new_user().get_player();
+        let started = coordinator.get_countdown_started().unwrap();
+        assert_eq!(0, coordinator.get_current_games().len());
+
+        coordinator.tick_at(started + std::time::Duration::from_secs(10));
+        assert_eq!(0, coordinator.get_current_games().len());
+        coordinator.tick_at(started + std::time::Duration::from_secs(30));
+        assert_eq!(
+            vec![player1, player2],
+            *coordinator.get_game(0)?.get_player_list()
+        );
+
+        // Joins the table with an open seat
+        let player3 = coordinator.on_new_user().get_player();
+        assert!(coordinator
+            .get_game(0)?
+            .get_player_list()
+            .contains(&player3));
+        assert_eq!(0.0, coordinator.get_game(0)?.get_player_money(player3)?);
+
+        // The first table is full, a full waiting room starts a table without a countdown
+        coordinator.on_new_user();
+        coordinator.on_new_user();
+        assert_eq!(2, coordinator.get_available_players().len());
+        coordinator.on_new_user();
+        assert_eq!(2, coordinator.get_current_games().len());
+        assert!(coordinator.get_available_players().is_empty());
+
+        // A single player can play alone
+        let mut coordinator = GameCoordinator::with_config(TableConfig {
+            min_seats: 1,
+            ..TableConfig::default()
+        });
+        let player = coordinator.on_new_user().get_player();
+        assert_eq!(vec![player], *coordinator.get_game(0)?.get_player_list());
+
+        // The server's tables give a lone player a game once the countdown runs out
+        let mut coordinator = GameCoordinator::with_config(ServerConfig::default().table_config);
+        let player = coordinator.on_new_user().get_player();
+        let started = coordinator.get_countdown_started().unwrap();
+        assert_eq!(0, coordinator.get_current_games().len());
+        coordinator.tick_at(started + std::time::Duration::from_secs(10));
+        assert_eq!(vec![player], *coordinator.get_game(0)?.get_player_list());
+        Ok(())
+    }
+
+    #[test]
+    fn lobby_tables() -> Result<(), CoordinatorError> {
+        // Named tables with their own stakes are listed in the lobby and can be picked by players
+        let mut coordinator = GameCoordinator::new();
+        let high_roller = coordinator.open_table(TableConfig {
+            name: "High Roller".to_string(),
+            min_bet: 100.0,
+            max_bet: 5000.0,
+            ..TableConfig::default()
+        })?;
+        let beginner = coordinator.open_table(TableConfig {
+            name: "Beginner".to_string(),
+            min_bet: 5.0,
+            max_bet: 50.0,
+            min_seats: 1,
+            max_seats: 1,
+            ..TableConfig::default()
+        })?;
+        assert!(matches!(
+            coordinator.open_table(TableConfig {
+                name: "Beginner".to_string(),
+                ..TableConfig::default()
+            }),
+            Err(CoordinatorError::TableNameTaken)
+        ));
+
+        let player1 = coordinator.on_new_user();
+        assert_eq!(beginner, coordinator.join_by_stakes(&player1, 10.0)?);
+        assert_eq!(
+            Some(beginner),
+            coordinator.get_player_table(player1.get_player())
+        );
+        assert_eq!(Some(beginner), coordinator.find_table("Beginner"));
+        let lobby = coordinator.lobby();
+        assert_eq!(
+            vec![("High Roller", 0), ("Beginner", 1)],
+            lobby
+                .iter()
+                .map(|table| (table.name.as_str(), table.occupancy))
+                .collect::<Vec<(&str, usize)>>()
+        );
+        assert_eq!(
+            "Bets 5-50, blackjack pays 3:2, dealer stands on soft 17, double on 9-11, split pairs once",
+            lobby[1].rules
+        );
+
+        let player2 = coordinator.on_new_user();
+        assert!(matches!(
+            coordinator.join_table(&player2, beginner),
+            Err(CoordinatorError::TableFull)
+        ));
+        assert!(matches!(
+            coordinator.join_table(&player2, 42),
+            Err(CoordinatorError::TableNotFound)
+        ));
+        coordinator.join_table(&player2, high_roller)?;
+
+        // Bets must respect the table stakes
+        coordinator.deposit(player2.get_player(), 500.0)?;
+        coordinator.buy_in(&player2, 500.0)?;
+        assert!(coordinator
+            .handle_action(&player2, GameAction::StartingBet(50.0))
+            .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn table_garbage_collection() -> Result<(), CoordinatorError> {
+        // Abandoned and empty tables are closed after their grace period
+        let mut coordinator = GameCoordinator::with_config(TableConfig {
+            min_seats: 1,
+            max_seats: 2,
+            empty_grace: std::time::Duration::from_secs(60),
+            abandoned_after: std::time::Duration::from_secs(600),
+            ..TableConfig::default()
+        });
+        let start = time::OffsetDateTime::now_utc();
+        let session1 = coordinator.on_new_user();
+        let session2 = coordinator.on_new_user();
+        let (player1, player2) = (session1.get_player(), session2.get_player());
+        // The spectator sits at a table of their own that is never closed
+        coordinator.open_table(TableConfig {
+            min_seats: 1,
+            max_seats: 1,
+            persistent: true,
+            ..TableConfig::default()
+        })?;
+        let watching = coordinator.on_new_user();
+        let spectator = watching.get_player();
+        coordinator.spectate(&watching, 0)?;
+        for session in &[&session1, &session2] {
+            coordinator.deposit(session.get_player(), 100.0)?;
+            coordinator.buy_in(session, 100.0)?;
+        }
+        coordinator.handle_action(&session1, GameAction::StartingBet(20.0))?;
+        coordinator.get_other_events();
+
+        // Nobody acts for longer than the abandoned timeout, the open bet is refunded
+        coordinator.tick_at(start + std::time::Duration::from_secs(300));
+        assert!(coordinator.get_game(0).is_ok());
+        coordinator.tick_at(start + std::time::Duration::from_secs(700));
+        assert!(coordinator.get_game(0).is_err());
+        assert_eq!(None, coordinator.get_player_table(player1));
+        assert_eq!(100.0, coordinator.get_wallet_balance(player1));
+        assert_eq!(100.0, coordinator.get_wallet_balance(player2));
+        assert_eq!(0.0, coordinator.get_ledger().total());
+        let events = coordinator.get_other_events();
+        for player in &[player1, player2, spectator] {
+            assert_eq!(ClientEvent::TableClosed(0), events[player][0].event.event);
+        }
+        let archived = &coordinator.get_archived_games()[0];
+        assert_eq!(CloseReason::Abandoned, archived.reason);
+        assert_eq!(
+            vec![(player1, 20.0)],
+            archived
+                .last_settlement
+                .iter()
+                .map(|settlement| (settlement.player, settlement.payout))
+                .collect::<Vec<_>>()
+        );
+
+        // The last player leaving starts the empty grace period
+        let player3 = coordinator.on_new_user().get_player();
+        let table = coordinator.get_player_table(player3).unwrap();
+        let dropped = time::OffsetDateTime::now_utc();
+        coordinator.on_dropped_user_at(player3, dropped);
+        let left = dropped + std::time::Duration::from_secs(60);
+        coordinator.tick_at(left);
+        assert_eq!(None, coordinator.get_player_table(player3));
+        coordinator.tick_at(left + std::time::Duration::from_secs(30));
+        assert!(coordinator.get_game(table).is_ok());
+        coordinator.tick_at(left + std::time::Duration::from_secs(61));
+        assert!(coordinator.get_game(table).is_err());
+        assert_eq!(
+            CloseReason::Empty,
+            coordinator.get_archived_games()[1].reason
+        );
+
+        // Only the most recently closed tables are kept
+        coordinator.set_archive_limit(1);
+        assert_eq!(1, coordinator.get_archived_games().len());
+        assert_eq!(
+            CloseReason::Empty,
+            coordinator.get_archived_games()[0].reason
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn concurrent_tables_conserve_chips() -> Result<(), CoordinatorError> {
+        // Many threads play many tables at once while deposits arrive, no chips are created or lost
+        const TABLES: usize = 16;
+        const ROUNDS: usize = 40;
+        let coordinator = ConcurrentCoordinator::new();
+        let mut tables = Vec::new();
+        for _ in 0..TABLES {
+            let id = coordinator.open_table(TableConfig {
+                min_seats: 1,
+                max_seats: 2,
+                ..TableConfig::default()
+            })?;
+            coordinator.with_table(id, |game| {
+                let suits = [
+                    CardSuit::Spades,
+                    CardSuit::Hearts,
+                    CardSuit::Diamonds,
+                    CardSuit::Clubs,
+                ];
+                let values = [
+                    Value::Two,
+                    Value::Three,
+                    Value::Four,
+                    Value::Five,
+                    Value::Six,
+                    Value::Seven,
+                    Value::Eight,
+                    Value::Nine,
+                    Value::Ten,
+                    Value::Jack,
+                    Value::Queen,
+                    Value::King,
+                    Value::Ace,
+                ];
+                for i in 0..2000 {
+                    game.get_mut_deck()
+                        .push(Card::new(suits[i % 4], values[(i * 7) % 13]));
+                }
+            })?;
+            let players = vec![coordinator.on_new_user(), coordinator.on_new_user()];
+            for player in &players {
+                coordinator.deposit(player.get_player(), 1000.0)?;
+                coordinator.join_table(player, id)?;
+                coordinator.handle_action(player, GameAction::BuyIn(1000.0))?;
+            }
+            tables.push((id, players));
+        }
+
+        let mut threads = Vec::new();
+        for chunk in tables.chunks(2) {
+            let coordinator = coordinator.clone();
+            let chunk = chunk.to_vec();
+            threads.push(std::thread::spawn(move || {
+                for _ in 0..ROUNDS {
+                    for (_, players) in &chunk {
+                        let mut round_over = false;
+                        for player in players {
+                            if let Ok(events) =
+                                coordinator.handle_action(player, GameAction::StartingBet(10.0))
+                            {
+                                round_over |= events
+                                    .iter()
+                                    .any(|event| event.event == ClientEvent::RoundOver);
+                            }
+                        }
+                        for _ in 0..10 {
+                            if round_over {
+                                break;
+                            }
+                            for player in players {
+                                for action in &[GameAction::Hit, GameAction::Stand] {
+                                    if let Ok(events) = coordinator.handle_action(player, *action) {
+                                        round_over |= events
+                                            .iter()
+                                            .any(|event| event.event == ClientEvent::RoundOver);
+                                    }
+                                }
+                            }
+                        }
+                        assert!(round_over);
+                    }
+                }
+            }));
+        }
+        let depositor = {
+            let coordinator = coordinator.clone();
+            let players: Vec<PlayerID> = tables
+                .iter()
+                .flat_map(|(_, players)| players.iter().map(Session::get_player))
+                .collect();
+            std::thread::spawn(move || {
+                for i in 0..1000 {
+                    coordinator
+                        .deposit(players[i % players.len()], 1.0)
+                        .unwrap();
+                    coordinator.lobby();
+                }
+            })
+        };
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        depositor.join().unwrap();
+
+        for (id, _) in &tables {
+            coordinator.close_table(*id)?;
+        }
+        let ledger = coordinator.get_ledger();
+        let deposited = (TABLES * 2 * 1000 + 1000) as f32;
+        assert_eq!(
+            TABLES * 2 * ROUNDS,
+            ledger.entries_of_kind(EntryKind::Bet).len()
+        );
+        assert_eq!(-deposited, ledger.balance(Account::Cashier));
+        assert_eq!(0.0, ledger.total());
+        assert!(ledger.get_tables().values().all(|money| *money == 0.0));
+        assert_eq!(
+            deposited,
+            ledger.get_wallets().values().sum::<f32>() + ledger.balance(Account::House)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn concurrent_moves_keep_one_seat() -> Result<(), CoordinatorError> {
+        // A player moves tables between rounds only, and racing moves never seat them twice
+        let coordinator = ConcurrentCoordinator::new();
+        let tables: Vec<GameID> = (0..3)
+            .map(|_| coordinator.open_table(TableConfig::default()))
+            .collect::<Result<_, _>>()?;
+        let seated = |id: GameID, player: PlayerID| {
+            coordinator.with_table(id, move |game| game.get_player_list().contains(&player))
+        };
+
+        coordinator.with_table(tables[0], |game| {
+            game.get_mut_deck().append(&mut vec![
+                Card::new(CardSuit::Hearts, Value::Ten),
+                Card::new(CardSuit::Diamonds, Value::Six),
+                Card::new(CardSuit::Clubs, Value::Seven),
+                Card::new(CardSuit::Spades, Value::Seven),
+            ])
+        })?;
+        let betting = coordinator.on_new_user();
+        coordinator.join_table(&betting, tables[0])?;
+        coordinator.deposit(betting.get_player(), 100.0)?;
+        coordinator.handle_action(&betting, GameAction::BuyIn(100.0))?;
+        coordinator.handle_action(&betting, GameAction::StartingBet(20.0))?;
+        assert!(matches!(
+            coordinator.join_table(&betting, tables[1]),
+            Err(CoordinatorError::RoundInProgress)
+        ));
+        assert_eq!(
+            Some(tables[0]),
+            coordinator.get_player_table(betting.get_player())
+        );
+        assert!(seated(tables[0], betting.get_player())?);
+        assert!(!seated(tables[1], betting.get_player())?);
+
+        let moving = coordinator.on_new_user();
+        let threads: Vec<_> = (0..8)
+            .map(|thread| {
+                let coordinator = coordinator.clone();
+                let moving = moving.clone();
+                let tables = tables.clone();
+                std::thread::spawn(move || {
+                    for round in 0..20 {
+                        let id = tables[1 + (thread + round) % 2];
+                        coordinator.join_table(&moving, id).unwrap();
+                        if round % 5 == 4 {
+                            coordinator.leave_table(&moving).ok();
+                        }
+                    }
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        let player = moving.get_player();
+        let seats: Vec<GameID> = tables
+            .iter()
+            .copied()
+            .filter(|id| seated(*id, player).unwrap())
+            .collect();
+        assert!(seats.len() <= 1);
+        assert_eq!(seats.first().copied(), coordinator.get_player_table(player));
+        Ok(())
+    }
+
+    #[test]
+    fn concurrent_events_queue_in_table_order() -> Result<(), CoordinatorError> {
+        // Players acting at once at the same table reach everyone else in the table's order
+        let coordinator = ConcurrentCoordinator::new();
+        let id = coordinator.open_table(TableConfig::default())?;
+        let sessions: Vec<Session> = (0..3).map(|_| coordinator.on_new_user()).collect();
+        for session in &sessions {
+            coordinator.join_table(session, id)?;
+            coordinator.deposit(session.get_player(), 1000.0)?;
+        }
+        let threads: Vec<_> = sessions[..2]
+            .iter()
+            .map(|session| {
+                let coordinator = coordinator.clone();
+                let session = session.clone();
+                std::thread::spawn(move || {
+                    for _ in 0..50 {
+                        coordinator
+                            .handle_action(&session, GameAction::BuyIn(1.0))
+                            .unwrap();
+                    }
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        let seqs: Vec<u64> = coordinator.get_other_events()[&sessions[2].get_player()]
+            .iter()
+            .map(|event| event.event.seq)
+            .collect();
+        assert_eq!(200, seqs.len());
+        assert!(seqs.windows(2).all(|pair| pair[0] < pair[1]));
+        Ok(())
+    }
+
+    #[test]
+    fn terminal_game() {
+        assert_eq!(Ok(Command::Bet(50.0)), parse_command("bet 50\n"));
+        assert_eq!(Ok(Command::Double), parse_command(" double "));
+        assert_eq!(Ok(Command::Split), parse_command("split"));
+        assert!(parse_command("bet fifty").is_err());
+        assert!(parse_command("fold").is_err());
+
+        // The same script and seed always plays out the same way
+        let script = "hit\nbet 10\nbet 10\nsplit\nstand\nstand\nbet 20\nstand\nstand\n";
+        let play = || {
+            let mut output = Vec::new();
+            let mut terminal = TerminalGame::new(
+                script.as_bytes(),
+                &mut output,
+                TerminalOptions {
+                    seats: 2,
+                    seed: 7,
+                    starting_balance: 100.0,
+                },
+            );
+            terminal.run().unwrap();
+            let game = terminal.get_game().clone();
+            (String::from_utf8(output).unwrap(), game)
+        };
+        let (output, game) = play();
+        assert_eq!(output, play().0);
+        assert!(output.contains("Place a bet first"));
+        assert!(output.contains("You can only split a first two card pair"));
+        assert!(output.contains("Dealer: "));
+        assert!(output.ends_with("Goodbye\n"));
+
+        // The first round is settled and the second is waiting on the second seat's bet
+        assert_eq!(2, output.matches(", bet 10 paid ").count());
+        let players = game.get_player_list();
+        assert_eq!(Ok(20.0), game.get_player_bet(players[0]));
+        assert!(game.get_player_bet(players[1]).is_err());
+
+        // A pair of tens is split, both hands are shown and settled on their own
+        let mut output = Vec::new();
+        let mut terminal = TerminalGame::new(
+            "bet 10\nsplit\nstand\nstand\n".as_bytes(),
+            &mut output,
+            TerminalOptions {
+                seats: 1,
+                seed: 12,
+                starting_balance: 100.0,
+            },
+        );
+        terminal.run().unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains("Seat 1: ♣Q ❤️A (21) | ❤️10 ❤️3 (13)"));
+        assert_eq!(2, output.matches("Seat 1 wins, bet 10 paid 20").count());
+    }
+
+    #[test]
+    fn event_queue_acknowledgements() -> Result<(), CoordinatorError> {
+        let dir = std::env::temp_dir().join(format!("blackjack-{}", PlayerID::new_v4()));
+        let mut persistent = PersistentCoordinator::open(FileStorage::open(&dir)?)?;
+        persistent.get_mut_coordinator().set_event_queue_limit(3);
+        persistent.checkpoint()?;
+        let sessions: Vec<Session> = (0..4)
+            .map(|_| persistent.on_new_user())
+            .collect::<Result<_, _>>()?;
+        let players: Vec<PlayerID> = sessions.iter().map(Session::get_player).collect();
+        for player in &players {
+            persistent.deposit(*player, 100.0)?;
+        }
+        let (first, watcher) = (players[0], players[1]);
+
+        // Everyone at the new table is told who sat down, the oldest beyond the limit are dropped
+        let joined = persistent.get_other_events()?;
+        assert_eq!(
+            vec![2, 3, 4],
+            joined[&watcher]
+                .iter()
+                .map(|event| event.seq)
+                .collect::<Vec<u64>>()
+        );
+        assert_eq!(
+            ClientEvent::PlayerJoined(persistent.get_coordinator().get_public_profile(players[3])),
+            joined[&watcher][2].event.event
+        );
+        for session in &sessions {
+            persistent.ack_events(session, 4)?;
+        }
+
+        // Every event meant for others is numbered per player and handed out once
+        persistent.handle_action(&sessions[0], GameAction::BuyIn(50.0))?;
+        let events = persistent.get_other_events()?;
+        assert_eq!(3, events.len());
+        assert_eq!(5, events[&watcher][0].seq);
+        assert_eq!(
+            ClientEvent::BuyIn(first, 50.0),
+            events[&watcher][0].event.event
+        );
+        assert!(persistent.get_other_events()?.is_empty());
+
+        // Handed out but unacknowledged events are replayed, acknowledged ones are not
+        persistent.handle_action(&sessions[2], GameAction::BuyIn(50.0))?;
+        persistent.get_other_events()?;
+        persistent.ack_events(&sessions[1], 6)?;
+        let coordinator = persistent.get_coordinator();
+        assert_eq!(
+            2,
+            coordinator.get_events_to_send()[&watcher]
+                .get_pending()
+                .len()
+        );
+        match coordinator.replay_events(&sessions[1], 6)? {
+            Replay::Events(events) => {
+                assert_eq!(vec![7, 8], events.iter().map(|e| e.seq).collect::<Vec<_>>())
+            }
+            replay => panic!("expected events, got {:?}", replay),
+        }
+
+        // Acknowledgements survive a restart
+        drop(persistent);
+        let mut persistent = PersistentCoordinator::open(FileStorage::open(&dir)?)?;
+        assert_eq!(
+            2,
+            persistent.get_coordinator().get_events_to_send()[&watcher]
+                .get_pending()
+                .len()
+        );
+
+        // Past the queue limit the oldest events are dropped and a snapshot is needed instead
+        persistent.handle_action(&sessions[3], GameAction::BuyIn(50.0))?;
+        persistent.handle_action(&sessions[3], GameAction::CashOut)?;
+        persistent.handle_action(&sessions[3], GameAction::BuyIn(20.0))?;
+        assert_eq!(
+            Replay::Snapshot(14),
+            persistent
+                .get_coordinator()
+                .replay_events(&sessions[1], 6)?
+        );
+        assert!(matches!(
+            persistent.get_coordinator().replay_events(&sessions[1], 11)?,
+            Replay::Events(events) if events.len() == 3
+        ));
+        std::fs::remove_dir_all(&dir).ok();
+        Ok(())
+    }
+
+    #[test]
+    fn reconnect_grace_period() -> Result<(), CoordinatorError> {
+        let mut coordinator = GameCoordinator::with_config(TableConfig {
+            min_seats: 2,
+            max_seats: 2,
+            reconnect_grace: std::time::Duration::from_secs(30),
+            ..TableConfig::default()
+        });
+        let first_session = coordinator.on_new_user();
+        let second_session = coordinator.on_new_user();
+        let (first, second) = (first_session.get_player(), second_session.get_player());
+        let token = first_session.get_token().clone();
+        for session in &[&first_session, &second_session] {
+            coordinator.deposit(session.get_player(), 100.0)?;
+            coordinator.buy_in(session, 100.0)?;
+        }
+        coordinator
+            .get_mut_game(0)?
+            .get_mut_deck()
+            .append(&mut vec![
+                Card::new(CardSuit::Hearts, Value::Ten),
+                Card::new(CardSuit::Spades, Value::Nine),
+                Card::new(CardSuit::Clubs, Value::Ten),
+                Card::new(CardSuit::Diamonds, Value::Ten),
+                Card::new(CardSuit::Hearts, Value::Nine),
+                Card::new(CardSuit::Hearts, Value::Seven),
+            ]);
+        coordinator.handle_action(&first_session, GameAction::StartingBet(10.0))?;
+        coordinator.handle_action(&second_session, GameAction::StartingBet(10.0))?;
+        coordinator.get_other_events();
+
+        // The first player drops on their turn, they keep their seat and stand automatically
+        let dropped = time::OffsetDateTime::now_utc();
+        coordinator.on_dropped_user_at(first, dropped);
+        assert!(coordinator.is_disconnected(first));
+        let game = coordinator.get_game(0)?;
+        assert_eq!(Some(second), game.get_current_player());
+        assert_eq!(Ok(10.0), game.get_player_bet(first));
+        assert_eq!(
+            vec![
+                ClientEvent::PlayerRoundOver(first),
+                ClientEvent::TurnChanged(second)
+            ],
+            coordinator.get_other_events()[&first]
+                .iter()
+                .map(|event| event.event.event.clone())
+                .collect::<Vec<ClientEvent>>()
+        );
+
+        // Within the grace period the token hands back the same player and seat
+        coordinator.tick_at(dropped + std::time::Duration::from_secs(29));
+        assert_eq!(first, coordinator.resume(&token)?.get_player());
+        assert!(!coordinator.is_disconnected(first));
+        assert_eq!(Some(0), coordinator.get_player_table(first));
+
+        // After the grace period the player is cashed out, winnings included
+        coordinator.handle_action(&second_session, GameAction::Stand)?;
+        coordinator.on_dropped_user_at(first, dropped);
+        coordinator.tick_at(dropped + std::time::Duration::from_secs(30));
+        assert_eq!(None, coordinator.get_player_table(first));
+        assert_eq!(110.0, coordinator.get_wallet_balance(first));
+        assert_eq!(0.0, coordinator.get_ledger().total());
+
+        // The token outlives the seat, it brings the player back to wait for a table until it
+        // expires
+        coordinator.resume(&token)?;
+        assert_eq!(Some(0), coordinator.get_player_table(first));
+        assert_eq!(110.0, coordinator.get_wallet_balance(first));
+        let expires_at = dropped + coordinator.get_auth().get_token_lifetime();
+        assert_eq!(
+            Err(AuthError::TokenExpired),
+            coordinator.get_auth().verify(&token, expires_at)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn expired_sessions_cannot_act() -> Result<(), CoordinatorError> {
+        // Every coordinator refuses a session once its token runs out, whatever it is used for,
+        // and the journal still replays what the player did before that
+        let start = time::OffsetDateTime::from_unix_timestamp(1_600_000_000);
+        let clock = ManualClock::new(start);
+        let config = TableConfig {
+            min_seats: 1,
+            ..TableConfig::default()
+        };
+        fn expired<T>(result: Result<T, CoordinatorError>) -> bool {
+            matches!(
+                result,
+                Err(CoordinatorError::AuthError(AuthError::TokenExpired))
+            )
+        }
+
+        let mut coordinator = GameCoordinator::new();
+        coordinator.set_clock(Arc::new(clock.clone()));
+        let lifetime = coordinator.get_auth().get_token_lifetime();
+        let session = coordinator.on_new_user();
+        let table = coordinator.open_table(config.clone())?;
+        coordinator.join_table(&session, table)?;
+        coordinator.deposit(session.get_player(), 100.0)?;
+        coordinator.handle_action(&session, GameAction::BuyIn(50.0))?;
+
+        let concurrent = ConcurrentCoordinator::with_clock(Arc::new(clock.clone()));
+        let concurrent_session = concurrent.on_new_user();
+        let concurrent_table = concurrent.open_table(config.clone())?;
+        concurrent.join_table(&concurrent_session, concurrent_table)?;
+        concurrent.deposit(concurrent_session.get_player(), 100.0)?;
+        concurrent.handle_action(&concurrent_session, GameAction::BuyIn(50.0))?;
+
+        let dir = std::env::temp_dir().join(format!("blackjack-{}", PlayerID::new_v4()));
+        let mut persistent = PersistentCoordinator::open(FileStorage::open(&dir)?)?;
+        persistent.set_clock(Arc::new(clock.clone()));
+        let persistent_session = persistent.on_new_user()?;
+        persistent.open_table(config)?;
+        let persistent_table = persistent
+            .get_coordinator()
+            .lobby()
+            .last()
+            .map(|table| table.id)
+            .unwrap();
+        persistent.join_table(&persistent_session, persistent_table)?;
+        persistent.deposit(persistent_session.get_player(), 100.0)?;
+        persistent.handle_action(&persistent_session, GameAction::BuyIn(50.0))?;
+
+        clock.advance(lifetime);
+        let bet = GameAction::StartingBet(10.0);
+        assert!(expired(coordinator.handle_action(&session, bet)));
+        assert!(expired(coordinator.join_table(&session, table)));
+        assert!(expired(coordinator.spectate(&session, table)));
+        assert!(expired(coordinator.register(
+            &session,
+            "late_larry",
+            "correct horse"
+        )));
+        assert!(expired(coordinator.ack_events(&session, 1)));
+        assert!(expired(coordinator.replay_events(&session, 0)));
+        assert!(expired(concurrent.handle_action(&concurrent_session, bet)));
+        assert!(expired(
+            concurrent.join_table(&concurrent_session, concurrent_table)
+        ));
+        assert!(expired(concurrent.leave_table(&concurrent_session)));
+        assert!(expired(persistent.handle_action(&persistent_session, bet)));
+        assert!(expired(
+            persistent.join_table(&persistent_session, persistent_table)
+        ));
+        assert!(expired(
+            persistent.spectate(&persistent_session, persistent_table)
+        ));
+        assert!(expired(persistent.register(
+            &persistent_session,
+            "late_larry",
+            "correct horse"
+        )));
+        assert_eq!(50.0, coordinator.get_wallet_balance(session.get_player()));
+        assert!(!coordinator.get_auth().is_username_taken("late_larry"));
+        assert_eq!(
+            50.0,
+            concurrent.get_wallet_balance(concurrent_session.get_player())
+        );
+        assert_eq!(
+            Some(concurrent_table),
+            concurrent.get_player_table(concurrent_session.get_player())
+        );
+
+        // Nothing refused reached the journal
+        drop(persistent);
+        let persistent = PersistentCoordinator::open(FileStorage::open(&dir)?)?;
+        let player = persistent_session.get_player();
+        let game = persistent.get_coordinator().get_game(persistent_table)?;
+        assert_eq!(Ok(50.0), game.get_player_money(player));
+        assert!(game.get_player_bet(player).is_err());
+        assert!(!persistent
+            .get_coordinator()
+            .get_auth()
+            .is_username_taken("late_larry"));
+        Ok(())
+    }
+
+    #[test]
+    fn accounts_survive_restart() -> Result<(), CoordinatorError> {
+        // Registered players log back in to the same id after a restart, only the hash is stored
+        let dir = std::env::temp_dir().join(format!("blackjack-{}", PlayerID::new_v4()));
+        let mut persistent = PersistentCoordinator::open(FileStorage::open(&dir)?)?;
+        persistent
+            .get_mut_coordinator()
+            .get_mut_auth()
+            .set_hash_rounds(1_000);
+        persistent.checkpoint()?;
+        let guest = persistent.on_new_user()?;
+        persistent.register(&guest, "Dealer_Dan", "correct horse")?;
+        assert!(matches!(
+            persistent.register(&guest, "dealer_dan", "another horse"),
+            Err(CoordinatorError::AuthError(AuthError::UsernameTaken))
+        ));
+        assert!(matches!(
+            persistent.register(&guest, "dan", "short"),
+            Err(CoordinatorError::AuthError(AuthError::WeakPassword))
+        ));
+        let journal = std::fs::read_to_string(dir.join("journal.log")).unwrap();
+        assert!(!journal.contains("correct horse"));
+
+        drop(persistent);
+        let mut persistent = PersistentCoordinator::open(FileStorage::open(&dir)?)?;
+        assert!(matches!(
+            persistent.login("dealer_dan", "wrong horse"),
+            Err(CoordinatorError::AuthError(AuthError::InvalidCredentials))
+        ));
+        let session = persistent.login("dealer_dan", "correct horse")?;
+        assert_eq!(guest.get_player(), session.get_player());
+
+        // Tokens signed before the restart still check out
+        assert_eq!(
+            guest.get_player(),
+            persistent.resume(guest.get_token())?.get_player()
+        );
+        std::fs::remove_dir_all(&dir).ok();
+        Ok(())
+    }
+
+    // Fails every append while fail is set, as a full disk would
+    struct FailingStorage {
+        inner: FileStorage,
+        fail: Arc<std::sync::atomic::AtomicBool>,
+    }
+
+    impl Storage for FailingStorage {
+        fn load_snapshot(&mut self) -> Result<Option<Snapshot>, StorageError> {
+            self.inner.load_snapshot()
+        }
+
+        fn save_snapshot(&mut self, snapshot: &Snapshot) -> Result<(), StorageError> {
+            self.inner.save_snapshot(snapshot)
+        }
+
+        fn append(&mut self, entry: &JournalEntry) -> Result<(), StorageError> {
+            if self.fail.load(std::sync::atomic::Ordering::SeqCst) {
+                return Err(StorageError::Io(std::io::Error::other("disk full")));
+            }
+            self.inner.append(entry)
+        }
+
+        fn read_journal(&mut self) -> Result<Vec<JournalEntry>, StorageError> {
+            self.inner.read_journal()
+        }
+
+        fn clear_journal(&mut self) -> Result<(), StorageError> {
+            self.inner.clear_journal()
+        }
+    }
+
+    #[test]
+    fn unwritten_mutations_are_not_applied() -> Result<(), CoordinatorError> {
+        // A mutation the journal could not take never happens, so memory matches the disk
+        let dir = std::env::temp_dir().join(format!("blackjack-{}", PlayerID::new_v4()));
+        let fail = Arc::new(std::sync::atomic::AtomicBool::new(false));
+        let storage = FailingStorage {
+            inner: FileStorage::open(&dir)?,
+            fail: fail.clone(),
+        };
+        let mut persistent = PersistentCoordinator::open(storage)?;
+        let sessions: Vec<Session> = (0..4)
+            .map(|_| persistent.on_new_user())
+            .collect::<Result<_, _>>()?;
+        let player = sessions[0].get_player();
+        persistent.deposit(player, 100.0)?;
+        persistent.handle_action(&sessions[0], GameAction::BuyIn(50.0))?;
+        let seq = persistent.get_seq();
+
+        fail.store(true, std::sync::atomic::Ordering::SeqCst);
+        assert!(matches!(
+            persistent.deposit(player, 100.0),
+            Err(CoordinatorError::StorageError(_))
+        ));
+        assert!(matches!(
+            persistent.handle_action(&sessions[0], GameAction::StartingBet(20.0)),
+            Err(CoordinatorError::StorageError(_))
+        ));
+        assert_eq!(seq, persistent.get_seq());
+        assert_eq!(
+            50.0,
+            persistent.get_coordinator().get_wallet_balance(player)
+        );
+        let game = persistent.get_coordinator().get_game(0)?;
+        assert_eq!(Ok(50.0), game.get_player_money(player));
+        assert!(game.get_player_bet(player).is_err());
+
+        // Once the disk recovers the same bet goes through as if nothing happened
+        fail.store(false, std::sync::atomic::Ordering::SeqCst);
+        persistent.handle_action(&sessions[0], GameAction::StartingBet(20.0))?;
+        let in_memory = serde_json::to_value(persistent.get_coordinator()).unwrap();
+        drop(persistent);
+        let persistent = PersistentCoordinator::open(FileStorage::open(&dir)?)?;
+        assert_eq!(
+            in_memory,
+            serde_json::to_value(persistent.get_coordinator()).unwrap()
+        );
+        std::fs::remove_dir_all(&dir).ok();
+        Ok(())
+    }
+
+    #[test]
+    fn refused_mutations_are_not_replayed() -> Result<(), CoordinatorError> {
+        // Observers and name checks are gone after a restart, so what they refused must never
+        // reach the journal
+        let dir = std::env::temp_dir().join(format!("blackjack-{}", PlayerID::new_v4()));
+        let mut persistent = PersistentCoordinator::open(FileStorage::open(&dir)?)?;
+        persistent
+            .get_mut_coordinator()
+            .add_observer(Arc::new(LossLimit::new(10.0)));
+        persistent
+            .get_mut_coordinator()
+            .get_mut_profiles()
+            .add_name_check(Arc::new(BlockedWords::new(vec!["dealer".to_string()])));
+        let mut sessions = Vec::new();
+        for _ in 0..4 {
+            sessions.push(persistent.on_new_user()?);
+        }
+        let session = sessions.remove(0);
+        let player = session.get_player();
+        persistent.deposit(player, 100.0)?;
+        persistent.handle_action(&session, GameAction::BuyIn(50.0))?;
+        let seq = persistent.get_seq();
+        assert!(matches!(
+            persistent.handle_action(&session, GameAction::StartingBet(20.0)),
+            Err(CoordinatorError::GameError(GameError::Vetoed(_)))
+        ));
+        let update = ProfileUpdate {
+            display_name: "Dealer Dan".to_string(),
+            avatar: "default".to_string(),
+            preferred_stakes: None,
+        };
+        assert!(matches!(
+            persistent.set_profile(&session, update),
+            Err(CoordinatorError::ProfileError(ProfileError::NameRejected))
+        ));
+        assert_eq!(seq, persistent.get_seq());
+
+        drop(persistent);
+        let persistent = PersistentCoordinator::open(FileStorage::open(&dir)?)?;
+        assert_eq!(seq, persistent.get_seq());
+        let game = persistent.get_coordinator().get_game(0)?;
+        assert_eq!(50.0, game.get_player_money(player)?);
+        assert_eq!(None, persistent.get_coordinator().get_profile(player));
+        std::fs::remove_dir_all(&dir).ok();
+        Ok(())
+    }
+
+    #[test]
+    fn bots_survive_restart() -> Result<(), CoordinatorError> {
+        // Bots are journaled with their moves, so a restart replays the same table without
+        // asking their strategies again, and they play on afterwards
+        let dir = std::env::temp_dir().join(format!("blackjack-{}", PlayerID::new_v4()));
+        let mut persistent = PersistentCoordinator::open(FileStorage::open(&dir)?)?;
+        let session = persistent.on_new_user()?;
+        let human = session.get_player();
+        persistent.open_table(TableConfig {
+            name: "Bots".to_string(),
+            min_bet: 10.0,
+            min_seats: 1,
+            max_seats: 3,
+            ..TableConfig::default()
+        })?;
+        let id = persistent
+            .get_coordinator()
+            .lobby()
+            .iter()
+            .find(|table| table.name == "Bots")
+            .unwrap()
+            .id;
+        persistent.join_table(&session, id)?;
+        persistent.deposit(human, 100.0)?;
+        persistent.handle_action(&session, GameAction::BuyIn(100.0))?;
+        persistent
+            .get_mut_coordinator()
+            .get_mut_game(id)?
+            .get_mut_deck()
+            .append(&mut vec![
+                Card::new(CardSuit::Hearts, Value::Ten),
+                Card::new(CardSuit::Spades, Value::Ten),
+                Card::new(CardSuit::Clubs, Value::Ten),
+                Card::new(CardSuit::Diamonds, Value::Nine),
+                Card::new(CardSuit::Hearts, Value::Eight),
+                Card::new(CardSuit::Spades, Value::Six),
+                Card::new(CardSuit::Clubs, Value::Six),
+                Card::new(CardSuit::Diamonds, Value::Six),
+                Card::new(CardSuit::Hearts, Value::King),
+                Card::new(CardSuit::Spades, Value::Ten),
+            ]);
+        persistent.checkpoint()?;
+        let mimic = persistent.seat_bot(id, BotStrategy::MimicTheDealer, 100.0)?;
+        let cautious = persistent.seat_bot(id, BotStrategy::NeverBust, 100.0)?;
+        persistent.handle_action(&session, GameAction::StartingBet(10.0))?;
+        persistent.handle_action(&session, GameAction::Stand)?;
+        let game = persistent.get_coordinator().get_game(id)?;
+        assert_eq!(
+            vec![(human, 20.0), (mimic, 0.0), (cautious, 20.0)],
+            game.get_last_settlement()
+                .iter()
+                .map(|settlement| (settlement.player, settlement.payout))
+                .collect::<Vec<_>>()
+        );
+        let before = serde_json::to_value(persistent.get_coordinator()).unwrap();
+
+        // The bots' bets and plays are in the journal as actions of their own
+        let journal = std::fs::read_to_string(dir.join("journal.log")).unwrap();
+        let actions = |bot: PlayerID| {
+            journal
+                .lines()
+                .filter(|line| line.contains("\"Action\"") && line.contains(&bot.to_string()))
+                .count()
+        };
+        assert_eq!((2, 2), (actions(mimic), actions(cautious)));
+
+        drop(persistent);
+        let mut persistent = PersistentCoordinator::open(FileStorage::open(&dir)?)?;
+        assert_eq!(
+            before,
+            serde_json::to_value(persistent.get_coordinator()).unwrap()
+        );
+        assert!(persistent.get_coordinator().is_bot(mimic));
+
+        // The restored bots bet along with the human again
+        persistent.get_other_events()?;
+        persistent.handle_action(&session, GameAction::StartingBet(10.0))?;
+        let pushed: Vec<ClientEvent> = persistent.get_other_events()?[&human]
+            .iter()
+            .map(|event| event.event.event.clone())
+            .collect();
+        assert!(pushed.contains(&ClientEvent::Betting(mimic, 10.0)));
+        assert!(pushed.contains(&ClientEvent::Betting(cautious, 10.0)));
+        std::fs::remove_dir_all(&dir).ok();
+        Ok(())
+    }
+
+    // Three seated players with 100 each and a deck where nobody has a natural and the dealer
+    // busts with 16 and a ten
+    fn departure_table(policy: DeparturePolicy) -> (GameState, Vec<PlayerID>) {
+        let players = vec![PlayerID::new_v4(), PlayerID::new_v4(), PlayerID::new_v4()];
+        let config = TableConfig {
+            departure_policy: policy,
+            ..TableConfig::default()
+        };
+        let mut game = GameState::with_config(0, players.clone(), config);
+        game.get_mut_deck().append(&mut vec![
+            Card::new(CardSuit::Hearts, Value::Ten),
+            Card::new(CardSuit::Spades, Value::Ten),
+            Card::new(CardSuit::Clubs, Value::Nine),
+            Card::new(CardSuit::Diamonds, Value::Ten),
+            Card::new(CardSuit::Hearts, Value::Seven),
+            Card::new(CardSuit::Spades, Value::Eight),
+            Card::new(CardSuit::Clubs, Value::Seven),
+            Card::new(CardSuit::Diamonds, Value::Six),
+            Card::new(CardSuit::Clubs, Value::Ten),
+        ]);
+        for player in &players {
+            game.action(GameAction::AddMoney(100.0), *player).unwrap();
+        }
+        (game, players)
+    }
+
+    #[test]
+    fn departure_mid_round() -> Result<(), GameError> {
+        // A bet placed before the deal is handed back whatever the policy, and once everyone
+        // left has bet the cards are dealt
+        let (mut game, players) = departure_table(DeparturePolicy::Forfeit);
+        game.action(GameAction::StartingBet(10.0), players[0])?;
+        game.action(GameAction::StartingBet(10.0), players[1])?;
+        let departure = game.remove_user(players[0])?;
+        assert_eq!(100.0, departure.money);
+        assert_eq!(Some(10.0), departure.settlement.map(|s| s.payout));
+        assert_eq!(vec![ClientEvent::PlayerLeft(players[0])], departure.events);
+        assert!(!game.is_dealt());
+        assert!(game
+            .remove_user(players[2])?
+            .events
+            .contains(&ClientEvent::RoundStarted));
+        assert!(game.is_dealt());
+        assert_eq!(Some(players[1]), game.get_current_player());
+
+        // The current player leaving forfeits their bet and passes the turn on
+        let (mut game, players) = departure_table(DeparturePolicy::Forfeit);
+        for player in &players {
+            game.action(GameAction::StartingBet(10.0), *player)?;
+        }
+        assert_eq!(Some(players[0]), game.get_current_player());
+        let departure = game.remove_user(players[0])?;
+        assert_eq!(90.0, departure.money);
+        assert_eq!(Some(0.0), departure.settlement.map(|s| s.payout));
+        assert_eq!(
+            vec![
+                ClientEvent::PlayerLeft(players[0]),
+                ClientEvent::TurnChanged(players[1])
+            ],
+            departure.events
+        );
+        assert_eq!(Some(players[1]), game.get_current_player());
+        assert_eq!(
+            Err(GameError::MissingPlayerID),
+            game.remove_user(players[0]).map(|_| ())
+        );
+
+        // Anyone else leaving is refunded under the refund policy and the turn stays put
+        let (mut game, players) = departure_table(DeparturePolicy::Refund);
+        for player in &players {
+            game.action(GameAction::StartingBet(10.0), *player)?;
+        }
+        assert_eq!(100.0, game.remove_user(players[2])?.money);
+        assert_eq!(Some(players[0]), game.get_current_player());
+        assert_eq!(2, game.get_player_list().len());
+
+        // The last player to act leaving has the dealer play out the round for the rest
+        let (mut game, players) = departure_table(DeparturePolicy::Forfeit);
+        for player in &players {
+            game.action(GameAction::StartingBet(10.0), *player)?;
+        }
+        game.action(GameAction::Stand, players[0])?;
+        game.action(GameAction::Stand, players[1])?;
+        let departure = game.remove_user(players[2])?;
+        assert_eq!(Some(&ClientEvent::RoundOver), departure.events.last());
+        assert!(game.is_between_rounds());
+        assert_eq!(None, game.get_current_player());
+        assert_eq!(26, game.sum_dealer());
+        assert_eq!(110.0, game.get_player_money(players[0])?);
+        assert_eq!(110.0, game.get_player_money(players[1])?);
+
+        // Between rounds there is nothing at stake
+        let departure = game.remove_user(players[0])?;
+        assert_eq!(110.0, departure.money);
+        assert_eq!(None, departure.settlement);
+        assert_eq!(vec![ClientEvent::PlayerLeft(players[0])], departure.events);
+        game.action(GameAction::StartingBet(10.0), players[1])?;
+        assert!(game.is_dealt());
+        Ok(())
+    }
+
+    #[test]
+    fn no_turns_once_the_round_is_settled() -> Result<(), GameError> {
+        // The last player to act cannot draw from the shoe or have the dealer play again
+        let (mut game, players) = departure_table(DeparturePolicy::Forfeit);
+        for player in &players {
+            game.action(GameAction::StartingBet(10.0), *player)?;
+        }
+        game.action(GameAction::Stand, players[0])?;
+        game.action(GameAction::Stand, players[1])?;
+        let events = game.action(GameAction::Stand, players[2])?;
+        assert_eq!(Some(&ClientEvent::RoundOver), events.last());
+        let deck = game.get_deck().len();
+        let dealer = game.get_dealer_hand().clone();
+        for action in &[GameAction::Hit, GameAction::Stand, GameAction::Double] {
+            assert_eq!(
+                Err(GameError::InvaildAction),
+                game.action(*action, players[2])
+            );
+        }
+        assert!(game
+            .legal_actions(players[2])
+            .contains(&GameAction::StartingBet(1.0)));
+        assert_eq!(deck, game.get_deck().len());
+        assert_eq!(&dealer, game.get_dealer_hand());
+        assert_eq!(Ok(110.0), game.get_player_money(players[2]));
+        Ok(())
+    }
+
+    #[test]
+    fn departure_keeps_ledger_in_step() -> Result<(), CoordinatorError> {
+        let mut coordinator = GameCoordinator::with_config(TableConfig {
+            min_seats: 2,
+            max_seats: 2,
+            ..TableConfig::default()
+        });
+        let first_session = coordinator.on_new_user();
+        let second_session = coordinator.on_new_user();
+        let (first, second) = (first_session.get_player(), second_session.get_player());
+        for session in &[&first_session, &second_session] {
+            coordinator.deposit(session.get_player(), 100.0)?;
+            coordinator.buy_in(session, 100.0)?;
+        }
+        coordinator
+            .get_mut_game(0)?
+            .get_mut_deck()
+            .append(&mut vec![
+                Card::new(CardSuit::Hearts, Value::Ten),
+                Card::new(CardSuit::Spades, Value::Ten),
+                Card::new(CardSuit::Clubs, Value::Ten),
+                Card::new(CardSuit::Diamonds, Value::Nine),
+                Card::new(CardSuit::Hearts, Value::Eight),
+                Card::new(CardSuit::Hearts, Value::Seven),
+            ]);
+        coordinator.handle_action(&first_session, GameAction::StartingBet(10.0))?;
+        coordinator.handle_action(&second_session, GameAction::StartingBet(10.0))?;
+
+        // The second player never comes back and forfeits their bet while the first plays on
+        let dropped = time::OffsetDateTime::now_utc();
+        coordinator.on_dropped_user_at(second, dropped);
+        coordinator.tick_at(dropped + std::time::Duration::from_secs(60));
+        assert_eq!(None, coordinator.get_player_table(second));
+        assert_eq!(90.0, coordinator.get_wallet_balance(second));
+        assert_eq!(Some(first), coordinator.get_game(0)?.get_current_player());
+        let client_event = coordinator.handle_action(&first_session, GameAction::Stand)?;
+        assert_eq!(
+            Some(&ClientEvent::RoundOver),
+            client_event.last().map(|event| &event.event)
+        );
+        assert_eq!(Ok(110.0), coordinator.get_game(0)?.get_player_money(first));
+        assert_eq!(0.0, coordinator.get_ledger().total());
+        Ok(())
+    }
+
+    #[test]
+    fn events_are_stamped_by_the_clock() -> Result<(), CoordinatorError> {
+        // Every event carries its table's seq and the coordinator's time, so a test clock
+        // decides when things happened and when the reconnect grace runs out
+        let start = time::OffsetDateTime::from_unix_timestamp(1_600_000_000);
+        let clock = ManualClock::new(start);
+        let dir = std::env::temp_dir().join(format!("blackjack-{}", PlayerID::new_v4()));
+        let mut persistent = PersistentCoordinator::open(FileStorage::open(&dir)?)?;
+        persistent.set_clock(Arc::new(clock.clone()));
+        let sessions: Vec<Session> = (0..4)
+            .map(|_| persistent.on_new_user())
+            .collect::<Result<_, _>>()?;
+        let players: Vec<PlayerID> = sessions.iter().map(Session::get_player).collect();
+        for player in &players {
+            persistent.deposit(*player, 100.0)?;
+        }
+
+        let bought_in = persistent.handle_action(&sessions[0], GameAction::BuyIn(50.0))?;
+        assert_eq!(
+            vec![(5, start), (6, start)],
+            bought_in
+                .iter()
+                .map(|event| (event.seq, event.at))
+                .collect::<Vec<_>>()
+        );
+        clock.advance(std::time::Duration::from_secs(5));
+        let later = start + std::time::Duration::from_secs(5);
+        let bought_in = persistent.handle_action(&sessions[1], GameAction::BuyIn(50.0))?;
+        assert_eq!(
+            vec![(7, later), (8, later)],
+            bought_in
+                .iter()
+                .map(|event| (event.seq, event.at))
+                .collect::<Vec<_>>()
+        );
+        let last_player_input = persistent.get_coordinator().get_last_player_input().clone();
+        assert_eq!(Some(&start), last_player_input.get(&players[0]));
+        assert_eq!(Some(&later), last_player_input.get(&players[1]));
+
+        // Replaying the journal on the system clock stamps everything as it happened
+        drop(persistent);
+        let mut persistent = PersistentCoordinator::open(FileStorage::open(&dir)?)?;
+        assert_eq!(
+            &last_player_input,
+            persistent.get_coordinator().get_last_player_input()
+        );
+        assert_eq!(8, persistent.get_coordinator().get_game(0)?.get_event_seq());
+
+        // The grace period is measured on the clock, not the wall
+        persistent.set_clock(Arc::new(clock.clone()));
+        persistent.on_dropped_user(players[1])?;
+        clock.advance(std::time::Duration::from_secs(59));
+        persistent.tick()?;
+        assert_eq!(
+            Some(0),
+            persistent.get_coordinator().get_player_table(players[1])
+        );
+        clock.advance(std::time::Duration::from_secs(1));
+        persistent.tick()?;
+        assert_eq!(
+            None,
+            persistent.get_coordinator().get_player_table(players[1])
+        );
+        assert_eq!(
+            100.0,
+            persistent.get_coordinator().get_wallet_balance(players[1])
+        );
+        std::fs::remove_dir_all(&dir).ok();
+        Ok(())
+    }
+
+    #[test]
+    fn observers_watch_every_table() -> Result<(), CoordinatorError> {
+        // Observers added to the coordinator reach open tables and every table opened later
+        let mut coordinator = GameCoordinator::with_config(TableConfig {
+            min_seats: 2,
+            max_seats: 2,
+            ..TableConfig::default()
+        });
+        let stats = TableStats::new();
+        coordinator.add_observer(Arc::new(stats.clone()));
+        let sessions: Vec<Session> = (0..2).map(|_| coordinator.on_new_user()).collect();
+        let log = EventLog::new();
+        let limit = LossLimit::new(10.0);
+        coordinator.add_observer(Arc::new(log.clone()));
+        coordinator.add_observer(Arc::new(limit.clone()));
+        for session in &sessions {
+            coordinator.deposit(session.get_player(), 100.0)?;
+            coordinator.buy_in(session, 50.0)?;
+        }
+        assert_eq!(2, stats.get_stats(0).actions);
+        assert_eq!(
+            format!("table 0: {} BuyIn(50.0)", sessions[0].get_player()),
+            log.get_lines()[0]
+        );
+
+        // A vetoed bet leaves the table and the ledger as they were
+        assert!(matches!(
+            coordinator.handle_action(&sessions[0], GameAction::StartingBet(20.0)),
+            Err(CoordinatorError::GameError(GameError::Vetoed(_)))
+        ));
+        assert_eq!(
+            Ok(50.0),
+            coordinator
+                .get_game(0)?
+                .get_player_money(sessions[0].get_player())
+        );
+        assert_eq!(
+            50.0,
+            coordinator.get_wallet_balance(sessions[0].get_player())
+        );
+        assert_eq!(2, stats.get_stats(0).actions);
+        coordinator.handle_action(&sessions[0], GameAction::StartingBet(10.0))?;
+
+        // Players joining a new table are announced to its observers too
+        let latecomers: Vec<Session> = (0..2).map(|_| coordinator.on_new_user()).collect();
+        assert!(log
+            .get_lines()
+            .iter()
+            .any(|line| line.starts_with("table 1: PlayerJoined")
+                && line.contains(&latecomers[1].get_player().to_string())));
+        Ok(())
+    }
+
+    // Deals a 19 against a dealer's soft 17 with a 3 to come, lets the player stand and returns
+    // the dealer's final hand and what the bet paid
+    fn play_against_soft_17(game: &mut GameState) -> Result<(usize, i8, ChipPile), GameError> {
+        let player = game.get_player_list()[0];
+        game.get_mut_deck().append(&mut vec![
+            Card::new(CardSuit::Hearts, Value::Ten),
+            Card::new(CardSuit::Spades, Value::Ace),
+            Card::new(CardSuit::Clubs, Value::Nine),
+            Card::new(CardSuit::Diamonds, Value::Six),
+            Card::new(CardSuit::Hearts, Value::Three),
+        ]);
+        game.action(GameAction::AddMoney(100.0), player)?;
+        game.action(GameAction::StartingBet(10.0), player)?;
+        game.action(GameAction::Stand, player)?;
+        Ok((
+            game.get_dealer_hand().len(),
+            game.sum_dealer(),
+            game.get_last_settlement()[0].payout,
+        ))
+    }
+
+    #[test]
+    fn dealer_rules() -> Result<(), GameError> {
+        let table = |dealer_rule| {
+            GameState::with_config(
+                0,
+                vec![PlayerID::new_v4()],
+                TableConfig {
+                    dealer_rule,
+                    ..TableConfig::default()
+                },
+            )
+        };
+
+        // Standing on soft 17 the dealer loses to 19, hitting it they draw to 20 and win
+        assert_eq!(
+            (2, 17, 20.0),
+            play_against_soft_17(&mut table(DealerRule::StandSoft17))?
+        );
+        assert_eq!(
+            (3, 20, 0.0),
+            play_against_soft_17(&mut table(DealerRule::HitSoft17))?
+        );
+        assert_eq!(
+            (2, 17, 20.0),
+            play_against_soft_17(&mut table(DealerRule::StandOn(16)))?
+        );
+        assert!(!TableConfig {
+            dealer_rule: DealerRule::StandOn(25),
+            ..TableConfig::default()
+        }
+        .is_valid());
+        assert_eq!(
+            "Bets 1-1000, blackjack pays 3:2, dealer hits soft 17, double on 9-11, split pairs once",
+            table(DealerRule::HitSoft17).get_config().rules_summary()
+        );
+
+        // A rule set on an open table takes over, is listed in its rules and saved with it
+        let mut game = table(DealerRule::StandSoft17);
+        assert_eq!(
+            Err(GameError::InvaildAction),
+            game.set_dealer_rule(DealerRule::StandOn(25))
+        );
+        game.set_dealer_rule(DealerRule::StandOn(20))?;
+        assert!(game
+            .get_config()
+            .rules_summary()
+            .contains("dealer stands on 20"));
+        let mut game: GameState =
+            serde_json::from_str(&serde_json::to_string(&game).unwrap()).unwrap();
+        assert_eq!((3, 20, 0.0), play_against_soft_17(&mut game)?);
+
+        // A policy of the table's own plays the dealer instead, it is code so a loaded table
+        // goes back to its rule
+        #[derive(Debug)]
+        struct HitsSoft18;
+        impl DealerPolicy for HitsSoft18 {
+            fn should_hit(&self, hand: &[Card]) -> bool {
+                let value = HandValue::of(hand);
+                value.total < 17 || value.soft && value.total < 19
+            }
+        }
+        let mut game = table(DealerRule::StandSoft17);
+        game.set_dealer_policy(Arc::new(HitsSoft18))?;
+        let mut loaded: GameState =
+            serde_json::from_str(&serde_json::to_string(&game).unwrap()).unwrap();
+        assert_eq!((3, 20, 0.0), play_against_soft_17(&mut game)?);
+        assert_eq!((2, 17, 20.0), play_against_soft_17(&mut loaded)?);
+
+        // Once bets are down the round is played under the rules they were placed on
+        let mut game = table(DealerRule::StandSoft17);
+        let player = game.get_player_list()[0];
+        game.get_mut_deck().append(&mut vec![
+            Card::new(CardSuit::Hearts, Value::Ten),
+            Card::new(CardSuit::Spades, Value::Ace),
+            Card::new(CardSuit::Clubs, Value::Nine),
+            Card::new(CardSuit::Diamonds, Value::Six),
+        ]);
+        game.action(GameAction::AddMoney(100.0), player)?;
+        game.action(GameAction::StartingBet(10.0), player)?;
+        assert_eq!(
+            Err(GameError::InvaildAction),
+            game.set_dealer_rule(DealerRule::HitSoft17)
+        );
+        assert_eq!(
+            Err(GameError::InvaildAction),
+            game.set_dealer_policy(Arc::new(HitsSoft18))
+        );
+        assert_eq!(DealerRule::StandSoft17, game.get_config().dealer_rule);
+        Ok(())
+    }
+
+    // Deals the player 6 and 5 against the dealer's ace with a 7 in the hole, a 10 comes next
+    fn eleven_against_an_ace(dealer_rule: DealerRule) -> Result<(GameState, PlayerID), GameError> {
+        let player = PlayerID::new_v4();
+        let mut game = GameState::with_config(
+            0,
+            vec![player],
+            TableConfig {
+                dealer_rule,
+                ..TableConfig::default()
+            },
+        );
+        game.get_mut_deck().append(&mut vec![
+            Card::new(CardSuit::Hearts, Value::Six),
+            Card::new(CardSuit::Diamonds, Value::Ace),
+            Card::new(CardSuit::Clubs, Value::Five),
+            Card::new(CardSuit::Spades, Value::Seven),
+            Card::new(CardSuit::Hearts, Value::Ten),
+        ]);
+        game.action(GameAction::AddMoney(100.0), player)?;
+        assert_eq!(Err(GameError::InvaildAction), game.hint(player));
+        game.action(GameAction::StartingBet(10.0), player)?;
+        Ok((game, player))
+    }
+
+    #[test]
+    fn hints_follow_basic_strategy() -> Result<(), GameError> {
+        // 11 against an ace is only doubled when the dealer hits soft 17
+        let (mut game, player) = eleven_against_an_ace(DealerRule::StandSoft17)?;
+        assert_eq!(Ok(Play::Hit), game.hint(player));
+        game.action(GameAction::Hit, player)?;
+        assert_eq!(Ok(Play::Stand), game.hint(player));
+        game.action(GameAction::Stand, player)?;
+        assert_eq!(Err(GameError::InvaildAction), game.hint(player));
+
+        let (game, player) = eleven_against_an_ace(DealerRule::HitSoft17)?;
+        assert_eq!(Ok(Play::Double), game.hint(player));
+        let view = game.get_player_view(player)?;
+        let legal = game.legal_actions(player);
+        assert_eq!(
+            GameAction::Double,
+            BasicStrategy.decide(&view, 90.0, &legal)
+        );
+        // Without the money to double the bot hits instead
+        assert_eq!(
+            GameAction::Hit,
+            BasicStrategy.decide(&view, 0.0, &[GameAction::Hit, GameAction::Stand])
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn hand_analysis_counts_unseen_cards() -> Result<(), GameError> {
+        let (mut game, player) = eleven_against_an_ace(DealerRule::StandSoft17)?;
+
+        // Only the 10 left in the shoe and the 7 in the hole are unseen, so the dealer has
+        // blackjack or a soft 18 and standing on 11 always loses
+        let analysis = game.get_hand_analysis(player)?;
+        assert_eq!(0.5, analysis.dealer.blackjack);
+        assert_eq!(0.5, analysis.dealer.get_total(18));
+        assert_eq!(-1.0, analysis.stand);
+        assert_eq!(None, analysis.surrender);
+        assert_eq!(None, analysis.split);
+        assert_ne!(Play::Surrender, analysis.best());
+        assert_eq!(Ok(analysis), game.get_hand_analysis(player));
+
+        game.action(GameAction::Stand, player)?;
+        assert_eq!(
+            Err(GameError::InvaildAction),
+            game.get_hand_analysis(player)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn training_costs_doubles_against_tens_and_aces() -> Result<(), GameError> {
+        // The chart hits 10 against a 10 and 11 against an ace when the dealer stands on soft
+        // 17. Doubling instead costs what the hint panel shows, the doubled bet lost to a
+        // dealer blackjack included.
+        let doubled = |first: Value, second: Value, up_card: Value| {
+            let player = PlayerID::new_v4();
+            let mut game = GameState::new(vec![player]);
+            game.get_mut_deck().append(&mut vec![
+                Card::new(CardSuit::Hearts, first),
+                Card::new(CardSuit::Diamonds, up_card),
+                Card::new(CardSuit::Clubs, second),
+                Card::new(CardSuit::Spades, Value::Seven),
+            ]);
+            game.get_mut_deck()
+                .append(&mut lib::card::Deck::new(6).into_cards());
+            game.action(GameAction::AddMoney(100.0), player)?;
+            game.set_training(player, true)?;
+            game.action(GameAction::StartingBet(10.0), player)?;
+            let analysis = game.get_hand_analysis(player)?;
+            let grade = match game.action(GameAction::Double, player)?[0] {
+                ClientEvent::DecisionGraded(grade) => grade,
+                ref event => panic!("expected a grade, got {:?}", event),
+            };
+            Ok::<_, GameError>((grade, analysis))
+        };
+
+        for (first, second, up_card) in [
+            (Value::Four, Value::Six, Value::Ten),
+            (Value::Five, Value::Six, Value::Ace),
+        ] {
+            let (grade, analysis) = doubled(first, second, up_card)?;
+            assert_eq!(
+                (Play::Double, Play::Hit, false),
+                (grade.taken, grade.advice, grade.correct)
+            );
+            let shown = (analysis.hit - analysis.double.unwrap()) * 10.0;
+            assert!(shown > 0.0);
+            assert!((grade.ev_cost as f64 - shown).abs() < 1e-4);
+        }
+
+        // A double the table refuses is not a decision, nothing is graded or costed
+        let player = PlayerID::new_v4();
+        let mut game = GameState::new(vec![player]);
+        game.get_mut_deck().append(&mut vec![
+            Card::new(CardSuit::Hearts, Value::Ten),
+            Card::new(CardSuit::Diamonds, Value::Six),
+            Card::new(CardSuit::Clubs, Value::Seven),
+            Card::new(CardSuit::Spades, Value::Seven),
+        ]);
+        game.action(GameAction::AddMoney(100.0), player)?;
+        game.set_training(player, true)?;
+        game.action(GameAction::StartingBet(10.0), player)?;
+        assert_eq!(Ok(Vec::new()), game.action(GameAction::Double, player));
+        assert_eq!(
+            0,
+            game.get_training_stats(player)
+                .unwrap()
+                .get_total()
+                .decisions
+        );
+        assert_eq!(Ok(10.0), game.get_player_bet(player));
+        Ok(())
+    }
+
+    #[test]
+    fn training_grades_decisions() -> Result<(), GameError> {
+        let (mut game, player) = eleven_against_an_ace(DealerRule::HitSoft17)?;
+        assert_eq!(
+            Err(GameError::MissingPlayerID),
+            game.set_training(PlayerID::new_v4(), true)
+        );
+        game.set_training(player, true)?;
+
+        // Hitting 11 when the chart doubles it is a mistake, costed by what the hint panel shows
+        let analysis = game.get_hand_analysis(player)?;
+        let events = game.action(GameAction::Hit, player)?;
+        let grade = match &events[0] {
+            ClientEvent::DecisionGraded(grade) => *grade,
+            event => panic!("expected a grade, got {:?}", event),
+        };
+        assert_eq!(
+            (player, Play::Hit, Play::Double, false),
+            (grade.player, grade.taken, grade.advice, grade.correct)
+        );
+        assert_eq!(
+            ((analysis.double.unwrap() - analysis.hit).max(0.0) * 10.0) as ChipPile,
+            grade.ev_cost
+        );
+        assert_eq!(
+            ClientEvent::CardRevealed(
+                FromPlayer::Player(player),
+                Card::new(CardSuit::Hearts, Value::Ten)
+            ),
+            events[1]
+        );
+
+        // Standing on 21 is right and the round plays out as usual
+        let events = game.action(GameAction::Stand, player)?;
+        assert!(matches!(
+            events[0],
+            ClientEvent::DecisionGraded(Grade { correct: true, .. })
+        ));
+        assert_eq!(Some(&ClientEvent::RoundOver), events.last());
+        let stats = *game.get_training_stats(player).unwrap();
+        assert_eq!((2, 1), (stats.hard.decisions, stats.hard.mistakes));
+        assert_eq!(0.5, stats.get(HandCategory::Hard).get_accuracy());
+        assert_eq!(grade.ev_cost, stats.get_total().ev_lost);
+
+        // Bets are not decisions, and stopping drops the stats
+        let events = game.action(GameAction::StartingBet(10.0), player)?;
+        assert!(!matches!(events[0], ClientEvent::DecisionGraded(_)));
+        game.set_training(player, false)?;
+        assert_eq!(None, game.get_training_stats(player));
+        Ok(())
+    }
+
+    #[test]
+    fn ties_hand_the_bet_back() -> Result<(), GameError> {
+        let player = PlayerID::new_v4();
+        let mut game = GameState::new(vec![player]);
+        game.get_mut_deck().append(&mut vec![
+            Card::new(CardSuit::Hearts, Value::Ten),
+            Card::new(CardSuit::Spades, Value::Ten),
+            Card::new(CardSuit::Clubs, Value::Eight),
+            Card::new(CardSuit::Diamonds, Value::Eight),
+            Card::new(CardSuit::Hearts, Value::Ace),
+            Card::new(CardSuit::Spades, Value::Ace),
+            Card::new(CardSuit::Clubs, Value::King),
+            Card::new(CardSuit::Diamonds, Value::King),
+            Card::new(CardSuit::Hearts, Value::Five),
+            Card::new(CardSuit::Spades, Value::Ace),
+            Card::new(CardSuit::Clubs, Value::Six),
+            Card::new(CardSuit::Diamonds, Value::Queen),
+            Card::new(CardSuit::Hearts, Value::Jack),
+        ]);
+        game.action(GameAction::AddMoney(100.0), player)?;
+
+        // 18 against 18, and then two naturals
+        game.action(GameAction::StartingBet(10.0), player)?;
+        game.action(GameAction::Stand, player)?;
+        assert_eq!(10.0, game.get_last_settlement()[0].payout);
+        let events = game.action(GameAction::StartingBet(10.0), player)?;
+        assert!(events.contains(&ClientEvent::PlayerBlackjack(player)));
+        assert_eq!(10.0, game.get_last_settlement()[0].payout);
+        assert_eq!(Ok(100.0), game.get_player_money(player));
+
+        // Three cards to 21 lose to the dealer's natural
+        game.action(GameAction::StartingBet(10.0), player)?;
+        game.action(GameAction::Hit, player)?;
+        assert_eq!(21, game.sum_hand(player)?);
+        game.action(GameAction::Stand, player)?;
+        assert_eq!(0.0, game.get_last_settlement()[0].payout);
+        assert_eq!(Ok(90.0), game.get_player_money(player));
+        Ok(())
+    }
+
+    #[test]
+    fn bots_fill_seats() -> Result<(), CoordinatorError> {
+        let mut coordinator = GameCoordinator::new();
+        let session = coordinator.on_new_user();
+        let human = session.get_player();
+        let id = coordinator.open_table(TableConfig {
+            name: "Bots".to_string(),
+            min_bet: 10.0,
+            min_seats: 1,
+            max_seats: 3,
+            ..TableConfig::default()
+        })?;
+        coordinator.join_table(&session, id)?;
+        coordinator.deposit(human, 100.0)?;
+        coordinator.buy_in(&session, 100.0)?;
+        coordinator
+            .get_mut_game(id)?
+            .get_mut_deck()
+            .append(&mut vec![
+                Card::new(CardSuit::Hearts, Value::Ten),
+                Card::new(CardSuit::Spades, Value::Ten),
+                Card::new(CardSuit::Clubs, Value::Ten),
+                Card::new(CardSuit::Diamonds, Value::Nine),
+                Card::new(CardSuit::Hearts, Value::Eight),
+                Card::new(CardSuit::Spades, Value::Six),
+                Card::new(CardSuit::Clubs, Value::Six),
+                Card::new(CardSuit::Diamonds, Value::Eight),
+                Card::new(CardSuit::Hearts, Value::King),
+            ]);
+
+        // Bots bet along with the human, whose bet is the last one missing before the deal
+        let mimic = coordinator.seat_bot(id, BotStrategy::MimicTheDealer, 100.0)?;
+        let cautious = coordinator.seat_bot(id, BotStrategy::NeverBust, 100.0)?;
+        assert!(coordinator.is_bot(mimic) && !coordinator.is_bot(human));
+        assert!(coordinator.get_game(id)?.is_between_rounds());
+        coordinator.handle_action(&session, GameAction::StartingBet(10.0))?;
+        assert_eq!(Ok(10.0), coordinator.get_game(id)?.get_player_bet(cautious));
+        assert_eq!(Some(human), coordinator.get_game(id)?.get_current_player());
+        coordinator.get_other_events();
+
+        // Once the human stands the bots play out their 16s, one hitting into a bust
+        let client_event = coordinator.handle_action(&session, GameAction::Stand)?;
+        assert_eq!(
+            vec![
+                ClientEvent::PlayerRoundOver(human),
+                ClientEvent::TurnChanged(mimic)
+            ],
+            client_event
+                .into_iter()
+                .map(|event| event.event)
+                .collect::<Vec<ClientEvent>>()
+        );
+        let pushed: Vec<ClientEvent> = coordinator.get_other_events()[&human]
+            .iter()
+            .map(|event| event.event.event.clone())
+            .collect();
+        assert!(pushed.contains(&ClientEvent::PlayerBusted(mimic)));
+        assert!(pushed.contains(&ClientEvent::PlayerRoundOver(cautious)));
+        assert_eq!(Some(&ClientEvent::RoundOver), pushed.last());
+        let game = coordinator.get_game(id)?;
+        assert_eq!(
+            vec![(human, 20.0), (mimic, 0.0), (cautious, 0.0)],
+            game.get_last_settlement()
+                .iter()
+                .map(|settlement| (settlement.player, settlement.payout))
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(0.0, coordinator.get_ledger().total());
+
+        // A removed bot frees its seat and the one left bets along with the human
+        coordinator.remove_bot(mimic)?;
+        assert_eq!(90.0, coordinator.get_wallet_balance(mimic));
+        assert_eq!(2, coordinator.get_game(id)?.get_player_list().len());
+        coordinator.handle_action(&session, GameAction::StartingBet(10.0))?;
+        assert!(coordinator.get_other_events()[&human]
+            .iter()
+            .any(|event| event.event.event == ClientEvent::Betting(cautious, 10.0)));
+        Ok(())
+    }
 }
@@ -0,0 +1,334 @@
+use lib::clock::ManualClock;
+use lib::eventqueue::SequencedEvent;
+use lib::gamestate::{ClientEvent, GameAction, TableEvent};
+use lib::profile::ProfileUpdate;
+use lib::protocol::{
+    ClientMessage, ClientRequest, Credentials, ErrorCode, ServerMessage, ServerPayload,
+};
+use lib::server::{Server, ServerConfig};
+use lib::tableconfig::TableConfig;
+use std::net::TcpStream;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+use time::OffsetDateTime;
+use tungstenite::stream::MaybeTlsStream;
+use tungstenite::{Message, WebSocket};
+
+type Client = WebSocket<MaybeTlsStream<TcpStream>>;
+
+fn receive(client: &mut Client) -> ServerMessage {
+    loop {
+        if let Message::Text(text) = client.read().unwrap() {
+            return ServerMessage::decode(&text).unwrap();
+        }
+    }
+}
+
+fn send_text(client: &mut Client, text: String) -> ServerMessage {
+    client.send(Message::Text(text)).unwrap();
+    receive(client)
+}
+
+fn send(client: &mut Client, seq: u64, request: ClientRequest) -> ServerMessage {
+    let reply = send_text(client, ClientMessage::new(seq, request).encode());
+    assert_eq!(Some(seq), reply.reply_to);
+    reply
+}
+
+#[test]
+fn websocket_server() {
+    let server = Server::bind(
+        "127.0.0.1:0",
+        ServerConfig {
+            table_config: TableConfig {
+                min_seats: 2,
+                max_seats: 2,
+                reconnect_grace: Duration::from_millis(300),
+                ..TableConfig::default()
+            },
+            starting_balance: 500.0,
+            tick_interval: Duration::from_millis(20),
+        },
+    )
+    .unwrap();
+    let url = format!("ws://{}", server.local_addr().unwrap());
+    let coordinator = server.get_coordinator();
+    // The server only moves on when the test moves its clock
+    let start = OffsetDateTime::from_unix_timestamp(1_600_000_000);
+    let clock = ManualClock::new(start);
+    coordinator
+        .lock()
+        .unwrap()
+        .set_clock(Arc::new(clock.clone()));
+    thread::spawn(move || server.run());
+    // Everything here happens at the start, and each table numbers its events from 1 in the
+    // same order they are pushed
+    let stamped = |events: Vec<ClientEvent>, first: u64| -> Vec<TableEvent> {
+        events
+            .into_iter()
+            .zip(first..)
+            .map(|(event, seq)| TableEvent {
+                seq,
+                at: start,
+                event,
+            })
+            .collect()
+    };
+    let sequenced = |events: &[TableEvent]| -> Vec<SequencedEvent> {
+        events
+            .iter()
+            .map(|event| SequencedEvent {
+                seq: event.seq,
+                event: event.clone(),
+            })
+            .collect()
+    };
+
+    // Each connection becomes a player and learns its id
+    let (mut alice, _) = tungstenite::connect(&url).unwrap();
+    let (mut bob, _) = tungstenite::connect(&url).unwrap();
+    let alice_id = match receive(&mut alice).body {
+        ServerPayload::Welcome(id) => id,
+        body => panic!("expected a welcome, got {:?}", body),
+    };
+    let alice_token = match receive(&mut alice).body {
+        ServerPayload::Session(token) => token,
+        body => panic!("expected a session, got {:?}", body),
+    };
+    let bob_id = match receive(&mut bob).body {
+        ServerPayload::Welcome(id) => id,
+        body => panic!("expected a welcome, got {:?}", body),
+    };
+    assert!(matches!(receive(&mut bob).body, ServerPayload::Session(_)));
+    {
+        let coordinator = coordinator.lock().unwrap();
+        assert_eq!(500.0, coordinator.get_wallet_balance(alice_id));
+        assert_eq!(
+            coordinator.get_player_table(alice_id),
+            coordinator.get_player_table(bob_id)
+        );
+    }
+
+    // Both are told who sat down at the table they were matched to, in seat order
+    let seats = coordinator
+        .lock()
+        .unwrap()
+        .get_player_game(alice_id)
+        .unwrap()
+        .get_player_list()
+        .clone();
+    let joined = ServerPayload::Pushed(sequenced(&stamped(
+        seats
+            .iter()
+            .map(|player| {
+                ClientEvent::PlayerJoined(coordinator.lock().unwrap().get_public_profile(*player))
+            })
+            .collect(),
+        1,
+    )));
+    assert_eq!(joined, receive(&mut alice).body);
+    assert_eq!(joined, receive(&mut bob).body);
+    assert_eq!(
+        ServerPayload::Pong,
+        send(&mut alice, 1, ClientRequest::Ping).body
+    );
+    match send(
+        &mut alice,
+        2,
+        ClientRequest::SetProfile(ProfileUpdate {
+            display_name: "Alice".to_string(),
+            avatar: "heart".to_string(),
+            preferred_stakes: Some(10.0),
+        }),
+    )
+    .body
+    {
+        ServerPayload::Profile(profile) => assert_eq!("Alice", profile.display_name),
+        body => panic!("expected a profile, got {:?}", body),
+    }
+
+    // The caller gets its own events back and the rest of the table has them pushed
+    let bought_in = stamped(
+        vec![
+            ClientEvent::BuyIn(alice_id, 100.0),
+            ClientEvent::BalanceChanged(alice_id, 100.0),
+        ],
+        3,
+    );
+    assert_eq!(
+        ServerPayload::Events(bought_in.clone()),
+        send(
+            &mut alice,
+            3,
+            ClientRequest::Action(GameAction::BuyIn(100.0))
+        )
+        .body
+    );
+    let pushed = receive(&mut bob);
+    assert_eq!(None, pushed.reply_to);
+    assert_eq!(ServerPayload::Pushed(sequenced(&bought_in)), pushed.body);
+    let bet = stamped(
+        vec![
+            ClientEvent::Betting(alice_id, 10.0),
+            ClientEvent::BalanceChanged(alice_id, 90.0),
+        ],
+        5,
+    );
+    assert_eq!(
+        ServerPayload::Events(bet.clone()),
+        send(
+            &mut alice,
+            4,
+            ClientRequest::Action(GameAction::StartingBet(10.0))
+        )
+        .body
+    );
+    let betting = sequenced(&bet);
+    assert_eq!(
+        ServerPayload::Pushed(betting.clone()),
+        receive(&mut bob).body
+    );
+
+    // Pushed events stay queued until acknowledged and can be resumed from the last one seen
+    bob.send(Message::Text(
+        ClientMessage::new(5, ClientRequest::Ack(4)).encode(),
+    ))
+    .unwrap();
+    assert_eq!(
+        ServerPayload::Pushed(betting),
+        send(&mut bob, 6, ClientRequest::Resume(4)).body
+    );
+
+    // Chat is relayed to the table and a resync describes the whole table
+    let chat = ServerPayload::Chat(bob_id, "hi".to_string());
+    assert_eq!(
+        chat,
+        send(&mut bob, 7, ClientRequest::Chat("hi".to_string())).body
+    );
+    assert_eq!(chat, receive(&mut alice).body);
+    match send(&mut bob, 8, ClientRequest::Resync).body {
+        ServerPayload::StateSnapshot(snapshot) => {
+            assert_eq!(6, snapshot.last_event_seq);
+            assert_eq!(2, snapshot.seats.len());
+            let seat = snapshot
+                .seats
+                .iter()
+                .find(|seat| seat.player == alice_id)
+                .unwrap();
+            assert_eq!(
+                Some("Alice".to_string()),
+                seat.profile
+                    .as_ref()
+                    .map(|profile| profile.display_name.clone())
+            );
+            assert_eq!(Some(10.0), seat.bet);
+            assert_eq!(90.0, seat.money);
+        }
+        body => panic!("expected a snapshot, got {:?}", body),
+    }
+
+    // Bad input and rejected actions are answered with an error instead of dropping the connection
+    match send_text(&mut bob, "not a message".to_string()).body {
+        ServerPayload::Error(error) => assert_eq!(ErrorCode::Malformed, error.code),
+        body => panic!("expected an error, got {:?}", body),
+    }
+    match send(&mut bob, 9, ClientRequest::Action(GameAction::Hit)).body {
+        ServerPayload::Error(error) => assert_eq!(ErrorCode::Rejected, error.code),
+        body => panic!("expected an error, got {:?}", body),
+    }
+    match send(&mut bob, 10, ClientRequest::JoinTable(42)).body {
+        ServerPayload::Error(error) => assert_eq!(ErrorCode::TableNotFound, error.code),
+        body => panic!("expected an error, got {:?}", body),
+    }
+
+    // A dropped player keeps their seat and stake and can resume their session
+    alice.close(None).unwrap();
+    while alice.read().is_ok() {}
+    let wait_for = |done: &dyn Fn() -> bool| {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !done() {
+            assert!(Instant::now() < deadline, "timed out");
+            thread::sleep(Duration::from_millis(5));
+        }
+    };
+    wait_for(&|| coordinator.lock().unwrap().is_disconnected(alice_id));
+    let (mut alice, _) = tungstenite::connect(format!("{}/?session={}", url, alice_token)).unwrap();
+    assert_eq!(ServerPayload::Welcome(alice_id), receive(&mut alice).body);
+    assert_eq!(
+        ServerPayload::Session(alice_token.clone()),
+        receive(&mut alice).body
+    );
+    {
+        let coordinator = coordinator.lock().unwrap();
+        assert!(!coordinator.is_disconnected(alice_id));
+        assert_eq!(
+            Ok(10.0),
+            coordinator
+                .get_player_game(alice_id)
+                .unwrap()
+                .get_player_bet(alice_id)
+        );
+    }
+
+    // Once the reconnect grace runs out the player is removed from their table
+    alice.close(None).unwrap();
+    while alice.read().is_ok() {}
+    wait_for(&|| coordinator.lock().unwrap().is_disconnected(alice_id));
+    clock.advance(Duration::from_millis(300));
+    wait_for(&|| {
+        coordinator
+            .lock()
+            .unwrap()
+            .get_player_table(alice_id)
+            .is_none()
+    });
+    assert!(coordinator
+        .lock()
+        .unwrap()
+        .get_player_table(bob_id)
+        .is_some());
+
+    // The token still names the same player until it expires, who waits for a table again with
+    // their wallet intact, while a tampered token gets a new guest
+    let (mut alice, _) = tungstenite::connect(format!("{}/?session={}", url, alice_token)).unwrap();
+    assert_eq!(ServerPayload::Welcome(alice_id), receive(&mut alice).body);
+    assert_eq!(
+        500.0,
+        coordinator.lock().unwrap().get_wallet_balance(alice_id)
+    );
+    let tampered = alice_token.as_str().replace('.', "0.");
+    let (mut stranger, _) = tungstenite::connect(format!("{}/?session={}", url, tampered)).unwrap();
+    assert!(matches!(receive(&mut stranger).body, ServerPayload::Welcome(id) if id != alice_id));
+
+    // A guest can register and later log in as the same player from another connection
+    let credentials = Credentials {
+        username: "bob".to_string(),
+        password: "correct horse".to_string(),
+    };
+    assert_eq!(
+        ServerPayload::Registered("bob".to_string()),
+        send(&mut bob, 11, ClientRequest::Register(credentials.clone())).body
+    );
+    assert!(matches!(
+        receive(&mut stranger).body,
+        ServerPayload::Session(_)
+    ));
+    match send(
+        &mut stranger,
+        1,
+        ClientRequest::Login(Credentials {
+            password: "wrong horse".to_string(),
+            ..credentials.clone()
+        }),
+    )
+    .body
+    {
+        ServerPayload::Error(error) => assert_eq!(ErrorCode::InvalidCredentials, error.code),
+        body => panic!("expected an error, got {:?}", body),
+    }
+    match send(&mut stranger, 2, ClientRequest::Login(credentials)).body {
+        ServerPayload::LoggedIn(id, _) => assert_eq!(bob_id, id),
+        body => panic!("expected to be logged in, got {:?}", body),
+    }
+}
@@ -0,0 +1,33 @@
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+fn play(seed: &str, script: &[u8]) -> String {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_black_jack"))
+        .args(["--seed", seed])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(script).unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success());
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn terminal_game_reads_scripted_stdin() {
+    let script = b"bet 25\nstand\nbet 25\nhit\nstand\nquit\n";
+    let output = play("3", script);
+    assert_eq!(output, play("3", script));
+    assert!(output.starts_with("Bets 1-1000"));
+    assert!(output.contains("Seat 1 has 1000, place a bet"));
+    assert!(output.contains("Dealer: "));
+    assert!(output.contains(", bet 25 paid "));
+    assert!(output.ends_with("Goodbye\n"));
+
+    // The shoe for seed 12 deals a pair of tens, which the terminal splits into two hands
+    let output = play("12", b"bet 25\nsplit\nstand\nstand\nquit\n");
+    assert!(output.contains("Commands: bet <amount>, hit, stand, double, split, help, quit"));
+    assert!(output.contains(" | "));
+    assert_eq!(2, output.matches(", bet 25 paid ").count());
+}